@@ -1,33 +1,712 @@
-use std::{fs::File, io, sync::Mutex};
+use core::time::Duration;
+use std::{io, path::PathBuf, sync::Mutex};
 
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
+use waylrc_core::{
+    lang,
+    parser::TimeTag,
+    provider::{self, Provider},
+    state::{PlayerctldPolicy, PositionUnit, SwitchPolicy},
+};
+
+use crate::{
+    display::{self, Escape, Percentage},
+    generate,
+    hooks::Hooks,
+    init,
+    latency::AudioLatency,
+    log::RotatingFile,
+    out::Output,
+    providers, publish, storage,
+    transform::{Filter, Transforms},
+    translate::Service,
+};
+
+/// Largest audio latency, render advance or sync tolerance accepted, in milliseconds
+const MAX_OFFSET: u64 = 10_000;
+/// Largest length given in minutes accepted, a year
+const MAX_MINUTES: u64 = 365 * 24 * 60;
+/// Maximum wait below which the module is warned to wake up needlessly often, in milliseconds
+const LOW_MAX_WAIT: u64 = 500;
 
 /// Command line arguments
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
+#[allow(clippy::struct_excessive_bools, reason = "flags are independent")]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     /// Maximum number of millisecond to wait between lyric refreshes
-    #[clap(long, short, default_value_t = 1000)]
+    #[clap(long, short, default_value_t = 1000, env = "WAYLRC_MAX_WAIT")]
     pub max_wait: u64,
+    /// Number of milliseconds the metadata of a new song must stay unchanged before its lyrics
+    /// are looked up
+    #[clap(long, default_value_t = 300, env = "WAYLRC_DEBOUNCE")]
+    pub debounce: u64,
+    /// Identities or bus names of preferred players (e.g. `mpd` or `spotify`), most preferred
+    /// first
+    #[clap(long, value_delimiter = ',', env = "WAYLRC_PLAYER_PRIORITY")]
+    pub player_priority: Vec<String>,
+    /// Whether to follow `playerctld` or the players it mirrors, so that the same song is not
+    /// tracked twice
+    #[clap(long, value_enum, default_value_t = Playerctld::Ignore, env = "WAYLRC_PLAYERCTLD")]
+    pub playerctld: Playerctld,
+    /// Which player to follow when several are playing at the same time
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Switch::PreferWithLyrics,
+        env = "WAYLRC_SWITCH_POLICY"
+    )]
+    pub switch_policy: Switch,
+    /// Unit of the positions reported by a player, as `player=us` or `player=ms`, for players
+    /// that do not use microseconds as required. Detected automatically if not given.
+    #[clap(long, value_parser = parse_position_unit, value_delimiter = ',', env = "WAYLRC_POSITION_UNIT")]
+    pub position_unit: Vec<(String, PositionUnit)>,
+    /// Players whose reported position sometimes stops advancing while playing, such as mpv
+    /// with some versions of its MPRIS script. Once the same position is reported several times
+    /// in a row, the position is extrapolated from the playback instead.
+    #[clap(long, value_delimiter = ',', env = "WAYLRC_STALE_POSITION_PLAYERS")]
+    pub stale_position_players: Vec<String>,
+    /// Keep showing the (frozen) lyrics of a paused player instead of blanking the module
+    #[clap(long, env = "WAYLRC_SHOW_WHEN_PAUSED")]
+    pub show_when_paused: bool,
+    /// File or FIFO to write a second module to, showing the title, artists and position of the
+    /// current song
+    #[clap(long, env = "WAYLRC_METADATA_MODULE")]
+    pub metadata_module: Option<String>,
+    /// File to keep the current lyric lines in as plain text, one per line, for tools that can
+    /// only read files such as hyprlock labels. The file is replaced atomically on each change.
+    #[clap(long, value_name = "PATH", env = "WAYLRC_WRITE_FILE")]
+    pub write_file: Option<PathBuf>,
+    /// Shell command run when another song starts, with its title, artists and source of
+    /// lyrics in `WAYLRC_TITLE`, `WAYLRC_ARTIST` and `WAYLRC_SOURCE`
+    #[clap(long, env = "WAYLRC_ON_TRACK_CHANGE")]
+    pub on_track_change: Option<String>,
+    /// Shell command run when another line is shown, with the line in `WAYLRC_LINE` and the
+    /// variables of `--on-track-change`
+    #[clap(long, env = "WAYLRC_ON_LINE_CHANGE")]
+    pub on_line_change: Option<String>,
+    /// Shell command run when no lyrics are found for a song, with the variables of
+    /// `--on-track-change`
+    #[clap(long, env = "WAYLRC_ON_NO_LYRICS")]
+    pub on_no_lyrics: Option<String>,
+    /// Count the songs played and the lyrics shown for `waylrc stats`
+    #[clap(long, env = "WAYLRC_STATS")]
+    pub stats: bool,
+    /// Where to show the lyrics. Outputs other than Waybar show the lyrics without the format,
+    /// using `--version-separator` between versions.
+    #[clap(long, value_enum, default_value_t = Output::Waybar, env = "WAYLRC_OUTPUT")]
+    pub output: Output,
+    /// Address of obs-websocket for `--output obs`
+    #[cfg(feature = "obs")]
+    #[clap(long, default_value = "ws://localhost:4455", env = "WAYLRC_OBS_URL")]
+    pub obs_url: String,
+    /// Password of obs-websocket, if authentication is enabled
+    #[cfg(feature = "obs")]
+    #[clap(long, env = "WAYLRC_OBS_PASSWORD", hide_env_values = true)]
+    pub obs_password: Option<String>,
+    /// Text source of OBS to show the lines in, instead of sending them as live captions
+    #[cfg(feature = "obs")]
+    #[clap(long, env = "WAYLRC_OBS_SOURCE")]
+    pub obs_source: Option<String>,
+    /// Query all lyric providers at the same time instead of one after another
+    #[clap(long, env = "WAYLRC_PARALLEL_PROVIDERS")]
+    pub parallel_providers: bool,
+    /// Number of milliseconds preferred providers may take to finish after another provider
+    /// found lyrics, when querying them in parallel
+    #[clap(long, default_value_t = 200, env = "WAYLRC_PROVIDER_GRACE")]
+    pub provider_grace: u64,
+    /// Number of minutes above which a track is taken to be a podcast or an audiobook, whose
+    /// lyrics are not looked up; its artists and title are shown instead, with the `spoken-word`
+    /// class. Tracks with such a genre are always treated that way. Set to 0 to only use the
+    /// genre.
+    #[clap(long, default_value_t = 60, env = "WAYLRC_SPOKEN_WORD_LENGTH")]
+    pub spoken_word_length: u64,
+    /// Number of minutes after which the lyrics of a song are looked up again if none were
+    /// found, for songs played on repeat whose lyrics are published after their release. Set to
+    /// 0 to never look them up again.
+    #[clap(long, default_value_t = 0, env = "WAYLRC_REQUERY_INTERVAL")]
+    pub requery_interval: u64,
+    /// Number of milliseconds the position reported by the player may be off before the lyrics
+    /// are resynchronised. Larger differences are corrected over a second instead of at once.
+    /// Set to 0 to always follow the reported position.
+    #[clap(long, default_value_t = 0, env = "WAYLRC_SYNC_TOLERANCE")]
+    pub sync_tolerance: u64,
+    /// Always use the position reported by the player, asking for it again shortly before each
+    /// line instead of relying on the position extrapolated since the last poll. Best for players
+    /// reporting accurate positions, such as mpd or mpv. Disables `--sync-tolerance`.
+    #[clap(long, env = "WAYLRC_TRUST_PLAYER_POSITION")]
+    pub trust_player_position: bool,
+    /// Number of milliseconds before each line starts to show it, to make up for the time the bar
+    /// takes to redraw, e.g. 50
+    #[clap(long, default_value_t = 0, env = "WAYLRC_RENDER_ADVANCE")]
+    pub render_advance: u64,
+    /// Minimum number of milliseconds between two updates of the module, for bars that poll it
+    /// rather than redrawing on each line. Disabled if zero.
+    #[clap(long, default_value_t = 0, env = "WAYLRC_MIN_REDRAW_INTERVAL")]
+    pub min_redraw_interval: u64,
+    /// Number of seconds each line of plain text lyrics (without time tags) is shown. By default,
+    /// the lines are spread evenly over the length of the song.
+    #[clap(long, env = "WAYLRC_UNSYNCED_LINE_DURATION")]
+    pub unsynced_line_duration: Option<f64>,
+    /// Keep showing the previous line at timed lines without text, instead of clearing the
+    /// display between verses
+    #[clap(long, env = "WAYLRC_IGNORE_BLANK_LINES")]
+    pub ignore_blank_lines: bool,
+    /// Stretch lyrics written for a longer version of the song, such as an extended mix, to the
+    /// length of the track. Such lyrics are only used if no provider has better ones.
+    #[clap(long, env = "WAYLRC_RESCALE_MISMATCH")]
+    pub rescale_mismatch: bool,
+    /// Stretch or shrink lyrics whose `[length:]` tag differs from the length of the track by at
+    /// most this percentage, e.g. 10, as they were likely written for another master or a radio
+    /// edit. Files of lyric directories written for such a version are then used rather than
+    /// skipped. Disabled if zero.
+    #[clap(long, default_value_t = 0, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=50), env = "WAYLRC_RESCALE_VERSIONS")]
+    pub rescale_versions: u8,
+    /// Remove readings in parentheses after kanji, e.g. `漢字(かんじ)` is shown as `漢字`
+    #[clap(long, env = "WAYLRC_STRIP_READINGS")]
+    pub strip_readings: bool,
+    /// Show the reading instead of kanji annotated with one, e.g. `漢字(かんじ)` is shown as
+    /// `かんじ`
+    #[clap(long, env = "WAYLRC_KANA_ONLY")]
+    pub kana_only: bool,
+    /// Filters applied to each line, in order, after the options above
+    #[clap(long, value_enum, value_delimiter = ',', env = "WAYLRC_TRANSFORM")]
+    pub transform: Vec<Filter>,
+    /// Number of milliseconds between the player reporting a position and it being heard, e.g.
+    /// 300 for Bluetooth headphones. `auto` asks the sound server for the latency of the
+    /// default output at startup.
+    #[clap(long, default_value = "0", env = "WAYLRC_AUDIO_LATENCY")]
+    pub audio_latency: AudioLatency,
+    /// Number of milliseconds after a line starts and before the next one during which the
+    /// `line-new` and `line-ending` classes are set, to animate transitions with CSS. Set to 0 to
+    /// disable.
+    #[clap(long, default_value_t = 0, env = "WAYLRC_LINE_TRANSITION")]
+    pub line_transition: u64,
+    /// Number of seconds before the first line during which a countdown of dots (`● ● ●`) is
+    /// shown, with the `lead-in` class. Set to 0 to disable.
+    #[clap(long, default_value_t = 0, env = "WAYLRC_LEAD_IN")]
+    pub lead_in: u64,
+    /// Text shown in place of the lyrics of a track a provider knows to be instrumental, with
+    /// the `instrumental` class. Set to an empty string to show nothing.
+    #[clap(long, default_value = "♪", env = "WAYLRC_INSTRUMENTAL_TEXT")]
+    pub instrumental_text: String,
+    /// Text of the module. `{lines}` is replaced by the current lyrics, `{title}` and `{artists}`
+    /// by those of the song, `{line_index}` by the number of the current line, `{line_total}` by
+    /// the number of lines, and `{prev_line}` and `{next_line}` by the lines around the current
+    /// one, e.g. to show the upcoming line dimmed. `{loop_count}` becomes `(x3)` when the song
+    /// is played for the third time in a row, and nothing on its first play.
+    #[clap(long, default_value = "{lines}", env = "WAYLRC_FORMAT")]
+    pub format: String,
+    /// Treat the format as Pango markup (e.g. `<b>{lines}</b>`). Lyrics and metadata substituted
+    /// into it are still escaped. Set `"escape": false` in the Waybar module configuration.
+    #[clap(long, env = "WAYLRC_MARKUP")]
+    pub markup: bool,
+    /// Whether waylrc escapes `&`, `<` and `>` in the text of the modules (`html`), or leaves it
+    /// to Waybar (`none`) for modules with `"escape": true`
+    #[clap(long, value_enum, default_value_t = Escape::Html, env = "WAYLRC_ESCAPE_MODE")]
+    pub escape_mode: Escape,
+    /// What the `percentage` field of the module is based on, for progress bars
+    #[clap(long, value_enum, default_value_t = Percentage::None, env = "WAYLRC_PERCENTAGE")]
+    pub percentage: Percentage,
+    /// Put a JSON object describing the lyrics (source, track id, offset, line index and total
+    /// lines) in the `alt` field of the module, for widgets parsing the output
+    #[clap(long, env = "WAYLRC_JSON_EXTRAS")]
+    pub json_extras: bool,
+    /// Add the position, the times of the current and next lines and the drift from the position
+    /// reported by the player to the tooltip, to diagnose lyrics out of sync
+    #[clap(long, env = "WAYLRC_DEBUG_TIMING")]
+    pub debug_timing: bool,
+    /// Only show the version of lyrics with translations that is in this language (e.g. `zh`),
+    /// or the original if there is none, and prefer lyric files named with it (e.g.
+    /// `song.zh.lrc`). `auto` uses the language of the locale.
+    #[clap(long, visible_alias = "lyrics-lang", env = "WAYLRC_PREFERRED_LANG")]
+    pub preferred_lang: Option<String>,
+    /// Text put between the lines of different lyric tracks, such as the original and its
+    /// translation. Identical lines are only shown once.
+    #[clap(long, default_value = " ", env = "WAYLRC_VERSION_SEPARATOR")]
+    pub version_separator: String,
+    /// Lyric provider to use, as its name followed by its options, e.g.
+    /// `directory:path=/music/lyrics,timeout=500`, with `\,` for a comma within a value. Can be
+    /// given several times to build the chain in order of preference, replacing the default
+    /// `pinned`, `sidecar` and `embedded` chain.
+    /// Providers and the translator are checked at startup, and every few minutes while one
+    /// cannot work, setting the `provider-degraded` class until they all work.
+    #[clap(
+        long,
+        value_name = "NAME[:OPTIONS]",
+        env = "WAYLRC_PROVIDER",
+        value_delimiter = ';'
+    )]
+    pub provider: Vec<providers::Spec>,
+    /// Directory of lyric files to search, such as a `LyricsX` or `ESLyric` library. Can be given
+    /// several times, or as a colon-separated list.
+    #[clap(long, value_delimiter = ':', env = "WAYLRC_LYRICS_DIR")]
+    pub lyrics_dir: Vec<PathBuf>,
+    /// Send `SIGRTMIN+N` to Waybar when the track or the source of its lyrics changes, so that
+    /// modules configured with `"signal": N` are refreshed along with the lyrics
+    #[clap(long, value_parser = clap::value_parser!(i32).range(1..=30), env = "WAYLRC_WAYBAR_SIGNAL")]
+    pub waybar_signal: Option<i32>,
+    /// Process to send `--waybar-signal` to. Every process named `waybar` is signalled if unset.
+    #[clap(long, value_parser = clap::value_parser!(i32).range(1..), env = "WAYLRC_WAYBAR_PID")]
+    pub waybar_pid: Option<i32>,
+    /// Write internal counters in the Prometheus text format to this file every few seconds, e.g.
+    /// for the textfile collector of the node exporter
+    #[cfg(feature = "metrics")]
+    #[clap(long, env = "WAYLRC_METRICS_FILE")]
+    pub metrics_file: Option<PathBuf>,
+    /// Give up on reading lyric files after this many milliseconds, retrying later, so that a
+    /// stalled network file system does not freeze the module. Disabled if zero.
+    #[clap(long, default_value_t = 0, env = "WAYLRC_IO_TIMEOUT")]
+    pub io_timeout: u64,
+    /// Treat lyric files without any valid time tag as having no lyrics, instead of showing
+    /// their text as plain text lyrics
+    #[clap(long, env = "WAYLRC_STRICT_LRC")]
+    pub strict_lrc: bool,
+    /// Metadata entries not to show in the tooltip, by MPRIS key. `*` matches any text, e.g.
+    /// `xesam:*` or `*:artUrl`.
+    #[clap(long, value_delimiter = ',', env = "WAYLRC_SKIP_METADATA")]
+    pub skip_metadata: Vec<String>,
+    /// Only show these metadata entries in the tooltip, by MPRIS key, including entries not shown
+    /// by default (e.g. `xesam:genre`). `*` matches any text.
+    #[clap(long, value_delimiter = ',', env = "WAYLRC_ONLY_METADATA")]
+    pub only_metadata: Vec<String>,
+    /// Never send lyrics to the translation service, the only use of the network. Can be toggled
+    /// at runtime by sending `SIGUSR1`, and sets the `offline` class.
+    #[clap(long, env = "WAYLRC_OFFLINE")]
+    pub offline: bool,
+    /// Translate lyrics without a translation to this language (e.g. `en`) with an online
+    /// service. Lyrics are sent to the service once per song and the result is cached.
+    #[clap(long, env = "WAYLRC_TRANSLATE_TO")]
+    pub translate_to: Option<String>,
+    /// Service used to translate lyrics
+    #[clap(long, value_enum, default_value_t = Service::Libretranslate, env = "WAYLRC_TRANSLATION_SERVICE")]
+    pub translation_service: Service,
+    /// Endpoint of the translation service, if not its public instance
+    #[clap(long, env = "WAYLRC_TRANSLATION_URL")]
+    pub translation_url: Option<String>,
+    /// API key of the translation service
+    #[clap(long, env = "WAYLRC_TRANSLATION_API_KEY", hide_env_values = true)]
+    pub translation_api_key: Option<String>,
+    /// Minimum number of seconds between two requests to the translation service. Songs
+    /// changed to in the meantime are not translated.
+    #[clap(long, default_value_t = 10, env = "WAYLRC_TRANSLATION_INTERVAL")]
+    pub translation_interval: u64,
+    /// Configuration file holding options, one `name = value` per line. Defaults to
+    /// `waylrc/config` in the XDG configuration directory, if it exists.
+    #[clap(long, env = "WAYLRC_CONFIG")]
+    pub config: Option<PathBuf>,
     /// File to write the log to. If not specified, logs will be written to stderr.
-    #[clap(long, short)]
-    log_file: Option<String>,
+    #[clap(long, short, env = "WAYLRC_LOG_FILE")]
+    pub log_file: Option<String>,
+    /// Number of kibibytes above which the log file is moved to `<file>.1`, the previous ones to
+    /// `<file>.2` and so on. Set to 0 to let the log file grow.
+    #[clap(long, default_value_t = 10240, env = "WAYLRC_LOG_MAX_SIZE")]
+    pub log_max_size: u64,
+    /// Number of rotated log files kept
+    #[clap(long, default_value_t = 3, env = "WAYLRC_LOG_KEEP")]
+    pub log_keep: u32,
+    /// Log more: `-v` logs what waylrc does, such as the lyrics found, `-vv` why it does it and
+    /// `-vvv` everything. Only warnings and errors are logged by default.
+    #[clap(long, short, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// Most verbose level of the messages logged (error, warn, info, debug or trace), overriding
+    /// `--verbose`
+    #[clap(long, env = "WAYLRC_LOG_LEVEL")]
+    pub log_level: Option<tracing::Level>,
+}
+
+/// Parse a `player=unit` pair
+fn parse_position_unit(s: &str) -> Result<(String, PositionUnit), String> {
+    let (player, unit) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected player=unit, got {s}"))?;
+    let unit = match unit {
+        "us" | "µs" => PositionUnit::Microseconds,
+        "ms" => PositionUnit::Milliseconds,
+        _ => return Err(format!("unknown unit {unit}, expected us or ms")),
+    };
+    Ok((player.to_owned(), unit))
+}
+
+/// Parse a playback speed such as `2x` or `0.5`
+fn parse_speed(s: &str) -> Result<f64, String> {
+    let speed: f64 = s
+        .strip_suffix(['x', 'X'])
+        .unwrap_or(s)
+        .parse()
+        .map_err(|e| format!("invalid speed {s}: {e}"))?;
+    if speed.is_finite() && speed > 0.0 {
+        Ok(speed)
+    } else {
+        Err(format!("speed must be positive, got {s}"))
+    }
+}
+
+/// How to handle `playerctld`
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Playerctld {
+    /// Follow the players mirrored by `playerctld` directly
+    Ignore,
+    /// Only follow `playerctld` when it is running
+    Prefer,
+}
+
+impl From<Playerctld> for PlayerctldPolicy {
+    fn from(p: Playerctld) -> Self {
+        match p {
+            Playerctld::Ignore => Self::Ignore,
+            Playerctld::Prefer => Self::Prefer,
+        }
+    }
+}
+
+/// Which player to follow when several are playing
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Switch {
+    /// Prefer players in `--player-priority`, then players whose song has lyrics, and keep
+    /// following a player while it plays
+    PreferWithLyrics,
+    /// Prefer players in `--player-priority`, and keep following a player while it plays
+    PreferPriorityList,
+    /// Follow the player that started playing last
+    MostRecentStart,
+    /// Never switch away from the followed player, even while it is paused
+    ManualOnly,
+}
+
+impl From<Switch> for SwitchPolicy {
+    fn from(s: Switch) -> Self {
+        match s {
+            Switch::PreferWithLyrics => Self::PreferWithLyrics,
+            Switch::PreferPriorityList => Self::PreferPriorityList,
+            Switch::MostRecentStart => Self::MostRecentStart,
+            Switch::ManualOnly => Self::ManualOnly,
+        }
+    }
+}
+
+/// Subcommands other than running the Waybar module
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check that waylrc can find players and lyrics, and print what is wrong
+    Doctor,
+    /// Play back a lyric file in real time, printing the module as waylrc would show it
+    Preview {
+        /// The `.lrc` file to play back
+        file: PathBuf,
+        /// Playback speed, e.g. `2x`
+        #[clap(long, value_parser = parse_speed, default_value = "1")]
+        speed: f64,
+        /// Position to start at, as `mm:ss`
+        #[clap(long, default_value = "00:00")]
+        start: TimeTag,
+    },
+    /// Find the songs whose lyrics contain a phrase, in the lyric directories and pinned lyrics
+    SearchLyrics {
+        /// Text to look for, ignoring case
+        phrase: String,
+        /// Another directory of lyric files to search. Can be given several times.
+        #[clap(long)]
+        dir: Vec<PathBuf>,
+    },
+    /// Always show a lyric file for an audio file, even after it is renamed or, if `fpcalc` is
+    /// installed, retagged
+    Pin {
+        /// The audio file
+        audio: PathBuf,
+        /// The `.lrc` file to show for it
+        lyrics: PathBuf,
+    },
+    /// Manage the files cached on disk
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Publish a lyric file to LRCLIB, for songs it has no lyrics or only unsynchronised ones
+    /// for. The song is taken from the ID tags of the file unless given.
+    PublishLrc {
+        /// The `.lrc` file to publish
+        file: PathBuf,
+        #[command(flatten)]
+        song: publish::Song,
+        /// Print what would be published instead of publishing it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Write a commented configuration file, and print what to add to the configuration of the
+    /// bar
+    InitConfig {
+        /// Where to write the configuration, instead of `waylrc/config` in the XDG configuration
+        /// directory
+        path: Option<PathBuf>,
+        #[clap(long, value_enum, default_value_t = init::Bar::Waybar)]
+        bar: init::Bar,
+        /// Replace an existing configuration file
+        #[clap(long)]
+        force: bool,
+    },
+    /// Print shell completions or the manual page
+    Generate {
+        #[clap(value_enum)]
+        target: generate::Target,
+    },
+    /// Show the songs played the most, where their lyrics came from and how often each provider
+    /// had lyrics
+    Stats,
+    /// Move pinned lyrics to another machine, or share them
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+}
+
+/// Actions on the cache
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    /// Delete all cached files
+    Clear,
+}
+
+/// Actions on profiles
+#[derive(Subcommand, Debug)]
+pub enum ProfileAction {
+    /// Write the pinned lyrics as JSON
+    Export {
+        /// Where to write the profile, instead of the standard output
+        file: Option<PathBuf>,
+    },
+    /// Pin the lyrics of a profile, replacing those pinned to the same audio files
+    Import {
+        /// The profile written by `waylrc profile export`
+        file: PathBuf,
+    },
+}
+
+/// Directory of lyrics pinned to audio files
+#[must_use]
+pub fn pins() -> Option<PathBuf> {
+    Some(storage::state_dir()?.join("pins"))
 }
 
 impl Args {
+    /// Build the chain of lyric providers
+    #[must_use]
+    pub fn providers(&self) -> Vec<Box<dyn Provider>> {
+        let language = self.preferred_language();
+        let io_timeout = (self.io_timeout != 0).then(|| Duration::from_millis(self.io_timeout));
+        let specs = if self.provider.is_empty() {
+            providers::Spec::defaults()
+        } else {
+            self.provider.clone()
+        };
+        let directories = self.lyrics_dir.iter().map(|path| providers::Spec {
+            kind: providers::Kind::Directory { path: path.clone() },
+            timeout: None,
+        });
+        specs
+            .into_iter()
+            .chain(directories)
+            .filter_map(|spec| {
+                spec.build(language.as_deref(), io_timeout, self.version_tolerance())
+            })
+            .map(|provider| -> Box<dyn Provider> {
+                if self.strict_lrc {
+                    Box::new(provider::Strict::new(provider))
+                } else {
+                    provider
+                }
+            })
+            .collect()
+    }
+
+    /// How much the length lyrics were written for may differ from the length of the track, for
+    /// them to be rescaled, if `--rescale-versions` is set
+    #[must_use]
+    pub fn version_tolerance(&self) -> Option<f64> {
+        (self.rescale_versions != 0).then(|| f64::from(self.rescale_versions) / 100.0)
+    }
+
+    /// Get the language to show lyrics in, if one is preferred
+    #[must_use]
+    pub fn preferred_language(&self) -> Option<String> {
+        let preferred = self.preferred_lang.as_deref()?;
+        if !preferred.eq_ignore_ascii_case("auto") {
+            return Some(preferred.to_owned());
+        }
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|locale| !locale.is_empty())
+            .and_then(|locale| lang::language_of_locale(&locale).map(str::to_owned))
+    }
+
+    /// Check the values of the options that would otherwise fail at runtime, returning warnings
+    /// about the values that work but are likely mistakes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first invalid value.
+    pub fn validate(&self) -> Result<Vec<String>, clap::Error> {
+        let invalid = |message: String| Self::command().error(ErrorKind::ValueValidation, message);
+        let mut warnings = Vec::new();
+        if self.max_wait == 0 {
+            return Err(invalid(
+                "--max-wait must be at least 1 millisecond".to_owned(),
+            ));
+        }
+        if self.max_wait < LOW_MAX_WAIT {
+            warnings.push(format!(
+                "--max-wait of {} ms wakes waylrc more than twice a second, lines are shown on time without it",
+                self.max_wait
+            ));
+        }
+        if let Some(secs) = self.unsynced_line_duration {
+            if !secs.is_finite() || secs < 0.0 {
+                return Err(invalid(format!(
+                    "--unsynced-line-duration must be a positive number of seconds, not {secs}"
+                )));
+            }
+        }
+        if let AudioLatency::Fixed(ms) = self.audio_latency {
+            if ms > MAX_OFFSET {
+                return Err(invalid(format!(
+                    "--audio-latency must be at most {MAX_OFFSET} milliseconds, not {ms}"
+                )));
+            }
+        }
+        if self.render_advance > MAX_OFFSET {
+            return Err(invalid(format!(
+                "--render-advance must be at most {MAX_OFFSET} milliseconds, not {}",
+                self.render_advance
+            )));
+        }
+        if self.min_redraw_interval > self.max_wait {
+            warnings.push(format!(
+                "--min-redraw-interval of {} ms is longer than --max-wait, which it overrides",
+                self.min_redraw_interval
+            ));
+        }
+        if self.sync_tolerance > MAX_OFFSET {
+            return Err(invalid(format!(
+                "--sync-tolerance must be at most {MAX_OFFSET} milliseconds, not {}",
+                self.sync_tolerance
+            )));
+        }
+        for (name, minutes) in [
+            ("--spoken-word-length", self.spoken_word_length),
+            ("--requery-interval", self.requery_interval),
+        ] {
+            if minutes > MAX_MINUTES {
+                return Err(invalid(format!(
+                    "{name} must be at most {MAX_MINUTES} minutes, not {minutes}"
+                )));
+            }
+        }
+        if self.log_max_size.checked_mul(1024).is_none() {
+            return Err(invalid(format!(
+                "--log-max-size of {} KiB is too large",
+                self.log_max_size
+            )));
+        }
+        if let Some(url) = &self.translation_url {
+            let parsed = url::Url::parse(url).map_err(|e| {
+                invalid(format!("--translation-url {url:?} is not a valid URL: {e}"))
+            })?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Err(invalid(format!(
+                    "--translation-url {url:?} must be an http or https URL"
+                )));
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Get the commands run on lyric and track events
+    #[must_use]
+    pub fn hooks(&self) -> Hooks {
+        Hooks::new(
+            self.on_track_change.clone(),
+            self.on_line_change.clone(),
+            self.on_no_lyrics.clone(),
+        )
+    }
+
+    /// Get how lyrics are presented
+    #[must_use]
+    pub fn display_options(&self) -> display::Options {
+        display::Options {
+            version_separator: self.version_separator.clone(),
+            line_transition: Duration::from_millis(self.line_transition),
+            json_extras: self.json_extras,
+            format: self.format.clone(),
+            percentage: self.percentage,
+            markup: self.markup,
+            escape: self.escape_mode,
+            debug_timing: self.debug_timing,
+            lead_in: Duration::from_secs(self.lead_in),
+            offline: self.offline,
+            instrumental_text: self.instrumental_text.clone(),
+            transforms: Transforms {
+                strip_readings: self.strip_readings,
+                kana_only: self.kana_only,
+                filters: self.transform.clone(),
+            },
+        }
+    }
+
     /// Build the tracing subscriber using parameters from the command line arguments
     ///
     /// # Panics
     ///
     /// Panics if the log file cannot be opened.
     pub fn init_tracing_subscriber(&self) {
-        let builder = tracing_subscriber::fmt().pretty();
+        let builder = tracing_subscriber::fmt()
+            .pretty()
+            .with_max_level(self.log_level.unwrap_or(match self.verbose {
+                0 => tracing::Level::WARN,
+                1 => tracing::Level::INFO,
+                2 => tracing::Level::DEBUG,
+                _ => tracing::Level::TRACE,
+            }));
 
         match &self.log_file {
             None => builder.with_writer(io::stderr).init(),
             Some(f) => builder
-                .with_writer(Mutex::new(File::create(f).unwrap()))
+                .with_writer(Mutex::new(
+                    RotatingFile::create(f.into(), self.log_max_size * 1024, self.log_keep)
+                        .unwrap(),
+                ))
                 .init(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation() {
+        let validate = |args: &[&str]| {
+            Args::try_parse_from(core::iter::once("waylrc").chain(args.iter().copied()))
+                .unwrap()
+                .validate()
+        };
+        assert!(validate(&[]).unwrap().is_empty());
+        assert_eq!(validate(&["--max-wait=100"]).unwrap().len(), 1);
+        for invalid in [
+            "--max-wait=0",
+            "--unsynced-line-duration=-1",
+            "--unsynced-line-duration=NaN",
+            "--audio-latency=60000",
+            "--requery-interval=18446744073709551615",
+            "--log-max-size=18446744073709551615",
+            "--translation-url=localhost:5000",
+            "--translation-url=file:///etc/passwd",
+        ] {
+            assert!(validate(&[invalid]).is_err(), "{invalid} must be rejected");
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Configuration file
+//!
+//! The configuration file holds command line options, one per line, without their leading
+//! dashes: `name = value` for options taking a value and `name` alone for flags. Lines starting
+//! with `#` are comments. Options given on the command line override those of the file, except
+//! for lists such as `lyrics-dir`, which are extended. Options set through their environment
+//! variable override those of the file too. Providers are configured in sections such as
+//! `[providers.directory]`, each holding the `name = value` options of one provider.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::CommandFactory as _;
+
+use crate::{arg::Args, providers, storage};
+
+/// Default location of the configuration file
+#[must_use]
+pub fn default_path() -> Option<PathBuf> {
+    Some(storage::config_dir()?.join("config"))
+}
+
+/// Get the configuration file given with `--config`, if any
+fn path_from_args(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// Turn the content of a configuration file into command line options.
+///
+/// The options of a `[providers.NAME]` section become a single `--provider NAME:OPTIONS`.
+///
+/// # Errors
+///
+/// Returns an error naming the line of an unknown section or of a provider option without a
+/// value.
+pub fn parse(content: &str) -> Result<Vec<OsString>, String> {
+    let mut args = Vec::new();
+    let mut section: Option<(String, Vec<String>)> = None;
+    let flush = |section: Option<(String, Vec<String>)>, args: &mut Vec<OsString>| {
+        if let Some((name, options)) = section {
+            args.push(format!("--provider={name}:{}", options.join(",")).into());
+        }
+    };
+    for (number, line) in content
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+    {
+        let number = number + 1;
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            flush(section.take(), &mut args);
+            let Some(name) = header.trim().strip_prefix("providers.") else {
+                return Err(format!("line {number}: unknown section [{header}]"));
+            };
+            section = Some((name.trim().to_owned(), Vec::new()));
+            continue;
+        }
+        let option = line
+            .split_once('=')
+            .map(|(name, value)| (name.trim(), Some(value.trim())));
+        let (name, value) = option.unwrap_or((line, None));
+        match (&mut section, value) {
+            (Some((_, options)), Some(value)) => {
+                options.push(format!("{name}={}", providers::escape(value)));
+            }
+            (Some((provider, _)), None) => {
+                return Err(format!(
+                    "line {number}: {name} in [providers.{provider}] has no value"
+                ));
+            }
+            (None, Some(value)) => args.push(format!("--{name}={value}").into()),
+            (None, None) => args.push(format!("--{name}").into()),
+        }
+    }
+    flush(section, &mut args);
+    Ok(args)
+}
+
+/// Read the options of a configuration file
+fn read(path: &Path) -> io::Result<Vec<OsString>> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("cannot read configuration file {}: {e}", path.display()),
+        )
+    })?;
+    parse(&content).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid configuration file {}: {e}", path.display()),
+        )
+    })
+}
+
+/// Drop the options that are also set through their environment variable, which takes
+/// precedence over the configuration file
+fn without_env(options: Vec<OsString>, is_set: impl Fn(&OsStr) -> bool) -> Vec<OsString> {
+    let command = Args::command();
+    options
+        .into_iter()
+        .filter(|option| {
+            let Some(option) = option.to_str().and_then(|o| o.strip_prefix("--")) else {
+                return true;
+            };
+            let name = option.split_once('=').map_or(option, |(name, _)| name);
+            !command
+                .get_arguments()
+                .filter(|arg| arg.get_long() == Some(name))
+                .filter_map(clap::Arg::get_env)
+                .any(&is_set)
+        })
+        .collect()
+}
+
+/// Get the command line arguments, with the options of the configuration file inserted before
+/// those given on the command line.
+///
+/// The file is the one given with `--config` or `WAYLRC_CONFIG`, or `waylrc/config` in the XDG
+/// configuration directory if it exists.
+///
+/// # Errors
+///
+/// Returns an error if an explicitly given configuration file cannot be read, or if the
+/// configuration file is invalid.
+pub fn args() -> io::Result<Vec<OsString>> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let explicit =
+        path_from_args(&args).or_else(|| std::env::var_os("WAYLRC_CONFIG").map(PathBuf::from));
+    let options = match explicit {
+        Some(path) => read(&path)?,
+        None => default_path()
+            .filter(|path| path.exists())
+            .map(|path| read(&path))
+            .transpose()?
+            .unwrap_or_default(),
+    };
+    // The options must come after the program name, and before any subcommand
+    args.splice(
+        1.min(args.len())..1.min(args.len()),
+        without_env(options, |name| std::env::var_os(name).is_some()),
+    );
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options() {
+        assert_eq!(
+            parse("# lyrics\nformat = {title}: {lines}\n\n  show-when-paused  \n").unwrap(),
+            vec![
+                OsString::from("--format={title}: {lines}"),
+                OsString::from("--show-when-paused")
+            ]
+        );
+        assert_eq!(
+            parse("show-when-paused\n[providers.sidecar]\n[providers.directory]\npath = /l,a\ntimeout = 5\n")
+                .unwrap(),
+            vec![
+                OsString::from("--show-when-paused"),
+                OsString::from("--provider=sidecar:"),
+                OsString::from("--provider=directory:path=/l\\,a,timeout=5")
+            ]
+        );
+        assert_eq!(
+            parse("\n[display]\n"),
+            Err("line 2: unknown section [display]".to_owned())
+        );
+        assert!(parse("[providers.directory]\npath\n").is_err());
+        assert_eq!(
+            without_env(
+                vec!["--format={title}".into(), "--max-wait=5".into()],
+                |name| name == "WAYLRC_FORMAT"
+            ),
+            vec![OsString::from("--max-wait=5")],
+            "the environment overrides the file"
+        );
+        assert_eq!(
+            path_from_args(&["waylrc".into(), "--config".into(), "a".into()]),
+            Some(PathBuf::from("a"))
+        );
+        assert_eq!(
+            path_from_args(&["waylrc".into(), "--config=b".into()]),
+            Some(PathBuf::from("b"))
+        );
+    }
+}
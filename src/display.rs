@@ -0,0 +1,782 @@
+//! Conversion of player snapshots into Waybar modules
+
+use core::time::Duration;
+
+use waylrc_core::{parser::TimeTag, state::Snapshot};
+
+use clap::ValueEnum;
+
+use crate::{
+    out::{Field, WaybarCustomModule},
+    template,
+    transform::Transforms,
+};
+
+/// What the `percentage` field of the lyrics module is based on
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Percentage {
+    /// No percentage
+    #[default]
+    None,
+    /// Position in the song
+    Track,
+    /// Current line among all lines of the lyrics
+    Lines,
+}
+
+/// Where the text of the modules is escaped for Pango
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Escape {
+    /// Escape `&`, `<` and `>` as HTML entities, for modules with `"escape": false`
+    #[default]
+    Html,
+    /// Leave the text as is, for modules with `"escape": true` where Waybar escapes it
+    None,
+}
+
+/// How the lyrics are presented
+#[derive(Clone, Debug)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "each flag is an independent option"
+)]
+pub struct Options {
+    /// Put between the lines of different lyric tracks
+    pub version_separator: String,
+    /// Applied to each line before display
+    pub transforms: Transforms,
+    /// How long the `line-new` and `line-ending` classes are set after a line starts and before
+    /// the next one. Disabled if zero.
+    pub line_transition: Duration,
+    /// Put machine-readable information about the lyrics in the `alt` field, as a JSON object
+    pub json_extras: bool,
+    /// Text of the lyrics module, with placeholders such as `{lines}`
+    pub format: String,
+    /// What the percentage of the lyrics module is based on
+    pub percentage: Percentage,
+    /// Treat the format as Pango markup, only escaping the text substituted into it
+    pub markup: bool,
+    /// Add the position, line times and drift from the reported position to the tooltip
+    pub debug_timing: bool,
+    /// Count down with dots during this long before the first line. Disabled if zero.
+    pub lead_in: Duration,
+    /// Whether only local lyric sources are used
+    pub offline: bool,
+    /// Shown in place of the lyrics of instrumental tracks
+    pub instrumental_text: String,
+    /// Whether the text is escaped by waylrc or left to Waybar. The text is always escaped
+    /// when it is markup.
+    pub escape: Escape,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            version_separator: " ".to_owned(),
+            transforms: Transforms::default(),
+            line_transition: Duration::ZERO,
+            json_extras: false,
+            format: "{lines}".to_owned(),
+            percentage: Percentage::None,
+            markup: false,
+            debug_timing: false,
+            lead_in: Duration::ZERO,
+            offline: false,
+            instrumental_text: "♪".to_owned(),
+            escape: Escape::Html,
+        }
+    }
+}
+
+/// Normalise a line for comparison, ignoring case and differences in whitespace
+fn comparison_key(line: &str) -> String {
+    line.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Join the lines of all lyric tracks, skipping blank lines and lines identical to one already
+/// shown.
+///
+/// Tracks often mark instrumental sections the same way, which would otherwise be repeated.
+fn join_lines<'a>(lines: impl IntoIterator<Item = &'a str>, separator: &str) -> String {
+    let mut seen = Vec::new();
+    let mut result = String::new();
+    for line in lines {
+        let key = comparison_key(line);
+        if key.is_empty() || seen.contains(&key) {
+            continue;
+        }
+        if !seen.is_empty() {
+            result.push_str(separator);
+        }
+        result.push_str(line);
+        seen.push(key);
+    }
+    result
+}
+
+/// Format a duration as `mm:ss`
+fn format_time(d: Duration) -> String {
+    format!("{:02}:{:02}", d.as_secs() / 60, d.as_secs() % 60)
+}
+
+/// Describe the timing of the current line, to diagnose lyrics out of sync
+fn debug_timing(snapshot: &Snapshot) -> String {
+    let tag =
+        |time: Option<Duration>| time.map_or_else(|| "-".to_owned(), |t| TimeTag(t).to_string());
+    let position = snapshot.position;
+    let drift = snapshot
+        .reported_position
+        .map(|reported| {
+            let drift = reported.as_secs_f64() - position.as_secs_f64();
+            format!(" | drift {drift:+.2}s")
+        })
+        .unwrap_or_default();
+    format!(
+        "[pos {:02}:{:02}.{:03} | line {} | next {}{drift}]",
+        position.as_secs() / 60,
+        position.as_secs() % 60,
+        position.subsec_millis(),
+        tag(snapshot.line_start),
+        tag(snapshot.line_end)
+    )
+}
+
+/// The current lyrics as plain text, with versions separated by `separator`, for outputs other
+/// than Waybar
+#[must_use]
+pub fn plain_text(snapshot: &Snapshot, options: &Options, separator: &str) -> String {
+    if snapshot.spoken_word {
+        return now_playing(snapshot);
+    }
+    if snapshot.instrumental {
+        return options.instrumental_text.clone();
+    }
+    let lines: Vec<_> = snapshot
+        .lines
+        .iter()
+        .map(|(_, line)| options.transforms.apply(line))
+        .collect();
+    join_lines(lines.iter().map(String::as_str), separator)
+}
+
+/// Build the module displaying the current lyrics
+#[must_use]
+pub fn lyrics_module(snapshot: &Snapshot, options: &Options) -> WaybarCustomModule {
+    let lines: Vec<_> = snapshot
+        .lines
+        .iter()
+        .map(|(_, line)| options.transforms.apply(line))
+        .collect();
+    let mut lyrics = join_lines(lines.iter().map(String::as_str), &options.version_separator);
+    if snapshot.spoken_word {
+        lyrics = now_playing(snapshot);
+    } else if snapshot.instrumental {
+        lyrics.clone_from(&options.instrumental_text);
+    }
+    let counting_down = lyrics.is_empty() && snapshot.line_start.is_none();
+    if let Some(dots) = counting_down
+        .then(|| lead_in(snapshot, options.lead_in))
+        .flatten()
+    {
+        lyrics = dots;
+    }
+    // Blank lines clear the whole module, whatever the format
+    let text = if lyrics.is_empty() {
+        lyrics
+    } else if options.markup {
+        template::render(&options.format, |name| {
+            placeholder(snapshot, &lyrics, &options.transforms, name)
+                .map(|value| html_escape::encode_text(&value).into_owned())
+        })
+    } else {
+        template::render(&options.format, |name| {
+            placeholder(snapshot, &lyrics, &options.transforms, name)
+        })
+    };
+    let mut tooltip = snapshot.metadata.to_string();
+    if let (Some(source), Some(quality)) = (&snapshot.source, &snapshot.quality) {
+        tooltip.push_str("lyrics: ");
+        tooltip.push_str(source);
+        tooltip.push_str(" (");
+        tooltip.push_str(&quality.to_string());
+        tooltip.push_str(")\n");
+    }
+    if let Some(diagnostic) = &snapshot.diagnostic {
+        tooltip.push_str("no lyrics: ");
+        tooltip.push_str(diagnostic);
+    }
+    if options.debug_timing {
+        if !tooltip.is_empty() && !tooltip.ends_with('\n') {
+            tooltip.push('\n');
+        }
+        tooltip.push_str(&debug_timing(snapshot));
+    }
+    let class = snapshot.paused.then_some("paused");
+    let extras = options.json_extras.then(|| json_extras(snapshot));
+    let percentage = match options.percentage {
+        Percentage::None => None,
+        Percentage::Track => track_percentage(snapshot),
+        Percentage::Lines => snapshot
+            .line_index
+            .filter(|_| snapshot.line_total != 0)
+            .map(|i| (i * 100 / snapshot.line_total).min(100)),
+    };
+    let mut module = WaybarCustomModule::new(
+        Some(&text),
+        extras.as_deref(),
+        Some(&tooltip),
+        class,
+        percentage,
+    );
+    if options.markup {
+        module.set_markup(Field::Text, &text);
+    }
+    if options.escape == Escape::None {
+        if !options.markup {
+            module.set_markup(Field::Text, &text);
+        }
+        if let Some(extras) = &extras {
+            module.set_markup(Field::Alt, extras);
+        }
+        module.set_markup(Field::Tooltip, &tooltip);
+    }
+    for class in transition_classes(snapshot, options.line_transition) {
+        module.add_class(class);
+    }
+    if snapshot.spoken_word {
+        module.add_class("spoken-word");
+    }
+    if snapshot.instrumental {
+        module.add_class("instrumental");
+    }
+    if options.offline {
+        module.add_class("offline");
+    }
+    if snapshot.degraded {
+        module.add_class("provider-degraded");
+    }
+    if counting_down && !text.is_empty() {
+        module.add_class("lead-in");
+    }
+    module
+}
+
+/// Artists and title of the current song, shown instead of lyrics for podcasts and audiobooks
+fn now_playing(snapshot: &Snapshot) -> String {
+    let artists = snapshot.artists.join(", ");
+    match (artists.is_empty(), &snapshot.title) {
+        (false, Some(title)) => format!("{artists} - {title}"),
+        (true, Some(title)) => title.clone(),
+        (_, None) => artists,
+    }
+}
+
+/// Countdown shown during the last `lead_in` before the first line, one dot per remaining second
+fn lead_in(snapshot: &Snapshot, lead_in: Duration) -> Option<String> {
+    let remaining = snapshot.line_end?.checked_sub(snapshot.position)?;
+    if remaining.is_zero() || remaining > lead_in {
+        return None;
+    }
+    let dots = remaining.as_secs() + u64::from(remaining.subsec_nanos() != 0);
+    Some(vec!["●"; usize::try_from(dots).ok()?].join(" "))
+}
+
+/// Value of a placeholder in the format of the lyrics module. The neighbouring lines are
+/// transformed like the current ones.
+fn placeholder(
+    snapshot: &Snapshot,
+    lyrics: &str,
+    transforms: &Transforms,
+    name: &str,
+) -> Option<String> {
+    Some(match name {
+        "lines" => lyrics.to_owned(),
+        "title" => snapshot.title.clone().unwrap_or_default(),
+        "artists" => snapshot.artists.join(", "),
+        "line_index" => snapshot
+            .line_index
+            .map(|i| i.to_string())
+            .unwrap_or_default(),
+        "line_total" => snapshot.line_total.to_string(),
+        "prev_line" => snapshot
+            .prev_line
+            .as_deref()
+            .map(|line| transforms.apply(line))
+            .unwrap_or_default(),
+        "next_line" => snapshot
+            .next_line
+            .as_deref()
+            .map(|line| transforms.apply(line))
+            .unwrap_or_default(),
+        "loop_count" if snapshot.plays > 1 => format!("(x{})", snapshot.plays),
+        "loop_count" => String::new(),
+        _ => return None,
+    })
+}
+
+/// Position in the song as a percentage, if its length is known
+fn track_percentage(snapshot: &Snapshot) -> Option<usize> {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        reason = "the ratio is clamped to [0, 100]"
+    )]
+    snapshot.length.filter(|l| !l.is_zero()).map(|l| {
+        (snapshot.position.as_secs_f64() / l.as_secs_f64() * 100.0).clamp(0.0, 100.0) as usize
+    })
+}
+
+/// Information about the lyrics for widgets parsing the module, as a compact JSON object
+fn json_extras(snapshot: &Snapshot) -> String {
+    #[allow(
+        clippy::cast_possible_truncation,
+        reason = "offsets beyond the range of u64 milliseconds are not meaningful"
+    )]
+    let offset_ms = snapshot.offset.as_millis() as u64;
+    serde_json::json!({
+        "source": snapshot.source,
+        "track_id": snapshot.track_id,
+        "offset_ms": offset_ms,
+        "line_index": snapshot.line_index,
+        "line_total": snapshot.line_total,
+    })
+    .to_string()
+}
+
+/// Classes marking a line that has just started or is about to end, for CSS transitions
+fn transition_classes(snapshot: &Snapshot, transition: Duration) -> Vec<&'static str> {
+    let mut classes = Vec::new();
+    if transition.is_zero() || snapshot.paused {
+        return classes;
+    }
+    if snapshot
+        .line_start
+        .is_some_and(|start| snapshot.position.saturating_sub(start) < transition)
+    {
+        classes.push("line-new");
+    }
+    if snapshot
+        .line_end
+        .is_some_and(|end| end.saturating_sub(snapshot.position) <= transition)
+    {
+        classes.push("line-ending");
+    }
+    classes
+}
+
+/// Build the module displaying what is playing, without lyrics
+#[must_use]
+pub fn metadata_module(snapshot: &Snapshot, escape: Escape) -> WaybarCustomModule {
+    let text = match (&snapshot.title, snapshot.artists.is_empty()) {
+        (Some(title), false) => format!("{} - {title}", snapshot.artists.join(", ")),
+        (Some(title), true) => title.clone(),
+        (None, _) => snapshot.artists.join(", "),
+    };
+    let mut tooltip = snapshot.metadata.to_string();
+    tooltip.push_str(&format_time(snapshot.position));
+    if let Some(length) = snapshot.length {
+        tooltip.push_str(" / ");
+        tooltip.push_str(&format_time(length));
+    }
+    let class = snapshot.paused.then_some("paused");
+    let mut module = WaybarCustomModule::new(
+        Some(&text),
+        None,
+        Some(&tooltip),
+        class,
+        track_percentage(snapshot),
+    );
+    if escape == Escape::None {
+        module.set_markup(Field::Text, &text);
+        module.set_markup(Field::Tooltip, &tooltip);
+    }
+    module
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use super::*;
+    use waylrc_core::{
+        metadata::MetadataExt as _,
+        mpris::{DBusError, Metadata, MetadataValue, PlaybackStatus},
+        parser::{Lrc, TrackKind},
+        player::{Bus, MediaPlayer},
+        provider::{error, Provider},
+        state::{self, State},
+    };
+
+    #[test]
+    fn escaping() {
+        let snapshot = Snapshot {
+            lines: vec![(TrackKind::Original, "Rock & <roll>".to_owned())],
+            line_start: Some(Duration::ZERO),
+            ..Snapshot::default()
+        };
+        let text = |escape| {
+            let options = Options {
+                escape,
+                ..Options::default()
+            };
+            let mut buf = Vec::new();
+            lyrics_module(&snapshot, &options).format(&mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert!(text(Escape::Html).starts_with(r#"{"text":"Rock &amp; &lt;roll&gt;""#));
+        assert!(text(Escape::None).starts_with(r#"{"text":"Rock & <roll>""#));
+        let translated = Snapshot {
+            lines: vec![
+                (TrackKind::Original, "Rock & <roll>".to_owned()),
+                (TrackKind::Translation, "Rock'n'roll".to_owned()),
+            ],
+            ..snapshot
+        };
+        assert_eq!(
+            plain_text(&translated, &Options::default(), "\n"),
+            "Rock & <roll>\nRock'n'roll"
+        );
+    }
+
+    /// What the simulated player is playing, changed by the scenario between updates
+    struct Playback {
+        title: &'static str,
+        status: PlaybackStatus,
+        position: Duration,
+    }
+
+    /// A simulated player, which quits when its playback is taken away. It is also the bus it is
+    /// found on.
+    #[derive(Clone)]
+    struct FakePlayer(Rc<RefCell<Option<Playback>>>);
+
+    impl MediaPlayer for FakePlayer {
+        fn bus_name(&self) -> &'static str {
+            "org.mpris.MediaPlayer2.fake"
+        }
+
+        fn bus_name_player_name_part(&self) -> &'static str {
+            "fake"
+        }
+
+        fn unique_name(&self) -> &'static str {
+            ":1.1"
+        }
+
+        fn identity(&self) -> &'static str {
+            "Fake"
+        }
+
+        fn get_metadata(&self) -> Result<Metadata, DBusError> {
+            let title = self.0.borrow().as_ref().map_or("", |p| p.title);
+            Ok(Metadata::from(HashMap::from([
+                (
+                    "xesam:title".to_owned(),
+                    MetadataValue::String(title.to_owned()),
+                ),
+                (
+                    "xesam:artist".to_owned(),
+                    MetadataValue::String("Artist".to_owned()),
+                ),
+                ("mpris:length".to_owned(), MetadataValue::I64(200_000_000)),
+            ])))
+        }
+
+        fn get_playback_status(&self) -> Result<PlaybackStatus, DBusError> {
+            Ok(self
+                .0
+                .borrow()
+                .as_ref()
+                .map_or(PlaybackStatus::Stopped, |p| p.status))
+        }
+
+        fn get_position_in_microseconds(&self) -> Result<u64, DBusError> {
+            let position = self
+                .0
+                .borrow()
+                .as_ref()
+                .map_or(0, |p| p.position.as_micros());
+            Ok(u64::try_from(position).unwrap())
+        }
+
+        fn get_playback_rate(&self) -> Result<f64, DBusError> {
+            Ok(1.0)
+        }
+
+        fn next_track_metadata(&self, _: &Metadata) -> Option<Metadata> {
+            None
+        }
+    }
+
+    impl Bus for FakePlayer {
+        type Player = Self;
+
+        fn find_all(&self) -> Result<Vec<Self>, DBusError> {
+            Ok(self
+                .0
+                .borrow()
+                .is_some()
+                .then(|| self.clone())
+                .into_iter()
+                .collect())
+        }
+
+        fn reconnect(&mut self) -> Result<(), DBusError> {
+            Ok(())
+        }
+    }
+
+    /// Lyrics of the songs of the scenario, the last one being instrumental
+    struct Songs;
+
+    impl Provider for Songs {
+        fn name(&self) -> &'static str {
+            "songs"
+        }
+
+        fn fetch(&self, metadata: &Metadata) -> Result<Lrc, error::Resolve> {
+            let lyrics = match metadata.tags().title() {
+                Some("One") => {
+                    "[00:04.00]First & last\n[00:08.00]Second\n[00:08.00]Zweite\n\
+                                [00:12.00]Third\n[00:16.00]Fourth"
+                }
+                Some("Two <live>") => "[00:04.00]Opening\n[00:12.00]Last",
+                _ => return Err(error::Resolve::Instrumental),
+            };
+            Ok(Lrc::from_str(lyrics).unwrap())
+        }
+    }
+
+    /// The module printed at each step of a simulated player session must not change
+    /// unexpectedly. After an intended change, update `tests/fixtures/lyrics_module.txt` with
+    /// the output shown by the failure.
+    #[test]
+    fn scenarios() {
+        let playback = |title, status, secs| {
+            Some(Playback {
+                title,
+                status,
+                position: Duration::from_millis(secs),
+            })
+        };
+        let steps = [
+            ("play", playback("One", PlaybackStatus::Playing, 5000)),
+            ("next line", playback("One", PlaybackStatus::Playing, 9000)),
+            ("pause", playback("One", PlaybackStatus::Paused, 10_000)),
+            ("seek", playback("One", PlaybackStatus::Playing, 4500)),
+            (
+                "track change",
+                playback("Two <live>", PlaybackStatus::Playing, 0),
+            ),
+            (
+                "last line",
+                playback("Two <live>", PlaybackStatus::Playing, 13_000),
+            ),
+            (
+                "loop",
+                playback("Two <live>", PlaybackStatus::Playing, 1000),
+            ),
+            (
+                "instrumental",
+                playback("Three", PlaybackStatus::Playing, 30_000),
+            ),
+            ("quit", None),
+        ];
+        let player = FakePlayer(Rc::default());
+        let mut state = State::with_bus(
+            player.clone(),
+            state::Options {
+                debounce: Duration::ZERO,
+                show_when_paused: true,
+                ..state::Options::default()
+            },
+            vec![Box::new(Songs)],
+        );
+        let options = Options {
+            json_extras: true,
+            ..Options::default()
+        };
+        let mut output = String::new();
+        for (step, playback) in steps {
+            *player.0.borrow_mut() = playback;
+            let (snapshot, _) = state.update();
+            let mut line = Vec::new();
+            lyrics_module(&snapshot.unwrap(), &options)
+                .format(&mut line)
+                .unwrap();
+            output.push_str(step);
+            output.push('\t');
+            output.push_str(&String::from_utf8(line).unwrap());
+        }
+        let expected = std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/lyrics_module.txt"
+        ))
+        .unwrap();
+        assert!(
+            output == expected,
+            "the module output changed, from:\n{expected}\nto:\n{output}"
+        );
+    }
+
+    #[test]
+    fn duplicate_lines() {
+        let lines = ["間奏", "間奏 ", "Interlude", "interlude"];
+        assert_eq!(join_lines(lines, " | "), "間奏 | Interlude");
+        assert_eq!(join_lines([], " | "), "");
+    }
+
+    #[test]
+    fn transitions() {
+        let transition = Duration::from_millis(500);
+        let at = |ms| Snapshot {
+            position: Duration::from_millis(ms),
+            line_start: Some(Duration::from_secs(10)),
+            line_end: Some(Duration::from_secs(12)),
+            ..Snapshot::default()
+        };
+        assert_eq!(transition_classes(&at(10_100), transition), ["line-new"]);
+        assert!(transition_classes(&at(11_000), transition).is_empty());
+        assert_eq!(transition_classes(&at(11_600), transition), ["line-ending"]);
+        assert!(transition_classes(&at(11_600), Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn extras() {
+        let snapshot = Snapshot {
+            source: Some("sidecar".to_owned()),
+            line_index: Some(3),
+            line_total: 40,
+            ..Snapshot::default()
+        };
+        assert_eq!(
+            json_extras(&snapshot),
+            r#"{"line_index":3,"line_total":40,"offset_ms":0,"source":"sidecar","track_id":null}"#
+        );
+    }
+
+    #[test]
+    fn countdown() {
+        let options = Options {
+            lead_in: Duration::from_secs(3),
+            ..Options::default()
+        };
+        let dots = |ms| {
+            let snapshot = Snapshot {
+                position: Duration::from_millis(ms),
+                line_end: Some(Duration::from_secs(10)),
+                ..Snapshot::default()
+            };
+            lead_in(&snapshot, options.lead_in)
+        };
+        assert_eq!(dots(6_000), None);
+        assert_eq!(dots(7_500).as_deref(), Some("● ● ●"));
+        assert_eq!(dots(9_000).as_deref(), Some("●"));
+        assert_eq!(dots(10_000), None);
+    }
+
+    #[test]
+    fn timing() {
+        let snapshot = Snapshot {
+            position: Duration::from_millis(83_456),
+            reported_position: Some(Duration::from_millis(84_016)),
+            line_start: Some(Duration::from_millis(82_900)),
+            line_end: Some(Duration::from_millis(85_100)),
+            ..Snapshot::default()
+        };
+        assert_eq!(
+            debug_timing(&snapshot),
+            "[pos 01:23.456 | line 01:22.90 | next 01:25.10 | drift +0.56s]"
+        );
+        assert_eq!(
+            debug_timing(&Snapshot::default()),
+            "[pos 00:00.000 | line - | next -]"
+        );
+    }
+
+    #[test]
+    fn format() {
+        let snapshot = Snapshot {
+            lines: vec![(waylrc_core::parser::TrackKind::Original, "Hello".to_owned())],
+            line_index: Some(3),
+            line_total: 4,
+            plays: 2,
+            ..Snapshot::default()
+        };
+        let options = Options {
+            format: "{line_index}/{line_total} {lines} {loop_count}".to_owned(),
+            percentage: Percentage::Lines,
+            ..Options::default()
+        };
+        let mut buf = Vec::new();
+        lyrics_module(&snapshot, &options).format(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"text\":\"3/4 Hello (x2)\",\"tooltip\":\"\",\"percentage\":75}\n"
+        );
+
+        let snapshot = Snapshot {
+            prev_line: Some("Before".to_owned()),
+            next_line: Some("After".to_owned()),
+            ..snapshot
+        };
+        let options = Options {
+            format: "{prev_line} {lines} {next_line}".to_owned(),
+            transforms: Transforms {
+                filters: vec![crate::transform::Filter::Uppercase],
+                ..Transforms::default()
+            },
+            ..Options::default()
+        };
+        let mut buf = Vec::new();
+        lyrics_module(&snapshot, &options).format(&mut buf).unwrap();
+        assert!(
+            String::from_utf8(buf)
+                .unwrap()
+                .starts_with("{\"text\":\"BEFORE HELLO AFTER\""),
+            "the neighbouring lines are transformed too"
+        );
+    }
+
+    #[test]
+    fn markup() {
+        let snapshot = Snapshot {
+            lines: vec![(waylrc_core::parser::TrackKind::Original, "R&B".to_owned())],
+            ..Snapshot::default()
+        };
+        let options = Options {
+            format: "<b>{lines}</b>".to_owned(),
+            markup: true,
+            ..Options::default()
+        };
+        let mut buf = Vec::new();
+        lyrics_module(&snapshot, &options).format(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"text\":\"<b>R&amp;B</b>\",\"tooltip\":\"\"}\n"
+        );
+    }
+
+    #[test]
+    fn metadata() {
+        let snapshot = Snapshot {
+            metadata: "title: Song\n".into(),
+            title: Some("Song".to_owned()),
+            artists: vec!["A".to_owned(), "B".to_owned()],
+            position: Duration::from_secs(75),
+            length: Some(Duration::from_secs(150)),
+            ..Snapshot::default()
+        };
+        let mut buf = Vec::new();
+        metadata_module(&snapshot, Escape::Html)
+            .format(&mut buf)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"text\":\"A, B - Song\",\"tooltip\":\"title: Song\\n01:15 / 02:30\",\"percentage\":50}\n"
+        );
+    }
+}
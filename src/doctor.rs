@@ -0,0 +1,206 @@
+//! Self-check of the environment waylrc runs in
+
+use std::{ffi::CString, io, os::unix::ffi::OsStrExt as _, path::Path};
+
+use waylrc_core::{metadata::MetadataExt as _, mpris, mpris::PlayerFinder, provider};
+
+use crate::arg::Args;
+
+/// Result of a single check
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Print the result of a check, with a hint on how to fix it if it did not pass
+fn report(status: &Status, message: &str, hint: Option<&str>) {
+    let tag = match status {
+        Status::Ok => "[ok]  ",
+        Status::Warn => "[warn]",
+        Status::Fail => "[fail]",
+    };
+    println!("{tag} {message}");
+    if let (Status::Warn | Status::Fail, Some(hint)) = (status, hint) {
+        println!("       hint: {hint}");
+    }
+}
+
+/// Whether a file can be written, or created if it does not exist yet, without touching it
+fn writable(path: &Path) -> io::Result<()> {
+    let (target, mode) = if path.exists() {
+        (path, libc::W_OK)
+    } else {
+        let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+        (parent.unwrap_or(Path::new(".")), libc::W_OK | libc::X_OK)
+    };
+    let target = CString::new(target.as_os_str().as_bytes())?;
+    // SAFETY: the path is a valid nul-terminated string
+    if unsafe { libc::access(target.as_ptr(), mode) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Check that the directory of a local track can be read, so that sidecar lyrics can be found
+fn check_lyric_directory(url: &str) {
+    let Some(path) = provider::url_to_path(url) else {
+        report(
+            &Status::Warn,
+            &format!("track {url} is not a local file"),
+            Some("only local files can have sidecar or embedded lyrics"),
+        );
+        return;
+    };
+    let Some(dir) = path.parent() else {
+        return;
+    };
+    match dir.read_dir() {
+        Ok(_) => report(
+            &Status::Ok,
+            &format!("lyric directory {} is readable", dir.display()),
+            None,
+        ),
+        Err(e) => report(
+            &Status::Fail,
+            &format!("lyric directory {} cannot be read: {e}", dir.display()),
+            Some("check the permissions of the music directory"),
+        ),
+    }
+}
+
+/// Check that the current track of a player can be read and has lyrics
+fn check_player(player: &mpris::Player, providers: &[Box<dyn provider::Provider>]) {
+    let status = player
+        .get_playback_status()
+        .map_or_else(|e| format!("unknown status: {e}"), |s| format!("{s:?}"));
+    report(
+        &Status::Ok,
+        &format!(
+            "found player {} ({}): {status}",
+            player.identity(),
+            player.bus_name()
+        ),
+        None,
+    );
+    let metadata = match player.get_metadata() {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            report(
+                &Status::Warn,
+                &format!("cannot read metadata of {}: {e}", player.identity()),
+                None,
+            );
+            return;
+        }
+    };
+    let Some(url) = metadata.tags().url() else {
+        report(
+            &Status::Warn,
+            &format!("{} does not report the url of its track", player.identity()),
+            Some("lyrics can only be found for players exposing xesam:url"),
+        );
+        return;
+    };
+    check_lyric_directory(url);
+    match provider::resolve(providers, &metadata) {
+        Ok(resolved) => report(
+            &Status::Ok,
+            &format!(
+                "{} found lyrics with {} track(s) for {url} ({})",
+                resolved.provider,
+                resolved.lyrics.tracks().len(),
+                resolved.quality
+            ),
+            None,
+        ),
+        Err(e) => report(
+            &Status::Warn,
+            &format!("no lyrics for {url}: {e}"),
+            Some("place a .lrc file with the same name next to the audio file, or embed lyrics in its tags"),
+        ),
+    }
+}
+
+/// Run all checks and print the results.
+///
+/// Returns whether all essential checks passed.
+#[must_use]
+pub fn run(args: &Args) -> bool {
+    let mut healthy = true;
+
+    if let Some(log_file) = &args.log_file {
+        match writable(Path::new(log_file)) {
+            Ok(()) => report(
+                &Status::Ok,
+                &format!("log file {log_file} is writable"),
+                None,
+            ),
+            Err(e) => {
+                healthy = false;
+                report(
+                    &Status::Fail,
+                    &format!("log file {log_file} is not writable: {e}"),
+                    Some("choose a --log-file in a writable directory"),
+                );
+            }
+        }
+    }
+
+    let finder = match PlayerFinder::new() {
+        Ok(finder) => {
+            report(&Status::Ok, "D-Bus session bus is reachable", None);
+            finder
+        }
+        Err(e) => {
+            report(
+                &Status::Fail,
+                &format!("cannot connect to the D-Bus session bus: {e}"),
+                Some("make sure DBUS_SESSION_BUS_ADDRESS is set in the environment Waybar runs in"),
+            );
+            return false;
+        }
+    };
+
+    let players = match finder.find_all() {
+        Ok(players) => players,
+        Err(mpris::FindingError::NoPlayerFound) => Vec::new(),
+        Err(mpris::FindingError::DBusError(e)) => {
+            report(
+                &Status::Fail,
+                &format!("cannot list MPRIS players: {e}"),
+                None,
+            );
+            return false;
+        }
+    };
+    if players.is_empty() {
+        report(
+            &Status::Warn,
+            "no MPRIS player is running",
+            Some("start a media player that supports MPRIS (for mpv, install mpv-mpris)"),
+        );
+        return healthy;
+    }
+
+    let providers = args.providers();
+    for player in players {
+        check_player(&player, &providers);
+    }
+
+    healthy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_file() {
+        let path = std::env::temp_dir().join(format!("waylrc-doctor-{}.log", std::process::id()));
+        assert!(writable(&path).is_ok());
+        assert!(!path.exists(), "the log file is not created");
+        assert!(writable(&path.join("log")).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+//! Generation of shell completions and of the manual page, for packaging
+
+use std::io::{self, Write};
+
+use clap::{CommandFactory, ValueEnum};
+use clap_complete::Shell;
+
+use crate::arg::Args;
+
+/// What to generate
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// Completions for bash
+    Bash,
+    /// Completions for zsh
+    Zsh,
+    /// Completions for fish
+    Fish,
+    /// The manual page, in roff
+    Man,
+}
+
+/// Write the completions or the manual page
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub fn run(target: Target, out: &mut impl Write) -> io::Result<()> {
+    let mut command = Args::command();
+    let shell = match target {
+        Target::Bash => Shell::Bash,
+        Target::Zsh => Shell::Zsh,
+        Target::Fish => Shell::Fish,
+        Target::Man => return clap_mangen::Man::new(command).render(out),
+    };
+    clap_complete::generate(shell, &mut command, env!("CARGO_PKG_NAME"), out);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets() {
+        for target in Target::value_variants() {
+            let mut out = Vec::new();
+            run(*target, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert!(out.contains("doctor"), "{target:?} misses subcommands");
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! User commands run when the track, the line or the lyrics change
+//!
+//! Commands are run with `sh -c` in the background, with the song and the line in
+//! `WAYLRC_TITLE`, `WAYLRC_ARTIST`, `WAYLRC_LINE` and `WAYLRC_SOURCE`, so that captions or a rich
+//! presence can be fed without waiting on them. A command still running when its hook runs
+//! again is killed, with the processes it started, so that commands do not pile up and an older
+//! one cannot finish after a newer one.
+
+use std::{
+    os::unix::process::CommandExt as _,
+    process::{Child, Command, Stdio},
+};
+
+use waylrc_core::state::Snapshot;
+
+/// An event a hook can be run on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Another song started
+    TrackChange,
+    /// Another line of the lyrics is shown
+    LineChange,
+    /// No lyrics were found for the current song
+    NoLyrics,
+}
+
+/// Track id, title, artists and number of plays in a row of a song, which changes when another
+/// song starts or the same one plays again
+type Song = (Option<String>, Option<String>, Vec<String>, u32);
+
+/// Runs the configured commands on the events of the snapshots
+#[derive(Default)]
+pub struct Hooks {
+    on_track_change: Option<String>,
+    on_line_change: Option<String>,
+    on_no_lyrics: Option<String>,
+    /// The last song
+    track: Option<Song>,
+    /// Line shown in the last snapshot
+    line: Option<(Option<usize>, Option<String>)>,
+    /// Whether the missing lyrics of the current song were reported
+    no_lyrics: bool,
+    /// Commands still running, by hook, waited on once they exit
+    running: Vec<(Event, Child)>,
+}
+
+/// Kill a command still running and the processes it started
+fn kill(event: Event, child: &mut Child) {
+    tracing::debug!("killing {:?} hook still running", event);
+    if let Ok(pid) = i32::try_from(child.id()) {
+        // SAFETY: kill has no memory safety requirements
+        unsafe { libc::kill(-pid, libc::SIGKILL) };
+    }
+    let _ = child.wait();
+}
+
+impl Hooks {
+    #[must_use]
+    pub fn new(
+        on_track_change: Option<String>,
+        on_line_change: Option<String>,
+        on_no_lyrics: Option<String>,
+    ) -> Self {
+        Self {
+            on_track_change,
+            on_line_change,
+            on_no_lyrics,
+            ..Self::default()
+        }
+    }
+
+    /// Whether any hook is configured
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.on_track_change.is_none()
+            && self.on_line_change.is_none()
+            && self.on_no_lyrics.is_none()
+    }
+
+    /// Events of a snapshot, given the previous ones
+    fn events(&mut self, snapshot: &Snapshot) -> Vec<Event> {
+        let mut events = Vec::new();
+        if snapshot.title.is_none() && snapshot.artists.is_empty() {
+            // The display is blank, e.g. while paused, which does not end the song
+            return events;
+        }
+        let track = (
+            snapshot.track_id.clone(),
+            snapshot.title.clone(),
+            snapshot.artists.clone(),
+            snapshot.plays,
+        );
+        if self.track.as_ref() != Some(&track) {
+            self.track = Some(track);
+            self.line = None;
+            self.no_lyrics = false;
+            events.push(Event::TrackChange);
+        }
+        if snapshot.diagnostic.is_some() && !self.no_lyrics {
+            self.no_lyrics = true;
+            events.push(Event::NoLyrics);
+        }
+        let line = (
+            snapshot.line_index,
+            snapshot.lines.first().map(|(_, text)| text.clone()),
+        );
+        if snapshot.line_index.is_some() && self.line.as_ref() != Some(&line) {
+            events.push(Event::LineChange);
+        }
+        self.line = Some(line);
+        events
+    }
+
+    /// Run the hooks of the events of a snapshot
+    pub fn run(&mut self, snapshot: &Snapshot) {
+        self.running
+            .retain_mut(|(_, child)| child.try_wait().is_ok_and(|status| status.is_none()));
+        for event in self.events(snapshot) {
+            let command = match event {
+                Event::TrackChange => &self.on_track_change,
+                Event::LineChange => &self.on_line_change,
+                Event::NoLyrics => &self.on_no_lyrics,
+            };
+            let Some(command) = command else {
+                continue;
+            };
+            if let Some(at) = self.running.iter().position(|(hook, _)| *hook == event) {
+                let (_, mut child) = self.running.swap_remove(at);
+                kill(event, &mut child);
+            }
+            tracing::debug!("running {:?} hook: {}", event, command);
+            let line = snapshot.lines.first().map_or("", |(_, text)| text.as_str());
+            let spawned = Command::new("sh")
+                .args(["-c", command])
+                .env(
+                    "WAYLRC_TITLE",
+                    snapshot.title.as_deref().unwrap_or_default(),
+                )
+                .env("WAYLRC_ARTIST", snapshot.artists.join(", "))
+                .env("WAYLRC_LINE", line)
+                .env(
+                    "WAYLRC_SOURCE",
+                    snapshot.source.as_deref().unwrap_or_default(),
+                )
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                // In a process group of its own, so that it can be killed with its children
+                .process_group(0)
+                .spawn();
+            match spawned {
+                Ok(child) => self.running.push((event, child)),
+                Err(e) => tracing::warn!("Failed to run hook {}: {}", command, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use waylrc_core::parser::TrackKind;
+
+    #[test]
+    fn events() {
+        let mut hooks = Hooks::default();
+        let mut snapshot = Snapshot {
+            title: Some("One".to_owned()),
+            ..Snapshot::default()
+        };
+        assert_eq!(hooks.events(&snapshot), [Event::TrackChange]);
+        assert!(hooks.events(&snapshot).is_empty());
+        snapshot.line_index = Some(1);
+        snapshot.lines = vec![(TrackKind::Original, "la".to_owned())];
+        assert_eq!(hooks.events(&snapshot), [Event::LineChange]);
+        assert!(hooks.events(&snapshot).is_empty());
+        assert!(hooks.events(&Snapshot::default()).is_empty());
+        assert!(
+            hooks.events(&snapshot).is_empty(),
+            "nothing changes when resuming"
+        );
+        snapshot.plays = 2;
+        assert_eq!(
+            hooks.events(&snapshot),
+            [Event::TrackChange, Event::LineChange],
+            "the song played again"
+        );
+
+        let missing = Snapshot {
+            title: Some("Two".to_owned()),
+            diagnostic: Some("no lyrics".to_owned()),
+            ..Snapshot::default()
+        };
+        assert_eq!(
+            hooks.events(&missing),
+            [Event::TrackChange, Event::NoLyrics]
+        );
+        assert!(hooks.events(&missing).is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+//! Starter configuration for new users
+//!
+//! `waylrc init-config` writes a commented configuration file and prints what to add to the
+//! configuration of the bar, which is where most setup mistakes happen (e.g. a Waybar module
+//! without `"return-type": "json"`).
+
+use std::{fs, path::Path};
+
+use clap::ValueEnum;
+
+/// Status bar waylrc is set up for
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Bar {
+    #[default]
+    Waybar,
+    Yambar,
+    Polybar,
+}
+
+/// Content of the configuration file for a bar
+fn config(bar: Bar) -> String {
+    let output = match bar {
+        Bar::Waybar => "",
+        Bar::Yambar => "\n# The bar reads the lyrics as a yambar script module\noutput = yambar\n",
+        Bar::Polybar => "\n# The bar reads the lyrics as lines of text\noutput = plain\n",
+    };
+    format!(
+        "\
+# waylrc configuration: one option per line, as on the command line without the leading
+# dashes. Options given on the command line take precedence. Run `waylrc --help` for all of
+# them.
+{output}
+# Text of the module
+# format = {{lines}}
+
+# Keep showing the lyrics of a paused player
+# show-when-paused
+
+# Preferred players, most preferred first
+# player-priority = mpd,spotify
+
+# Directories holding `Artist - Title.lrc` files
+# lyrics-dir = /home/me/Music/lyrics
+
+# Providers looked up in order, each with its options
+# [providers.pinned]
+# [providers.sidecar]
+# [providers.embedded]
+"
+    )
+}
+
+/// What to add to the configuration of the bar
+fn snippet(bar: Bar) -> &'static str {
+    match bar {
+        Bar::Waybar => {
+            r#"Add to your Waybar configuration:
+
+    "modules-right": ["custom/waylrc"],
+    "custom/waylrc": {
+        "exec": "waylrc",
+        "return-type": "json",
+        "escape": false
+    }
+"#
+        }
+        Bar::Yambar => {
+            r"Add to your yambar configuration:
+
+    - script:
+        path: /usr/bin/waylrc
+        content:
+          string: {text: '{lyrics}'}
+"
+        }
+        Bar::Polybar => {
+            r"Add to your polybar configuration, and `lyrics` to the modules of the bar:
+
+    [module/lyrics]
+    type = custom/script
+    exec = waylrc
+    tail = true
+"
+        }
+    }
+}
+
+/// Write the starter configuration file and print what to add to the bar
+///
+/// # Errors
+///
+/// Returns an error if the file exists and `force` is not set, or it cannot be written.
+pub fn run(path: &Path, bar: Bar, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if path.exists() && !force {
+        return Err(format!("{} exists, use --force to replace it", path.display()).into());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, config(bar))?;
+    println!("wrote {}\n", path.display());
+    print!("{}", snippet(bar));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::arg::Args;
+
+    #[test]
+    fn configs() {
+        for bar in Bar::value_variants() {
+            let args = core::iter::once("waylrc".into())
+                .chain(crate::config::parse(&config(*bar)).unwrap());
+            assert!(
+                Args::try_parse_from(args).is_ok(),
+                "{bar:?} config is invalid"
+            );
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Compensation for the latency of the audio output
+//!
+//! Players report the position of the audio they have sent to the sound server, which is heard
+//! later on outputs such as Bluetooth headphones, so lyrics would otherwise appear early.
+
+use core::{str::FromStr, time::Duration};
+use std::process::Command;
+
+/// How the audio latency is determined
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioLatency {
+    /// A fixed latency in milliseconds
+    Fixed(u64),
+    /// Ask the sound server for the latency of the default output
+    Auto,
+}
+
+impl FromStr for AudioLatency {
+    type Err = core::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        s.parse().map(Self::Fixed)
+    }
+}
+
+impl AudioLatency {
+    /// Get the latency to compensate for, falling back to none if it cannot be detected
+    #[must_use]
+    pub fn resolve(self) -> Duration {
+        match self {
+            Self::Fixed(ms) => Duration::from_millis(ms),
+            Self::Auto => detect().unwrap_or_else(|| {
+                tracing::warn!("Failed to detect audio latency, assuming none");
+                Duration::ZERO
+            }),
+        }
+    }
+}
+
+/// Run a command and get its output, if it succeeds
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| tracing::warn!("Failed to run {}: {}", program, e))
+        .ok()?;
+    if !output.status.success() {
+        tracing::warn!("{} exited with {}", program, output.status);
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Ask the sound server for the latency of the default sink, through `pactl` which works with
+/// both `PulseAudio` and `PipeWire`
+fn detect() -> Option<Duration> {
+    let default = run("pactl", &["get-default-sink"])?;
+    let sinks = run("pactl", &["--format=json", "list", "sinks"])?;
+    let micros = sink_latency(&sinks, default.trim())?;
+    tracing::info!(
+        "detected audio latency of {}us on {}",
+        micros,
+        default.trim()
+    );
+    Duration::try_from_secs_f64(micros / 1_000_000.0).ok()
+}
+
+/// Get the latency of a sink in microseconds from the JSON output of `pactl list sinks`
+fn sink_latency(sinks: &str, name: &str) -> Option<f64> {
+    let sinks: serde_json::Value = serde_json::from_str(sinks).ok()?;
+    sinks.as_array()?.iter().find(|sink| sink["name"] == name)?["latency"]["actual"].as_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("250".parse(), Ok(AudioLatency::Fixed(250)));
+        assert_eq!("auto".parse(), Ok(AudioLatency::Auto));
+        assert!("-5".parse::<AudioLatency>().is_err());
+        let sinks = r#"[{"name":"speakers","latency":{"actual":20000.0}},
+            {"name":"bluez_output.headphones","latency":{"actual":312000.0,"configured":0.0}}]"#;
+        assert_eq!(
+            sink_latency(sinks, "bluez_output.headphones"),
+            Some(312_000.0)
+        );
+        assert_eq!(sink_latency(sinks, "missing"), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! A log file rotated when it grows too large, so that long-running instances do not fill the
+//! disk
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// A log file moved to `<path>.1` once it reaches a size, the previous ones being moved to
+/// `<path>.2` and so on up to a number of kept files
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    /// Size above which the file is rotated, or 0 to never rotate it
+    max_bytes: u64,
+    /// Number of rotated files kept
+    keep: u32,
+    /// Bytes written to the current file
+    written: u64,
+}
+
+/// Path of the `n`th rotated log file
+fn rotated(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+impl RotatingFile {
+    /// Create the log file, replacing any existing one
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create(path: PathBuf, max_bytes: u64, keep: u32) -> io::Result<Self> {
+        Ok(Self {
+            file: File::create(&path)?,
+            path,
+            max_bytes,
+            keep,
+            written: 0,
+        })
+    }
+
+    /// Move the current file and the rotated ones one step back, and start a new file
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                let from = rotated(&self.path, n);
+                if from.exists() {
+                    std::fs::rename(from, rotated(&self.path, n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated(&self.path, 1))?;
+            self.file = File::create(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_bytes != 0 && self.written >= self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation() {
+        let dir = std::env::temp_dir().join(format!("waylrc-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("waylrc.log");
+        let mut log = RotatingFile::create(path.clone(), 10, 2).unwrap();
+        for line in [
+            "first line\n",
+            "second line\n",
+            "third line\n",
+            "fourth line\n",
+        ] {
+            log.write_all(line.as_bytes()).unwrap();
+        }
+        log.flush().unwrap();
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap_or_default();
+        let files = [
+            read(&path),
+            read(&rotated(&path, 1)),
+            read(&rotated(&path, 2)),
+        ];
+        let dropped = rotated(&path, 3).exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, ["fourth line\n", "third line\n", "second line\n"]);
+        assert!(!dropped, "only two rotated files are kept");
+    }
+}
@@ -1,4 +1,3 @@
-#![feature(result_option_inspect)]
 #![warn(
     clippy::pedantic,
     clippy::negative_feature_names,
@@ -22,25 +21,245 @@
     clippy::verbose_file_reads
 )]
 use core::time::Duration;
+use std::{
+    fs::OpenOptions,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use clap::Parser;
+use waylrc_core::{provider, state};
 
 pub mod arg;
+pub mod config;
+pub mod display;
+pub mod doctor;
+pub mod generate;
+pub mod hooks;
+pub mod init;
+pub mod latency;
+pub mod log;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "obs")]
+pub mod obs;
 pub mod out;
-pub mod parser;
-pub mod state;
+pub mod preview;
+pub mod profile;
+pub mod providers;
+pub mod publish;
+pub mod search;
+pub mod stats;
+pub mod storage;
+pub mod template;
+pub mod text_file;
+pub mod transform;
+pub mod translate;
+pub mod waybar;
+
+/// Track players as configured by the arguments
+fn new_state(args: &arg::Args) -> state::State {
+    let tracker = state::State::new(
+        state::Options {
+            max_sleep: Duration::from_millis(args.max_wait),
+            debounce: Duration::from_millis(args.debounce),
+            priority: args.player_priority.clone(),
+            show_when_paused: args.show_when_paused,
+            provider_grace: args
+                .parallel_providers
+                .then(|| Duration::from_millis(args.provider_grace)),
+            sync_tolerance: Duration::from_millis(args.sync_tolerance),
+            trust_player_position: args.trust_player_position,
+            render_advance: Duration::from_millis(args.render_advance),
+            min_redraw_interval: Duration::from_millis(args.min_redraw_interval),
+            audio_latency: args.audio_latency.resolve(),
+            ignore_blank_lines: args.ignore_blank_lines,
+            rescale_mismatch: args.rescale_mismatch,
+            rescale_versions: args.version_tolerance(),
+            playerctld: args.playerctld.into(),
+            switch_policy: args.switch_policy.into(),
+            position_units: args.position_unit.clone(),
+            stale_position_players: args.stale_position_players.clone(),
+            unsynced_line_duration: args
+                .unsynced_line_duration
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .filter(|d| !d.is_zero()),
+            line_transition: Duration::from_millis(args.line_transition),
+            lead_in: Duration::from_secs(args.lead_in),
+            offline: args.offline,
+            metadata_filter: state::MetadataFilter {
+                skip: args.skip_metadata.clone(),
+                only: args.only_metadata.clone(),
+            },
+            spoken_word_length: (args.spoken_word_length != 0)
+                .then(|| Duration::from_secs(args.spoken_word_length * 60)),
+            requery_interval: (args.requery_interval != 0)
+                .then(|| Duration::from_secs(args.requery_interval * 60)),
+            preferred_language: args.preferred_language(),
+        },
+        args.providers(),
+    );
+    if let Some(target) = &args.translate_to {
+        return tracker.with_translator(Box::new(translate::Http::new(
+            args.translation_service,
+            args.translation_url.clone(),
+            args.translation_api_key.clone(),
+            target.clone(),
+            Duration::from_secs(args.translation_interval),
+        )));
+    }
+    tracker
+}
+
+/// Run a subcommand other than the Waybar module
+fn run_command(args: &arg::Args, command: &arg::Command) -> Result<(), Box<dyn std::error::Error>> {
+    match *command {
+        arg::Command::Doctor => std::process::exit(i32::from(!doctor::run(args))),
+        arg::Command::Preview {
+            ref file,
+            speed,
+            start,
+        } => preview::run(args, file, speed, start),
+        arg::Command::SearchLyrics {
+            ref phrase,
+            ref dir,
+        } => search::run(args, phrase, dir),
+        arg::Command::Pin {
+            ref audio,
+            ref lyrics,
+        } => {
+            let root = arg::pins().ok_or("cannot find the state directory")?;
+            for path in provider::Pinned::new(root).pin(audio, lyrics)? {
+                println!("pinned as {}", path.display());
+            }
+            Ok(())
+        }
+        arg::Command::Cache {
+            action: arg::CacheAction::Clear,
+        } => {
+            match storage::clear_cache()? {
+                Some(dir) => println!("removed {}", dir.display()),
+                None => println!("the cache is empty"),
+            }
+            Ok(())
+        }
+        arg::Command::PublishLrc {
+            ref file,
+            ref song,
+            dry_run,
+        } => publish::run(file, song, dry_run),
+        arg::Command::InitConfig {
+            ref path,
+            bar,
+            force,
+        } => {
+            let path = path
+                .clone()
+                .or_else(config::default_path)
+                .ok_or("cannot find the configuration directory")?;
+            init::run(&path, bar, force)
+        }
+        arg::Command::Generate { target } => {
+            Ok(generate::run(target, &mut std::io::stdout().lock())?)
+        }
+        arg::Command::Stats => stats::run(),
+        arg::Command::Profile { ref action } => profile::run(action),
+    }
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = arg::Args::parse();
+    let args = arg::Args::parse_from(config::args()?);
+    let warnings = args.validate().unwrap_or_else(|e| e.exit());
+    if let Some(command) = &args.command {
+        return run_command(&args, command);
+    }
     args.init_tracing_subscriber();
+    for warning in warnings {
+        tracing::warn!("{}", warning);
+    }
 
-    let mut main_state = state::State::new(Duration::from_millis(args.max_wait));
+    let mut main_state = new_state(&args);
+    main_state.warm_up();
+    let mut display_options = args.display_options();
+    let toggle_offline = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, Arc::clone(&toggle_offline))?;
+    let mut notifier = args
+        .waybar_signal
+        .map(|signal| waybar::Notifier::new(signal, args.waybar_pid))
+        .transpose()?;
+    let mut metadata_output = args
+        .metadata_module
+        .as_ref()
+        .map(|path| OpenOptions::new().create(true).append(true).open(path))
+        .transpose()?;
+    let mut printer = out::Printer::default();
+    let mut terminal = out::Terminal::new(args.output);
+    #[cfg(feature = "obs")]
+    let mut obs = (args.output == out::Output::Obs).then(|| {
+        obs::Obs::new(
+            args.obs_url.clone(),
+            args.obs_password.clone(),
+            args.obs_source.clone(),
+        )
+    });
+    let mut text_file = args.write_file.clone().map(text_file::TextFile::new);
+    let mut hooks = Some(args.hooks()).filter(|hooks| !hooks.is_empty());
+    let mut recorder = args
+        .stats
+        .then(stats::path)
+        .flatten()
+        .map(stats::Recorder::new);
+    #[cfg(feature = "metrics")]
+    let mut metrics_file = args.metrics_file.clone().map(metrics::File::new);
     loop {
-        let (output, sleep) = main_state.update()?;
-        if let Some(output) = output {
-            output.print()?;
+        #[cfg(feature = "metrics")]
+        if let Some(file) = &mut metrics_file {
+            file.write();
+        }
+        if toggle_offline.swap(false, Ordering::Relaxed) {
+            display_options.offline = !display_options.offline;
+            main_state.set_offline(display_options.offline);
+        }
+        let (snapshot, sleep) = main_state.update();
+        if let Some(snapshot) = snapshot {
+            #[cfg(feature = "obs")]
+            if let Some(obs) = &mut obs {
+                obs.show(&display::plain_text(
+                    &snapshot,
+                    &display_options,
+                    &args.version_separator,
+                ));
+            }
+            if args.output == out::Output::Waybar {
+                printer.print(&display::lyrics_module(&snapshot, &display_options))?;
+            } else {
+                let text =
+                    display::plain_text(&snapshot, &display_options, &args.version_separator);
+                terminal.show(&text, &mut std::io::stdout().lock())?;
+            }
+            if let Some(notifier) = &mut notifier {
+                notifier.notify(&snapshot);
+            }
+            if let Some(file) = &mut text_file {
+                file.write(display::plain_text(&snapshot, &display_options, "\n"));
+            }
+            if let Some(hooks) = &mut hooks {
+                hooks.run(&snapshot);
+            }
+            if let Some(recorder) = &mut recorder {
+                recorder.record(&snapshot);
+            }
+            if let Some(file) = &mut metadata_output {
+                if let Err(e) =
+                    display::metadata_module(&snapshot, display_options.escape).format(file)
+                {
+                    tracing::warn!("Failed to write metadata module: {}", e);
+                }
+            }
         }
-        tracing::info!("sleeping for {:?}", sleep);
+        tracing::trace!("sleeping for {:?}", sleep);
         std::thread::sleep(sleep);
     }
 }
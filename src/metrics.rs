@@ -0,0 +1,41 @@
+//! Output of the internal counters to a file
+//!
+//! The file is replaced atomically, so that a collector never reads it half written.
+
+use core::time::Duration;
+use std::{path::PathBuf, time::Instant};
+
+use waylrc_core::metrics;
+
+use crate::storage;
+
+/// How often the counters are written
+const INTERVAL: Duration = Duration::from_secs(15);
+
+/// A file the counters are periodically written to
+pub struct File {
+    path: PathBuf,
+    /// When the counters were last written
+    written: Option<Instant>,
+}
+
+impl File {
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            written: None,
+        }
+    }
+
+    /// Write the counters if they were not written in the last [`INTERVAL`]
+    pub fn write(&mut self) {
+        if self.written.is_some_and(|at| at.elapsed() < INTERVAL) {
+            return;
+        }
+        self.written = Some(Instant::now());
+        if let Err(e) = storage::replace(&self.path, metrics::render()) {
+            tracing::warn!("Failed to write metrics to {}: {}", self.path.display(), e);
+        }
+    }
+}
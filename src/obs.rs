@@ -0,0 +1,187 @@
+//! Lyrics shown in OBS Studio through obs-websocket, as live captions or as the text of a source
+//!
+//! Version 5 of the obs-websocket protocol is spoken over a plain WebSocket. OBS not running is
+//! not an error: the connection is attempted again every few seconds.
+
+use core::time::Duration;
+use std::{
+    net::{TcpStream, ToSocketAddrs as _},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tungstenite::{Message, WebSocket};
+
+/// How long to wait before connecting again after failing to
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How long to wait for OBS to accept the connection or to answer
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+type Socket = WebSocket<TcpStream>;
+
+/// Answer to the authentication challenge of obs-websocket
+fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = STANDARD.encode(Sha256::digest(format!("{password}{salt}")));
+    STANDARD.encode(Sha256::digest(format!("{secret}{challenge}")))
+}
+
+/// Request showing a line, as the text of `source` or as a caption if there is none
+fn request(source: Option<&str>, text: &str) -> Value {
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string();
+    let (kind, data) = match source {
+        Some(source) => (
+            "SetInputSettings",
+            json!({ "inputName": source, "inputSettings": { "text": text } }),
+        ),
+        None => ("SendStreamCaption", json!({ "captionText": text })),
+    };
+    json!({
+        "op": 6,
+        "d": { "requestType": kind, "requestId": id, "requestData": data },
+    })
+}
+
+/// Read the next message with an opcode, skipping pings
+fn receive(socket: &mut Socket, op: u64) -> Result<Value, Box<dyn std::error::Error>> {
+    loop {
+        if let Message::Text(text) = socket.read()? {
+            let message: Value = serde_json::from_str(&text)?;
+            if message["op"] == op {
+                return Ok(message["d"].clone());
+            }
+        }
+    }
+}
+
+/// A connection to OBS, opened when there is something to show
+pub struct Obs {
+    url: String,
+    password: Option<String>,
+    /// Text source updated with the lines, instead of sending captions
+    source: Option<String>,
+    socket: Option<Socket>,
+    /// When connecting last failed
+    failed: Option<Instant>,
+    /// What is currently shown
+    shown: Option<String>,
+}
+
+impl Obs {
+    #[must_use]
+    pub fn new(url: String, password: Option<String>, source: Option<String>) -> Self {
+        Self {
+            url,
+            password,
+            source,
+            socket: None,
+            failed: None,
+            shown: None,
+        }
+    }
+
+    /// Connect and identify to obs-websocket, giving up on an unresponsive address after
+    /// [`TIMEOUT`] rather than blocking the display
+    fn connect(&self) -> Result<Socket, Box<dyn std::error::Error>> {
+        let url = url::Url::parse(&self.url)?;
+        let host = url.host_str().ok_or("the URL has no host")?;
+        let port = url.port_or_known_default().ok_or("the URL has no port")?;
+        let address = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or("the host has no address")?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let (mut socket, _) =
+            tungstenite::client(self.url.as_str(), stream).map_err(|e| e.to_string())?;
+        let hello = receive(&mut socket, 0)?;
+        let mut identify = json!({ "rpcVersion": 1, "eventSubscriptions": 0 });
+        if let (Some(challenge), Some(salt)) = (
+            hello["authentication"]["challenge"].as_str(),
+            hello["authentication"]["salt"].as_str(),
+        ) {
+            let password = self
+                .password
+                .as_deref()
+                .ok_or("OBS asks for a password, give it with --obs-password")?;
+            identify["authentication"] = authentication(password, salt, challenge).into();
+        }
+        socket.send(Message::Text(json!({ "op": 1, "d": identify }).to_string()))?;
+        receive(&mut socket, 2)?;
+        tracing::info!("connected to OBS at {}", self.url);
+        Ok(socket)
+    }
+
+    /// Send the request showing a line and check that OBS accepted it
+    fn send(socket: &mut Socket, request: &Value) -> Result<(), Box<dyn std::error::Error>> {
+        socket.send(Message::Text(request.to_string()))?;
+        let response = receive(socket, 7)?;
+        if response["requestStatus"]["result"] != true {
+            let comment = response["requestStatus"]["comment"].as_str();
+            return Err(comment.unwrap_or("request failed").into());
+        }
+        Ok(())
+    }
+
+    /// Show a line in OBS if it changed, connecting first if needed
+    pub fn show(&mut self, text: &str) {
+        if self.shown.as_deref() == Some(text) {
+            return;
+        }
+        if self.socket.is_none() {
+            if self.failed.is_some_and(|at| at.elapsed() < RECONNECT_DELAY) {
+                return;
+            }
+            match self.connect() {
+                Ok(socket) => self.socket = Some(socket),
+                Err(e) => {
+                    tracing::warn!("Failed to connect to OBS at {}: {}", self.url, e);
+                    self.failed = Some(Instant::now());
+                    return;
+                }
+            }
+        }
+        let Some(socket) = &mut self.socket else {
+            return;
+        };
+        match Self::send(socket, &request(self.source.as_deref(), text)) {
+            Ok(()) => self.shown = Some(text.to_owned()),
+            Err(e) => {
+                tracing::warn!("Failed to show the lyrics in OBS: {}", e);
+                self.socket = None;
+                self.shown = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let caption = request(None, "la la");
+        assert_eq!(caption["d"]["requestType"], "SendStreamCaption");
+        assert_eq!(caption["d"]["requestData"]["captionText"], "la la");
+        let text = request(Some("Lyrics"), "la la");
+        assert_eq!(text["d"]["requestData"]["inputName"], "Lyrics");
+        assert_eq!(text["d"]["requestData"]["inputSettings"]["text"], "la la");
+
+        // Example of the obs-websocket protocol documentation
+        assert_eq!(
+            authentication(
+                "supersecretpassword",
+                "lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI=",
+                "+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY="
+            ),
+            "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4="
+        );
+    }
+}
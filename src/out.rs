@@ -1,6 +1,75 @@
-use serde::Serialize;
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
 use std::io::{self, Write};
 
+/// Where the lyrics are shown
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Output {
+    /// JSON lines read by a Waybar custom module
+    #[default]
+    Waybar,
+    /// The title of the terminal waylrc runs in, e.g. for the status line of a multiplexer
+    TerminalTitle,
+    /// Desktop notifications sent through the terminal with OSC 777
+    Osc777,
+    /// One line of text per change, e.g. for a polybar script with `tail = true`
+    Plain,
+    /// The protocol of yambar script modules, setting a `lyrics` string tag
+    Yambar,
+    /// Live captions, or the text source given with `--obs-source`, in OBS Studio through
+    /// obs-websocket
+    #[cfg(feature = "obs")]
+    Obs,
+}
+
+/// Lyrics shown as lines of text or through terminal escape sequences
+#[derive(Debug)]
+pub struct Terminal {
+    output: Output,
+    /// What is currently shown
+    shown: Option<String>,
+}
+
+impl Terminal {
+    #[must_use]
+    pub fn new(output: Output) -> Self {
+        Self {
+            output,
+            shown: None,
+        }
+    }
+
+    /// Write the line or escape sequence showing the text, if it changed.
+    ///
+    /// Control characters are replaced so that lyrics cannot end the line or sequence early.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the given writer fails.
+    pub fn show<T: Write>(&mut self, text: &str, f: &mut T) -> io::Result<()> {
+        let text: String = text
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        if self.shown.as_ref() == Some(&text) {
+            return Ok(());
+        }
+        match self.output {
+            Output::Waybar => return Ok(()),
+            #[cfg(feature = "obs")]
+            Output::Obs => return Ok(()),
+            Output::TerminalTitle => write!(f, "\x1b]2;{text}\x07")?,
+            Output::Osc777 if text.trim().is_empty() => {}
+            Output::Osc777 => write!(f, "\x1b]777;notify;waylrc;{text}\x07")?,
+            Output::Plain => writeln!(f, "{text}")?,
+            Output::Yambar => write!(f, "lyrics|string|{text}\n\n")?,
+        }
+        f.flush()?;
+        self.shown = Some(text);
+        Ok(())
+    }
+}
+
 /// A structure that can be serialized to JSON and parsed by Waybar.
 #[derive(Serialize, Debug, Default)]
 pub struct WaybarCustomModule {
@@ -10,12 +79,39 @@ pub struct WaybarCustomModule {
     alt: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tooltip: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    class: Option<String>,
+    #[serde(skip_serializing_if = "Classes::is_empty")]
+    class: Classes,
     #[serde(skip_serializing_if = "Option::is_none")]
     percentage: Option<usize>,
 }
 
+/// A text field of a module
+#[derive(Clone, Copy, Debug)]
+pub enum Field {
+    Text,
+    Alt,
+    Tooltip,
+}
+
+/// CSS classes of a module, written as a single string if there is only one
+#[derive(Debug, Default)]
+struct Classes(Vec<String>);
+
+impl Classes {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Serialize for Classes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.as_slice() {
+            [class] => serializer.serialize_str(class),
+            classes => serializer.collect_seq(classes),
+        }
+    }
+}
+
 impl WaybarCustomModule {
     /// Create a new module with the given contents.
     pub fn new(
@@ -29,10 +125,36 @@ impl WaybarCustomModule {
             text: text.map(html_escape::encode_text).map(String::from),
             alt: alt.map(html_escape::encode_text).map(String::from),
             tooltip: tooltip.map(html_escape::encode_text).map(String::from),
-            class: class.map(html_escape::encode_text).map(String::from),
+            class: Classes(
+                class
+                    .map(html_escape::encode_text)
+                    .map(String::from)
+                    .into_iter()
+                    .collect(),
+            ),
             percentage,
         }
     }
+    /// Set a field to Pango markup, or to text escaped by Waybar, which is written without
+    /// escaping.
+    ///
+    /// Any text from lyrics or metadata in the markup must already be escaped.
+    pub fn set_markup(&mut self, field: Field, markup: &str) {
+        let field = match field {
+            Field::Text => &mut self.text,
+            Field::Alt => &mut self.alt,
+            Field::Tooltip => &mut self.tooltip,
+        };
+        *field = Some(markup.to_owned());
+    }
+
+    /// Add a CSS class to the module.
+    pub fn add_class(&mut self, class: &str) {
+        self.class
+            .0
+            .push(html_escape::encode_text(class).into_owned());
+    }
+
     /// Format the module as JSON and write it to the given writer.
     ///
     /// # Errors
@@ -58,6 +180,44 @@ impl WaybarCustomModule {
     }
 }
 
+/// Prints modules to stdout, skipping those identical to the previous one.
+///
+/// The buffers are reused, so that printing does not allocate once they are large enough.
+#[derive(Debug, Default)]
+pub struct Printer {
+    buffer: Vec<u8>,
+    /// The last module printed
+    printed: Vec<u8>,
+}
+
+impl Printer {
+    /// Print the module to stdout if it differs from the previous one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to stdout fails.
+    pub fn print(&mut self, module: &WaybarCustomModule) -> io::Result<()> {
+        self.print_to(module, &mut io::stdout().lock())
+    }
+
+    /// Write the module to the given writer if it differs from the previous one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the given writer fails.
+    pub fn print_to<T: Write>(&mut self, module: &WaybarCustomModule, f: &mut T) -> io::Result<()> {
+        self.buffer.clear();
+        module.format(&mut self.buffer)?;
+        if self.buffer == self.printed {
+            return Ok(());
+        }
+        f.write_all(&self.buffer)?;
+        f.flush()?;
+        core::mem::swap(&mut self.buffer, &mut self.printed);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -68,7 +228,7 @@ pub mod tests {
             text: Some("text".to_owned()),
             alt: Some("alt".to_owned()),
             tooltip: Some("tooltip".to_owned()),
-            class: Some("class".to_owned()),
+            class: Classes(vec!["class".to_owned()]),
             percentage: Some(50),
         };
         let mut buf = Vec::new();
@@ -85,11 +245,79 @@ pub mod tests {
             text: None,
             alt: None,
             tooltip: None,
-            class: None,
+            class: Classes::default(),
             percentage: None,
         };
         let mut buf = Vec::new();
         module.format(&mut buf).unwrap();
         assert_eq!(String::from_utf8(buf).unwrap(), "{}\n");
     }
+
+    #[test]
+    fn test_markup() {
+        let mut module = WaybarCustomModule::new(Some("<b>"), None, None, None, None);
+        let mut buf = Vec::new();
+        module.format(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"text\":\"&lt;b&gt;\"}\n"
+        );
+        module.set_markup(Field::Text, "<b>bold</b>");
+        let mut buf = Vec::new();
+        module.format(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"text\":\"<b>bold</b>\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_printer() {
+        let mut printer = Printer::default();
+        let mut buf = Vec::new();
+        for text in ["a", "a", "b", "a"] {
+            let module = WaybarCustomModule::new(Some(text), None, None, None, None);
+            printer.print_to(&module, &mut buf).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"text\":\"a\"}\n{\"text\":\"b\"}\n{\"text\":\"a\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_terminal() {
+        let mut title = Terminal::new(Output::TerminalTitle);
+        let mut buf = Vec::new();
+        title.show("Hello\x07world", &mut buf).unwrap();
+        title.show("Hello\x07world", &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "\x1b]2;Hello world\x07");
+        let mut notify = Terminal::new(Output::Osc777);
+        let mut buf = Vec::new();
+        notify.show("", &mut buf).unwrap();
+        notify.show("Hello", &mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "\x1b]777;notify;waylrc;Hello\x07"
+        );
+        let mut yambar = Terminal::new(Output::Yambar);
+        let mut buf = Vec::new();
+        yambar.show("Hello\nworld", &mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "lyrics|string|Hello world\n\n"
+        );
+    }
+
+    #[test]
+    fn test_classes() {
+        let mut module = WaybarCustomModule::new(None, None, None, Some("paused"), None);
+        module.add_class("line-new");
+        let mut buf = Vec::new();
+        module.format(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"class\":[\"paused\",\"line-new\"]}\n"
+        );
+    }
 }
@@ -0,0 +1,91 @@
+//! Playback of a lyric file without a media player
+//!
+//! The lyrics are rendered through the same module as during normal operation, so that lyric
+//! authors can check their timing and users can see how a file will be shown.
+
+use core::time::Duration;
+use std::{path::Path, time::Instant};
+
+use waylrc_core::{
+    parser::{Lrc, TimeTag},
+    state::{PlaybackClock, Snapshot, DEFAULT_UNSYNCED_LINE_DURATION},
+};
+
+use crate::{arg::Args, display};
+
+/// Describe what is displayed at a position of the lyrics
+fn snapshot_at(lrc: &Lrc, position: TimeTag) -> Snapshot {
+    Snapshot {
+        lines: lrc
+            .lines_at(position)
+            .map(|(kind, line)| (kind, line.text.clone()))
+            .collect(),
+        position: position.0,
+        line_start: lrc.current_timetag(position).map(|t| t.0),
+        line_end: lrc.next_timetag(position).map(|t| t.0),
+        line_index: lrc
+            .tracks()
+            .first()
+            .and_then(|track| track.index_at(position))
+            .map(|i| i + 1),
+        line_total: lrc.line_count(),
+        length: lrc.length(),
+        title: lrc.tag("ti").map(str::to_owned),
+        artists: lrc.tag("ar").map(str::to_owned).into_iter().collect(),
+        ..Snapshot::default()
+    }
+}
+
+/// Play back the lyrics of a file from `start`, `speed` times faster than real time, printing
+/// the module at each line until the last one.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed, or the module cannot be printed.
+pub fn run(
+    args: &Args,
+    file: &Path,
+    speed: f64,
+    start: TimeTag,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let lrc = Lrc::from_file(&file)?;
+    let lrc = if args.ignore_blank_lines {
+        lrc.without_blank_lines()
+    } else {
+        lrc
+    };
+    let lrc = lrc.spread(
+        args.unsynced_line_duration
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            .filter(|d| !d.is_zero())
+            .unwrap_or(DEFAULT_UNSYNCED_LINE_DURATION),
+    );
+    let options = args.display_options();
+    let clock = PlaybackClock::new(start.0, speed, Instant::now());
+    loop {
+        let position = TimeTag(clock.now(Instant::now()));
+        let snapshot = snapshot_at(&lrc, position);
+        display::lyrics_module(&snapshot, &options).print()?;
+        let Some(next) = snapshot.line_end else {
+            return Ok(());
+        };
+        std::thread::sleep(next.saturating_sub(position.0).div_f64(speed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots() {
+        let lrc = Lrc::from_str("[ti:Song]\n[00:01.00]One\n[00:03.00]Two").unwrap();
+        let at = |secs| snapshot_at(&lrc, TimeTag(Duration::from_secs(secs)));
+        assert!(at(0).lines.is_empty());
+        assert_eq!(at(0).line_end, Some(Duration::from_secs(1)));
+        assert_eq!(at(2).lines[0].1, "One");
+        assert_eq!(at(2).line_index, Some(1));
+        assert_eq!(at(4).line_end, None);
+        assert_eq!(at(4).title.as_deref(), Some("Song"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Export and import of the lyrics pinned to audio files, so that they survive moving to another
+//! machine and can be shared
+//!
+//! A profile is a JSON object mapping the content keys of audio files to the lyrics pinned to
+//! them. As the keys are derived from the content of the audio files, they stay valid wherever
+//! the files are copied.
+
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::arg::{self, ProfileAction};
+
+/// Version of the profile format written by this version of waylrc
+const VERSION: u32 = 1;
+
+/// Everything a user has chosen that is not in the configuration file
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub version: u32,
+    /// Pinned lyrics, by content key of the audio file
+    pub pins: BTreeMap<String, String>,
+}
+
+/// Whether a key could have been produced by `content_keys`, so that it is safe as a file name
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+impl Profile {
+    /// Collect the lyrics pinned in a directory
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory exists but cannot be read.
+    pub fn export(pins: &Path) -> io::Result<Self> {
+        let mut profile = Self {
+            version: VERSION,
+            pins: BTreeMap::new(),
+        };
+        let entries = match fs::read_dir(pins) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(profile),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "lrc") {
+                continue;
+            }
+            let Some(key) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            profile
+                .pins
+                .insert(key.to_owned(), fs::read_to_string(&path)?);
+        }
+        Ok(profile)
+    }
+
+    /// Pin the lyrics of the profile in a directory, replacing those pinned to the same files,
+    /// and return how many were pinned
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the profile comes from a newer version of waylrc, has a key that is
+    /// not a content key, or if the lyrics cannot be stored.
+    pub fn import(&self, pins: &Path) -> io::Result<usize> {
+        if self.version > VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("profile version {} is not supported", self.version),
+            ));
+        }
+        if let Some(key) = self.pins.keys().find(|key| !valid_key(key)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid pin {key:?}"),
+            ));
+        }
+        fs::create_dir_all(pins)?;
+        for (key, lyrics) in &self.pins {
+            fs::write(pins.join(key).with_extension("lrc"), lyrics)?;
+        }
+        Ok(self.pins.len())
+    }
+}
+
+/// Export or import a profile of the lyrics pinned in the state directory
+///
+/// # Errors
+///
+/// Returns an error if the state directory cannot be found, or the profile cannot be written or
+/// imported.
+pub fn run(action: &ProfileAction) -> Result<(), Box<dyn std::error::Error>> {
+    let root = arg::pins().ok_or("cannot find the state directory")?;
+    match action {
+        ProfileAction::Export { file: Some(file) } => {
+            serde_json::to_writer_pretty(fs::File::create(file)?, &Profile::export(&root)?)?;
+        }
+        ProfileAction::Export { file: None } => {
+            serde_json::to_writer_pretty(io::stdout().lock(), &Profile::export(&root)?)?;
+        }
+        ProfileAction::Import { file } => {
+            let profile: Profile = serde_json::from_reader(fs::File::open(file)?)?;
+            println!("pinned {} lyric files", profile.import(&root)?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("waylrc-profile-{}", std::process::id()));
+        let source = dir.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("file-1a2b.lrc"), "[00:01.00]hello\n").unwrap();
+        fs::write(source.join("notes.txt"), "not a pin").unwrap();
+
+        let profile = Profile::export(&source).unwrap();
+        let json = serde_json::to_string(&profile).unwrap();
+        let imported: Profile = serde_json::from_str(&json).unwrap();
+        let target = dir.join("target");
+        assert_eq!(imported.import(&target).unwrap(), 1);
+        let copied = fs::read_to_string(target.join("file-1a2b.lrc"));
+
+        let escaping = Profile {
+            version: VERSION,
+            pins: BTreeMap::from([("../evil".to_owned(), String::new())]),
+        };
+        let rejected = escaping.import(&target).is_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(copied.unwrap(), "[00:01.00]hello\n");
+        assert!(rejected, "keys must not escape the pin directory");
+    }
+}
@@ -0,0 +1,186 @@
+//! Configuration of the chain of lyric providers
+//!
+//! Each provider is given as its name followed by its options, e.g.
+//! `directory:path=/music/lyrics,timeout=500`, on the command line or as a
+//! `[providers.directory]` section of the configuration file. A comma or backslash within a value
+//! is escaped with a backslash.
+
+use core::{str::FromStr, time::Duration};
+use std::path::PathBuf;
+
+use waylrc_core::provider::{self, Provider};
+
+/// A lyric provider and its options
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// Lyrics pinned to audio files with `waylrc pin`, stored in `dir` or the state directory
+    Pinned { dir: Option<PathBuf> },
+    /// `.lrc` and `.srt` files next to the audio file
+    Sidecar,
+    /// Lyrics in the tags of the audio file
+    Embedded,
+    /// A directory of lyric files
+    Directory { path: PathBuf },
+}
+
+/// A provider in the chain, as configured
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spec {
+    pub kind: Kind,
+    /// Give up on the provider after this long, overriding `--io-timeout`
+    pub timeout: Option<Duration>,
+}
+
+/// Split options at commas, except for those escaped with a backslash
+fn split_options(options: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut option = String::new();
+    let mut chars = options.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => option.push(
+                chars
+                    .next_if(|next| matches!(next, ',' | '\\'))
+                    .unwrap_or(c),
+            ),
+            ',' => split.push(core::mem::take(&mut option)),
+            c => option.push(c),
+        }
+    }
+    split.push(option);
+    split
+}
+
+/// Escape the commas and backslashes of an option value, so that it stays a single option
+#[must_use]
+pub fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,")
+}
+
+impl FromStr for Spec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, options) = s.split_once(':').unwrap_or((s, ""));
+        let mut options: Vec<(String, String)> = split_options(options)
+            .into_iter()
+            .filter(|option| !option.trim().is_empty())
+            .map(|option| {
+                option
+                    .split_once('=')
+                    .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+                    .ok_or_else(|| format!("expected key=value, got {option:?}"))
+            })
+            .collect::<Result<_, _>>()?;
+        let mut take = |key: &str| {
+            options
+                .iter()
+                .position(|(k, _)| *k == key)
+                .map(|i| options.remove(i).1)
+        };
+        let timeout = take("timeout")
+            .map(|ms| ms.parse().map(Duration::from_millis))
+            .transpose()
+            .map_err(|e| format!("invalid timeout: {e}"))?;
+        let kind = match name.trim() {
+            "pinned" => Kind::Pinned {
+                dir: take("dir").map(PathBuf::from),
+            },
+            "sidecar" => Kind::Sidecar,
+            "embedded" => Kind::Embedded,
+            "directory" => Kind::Directory {
+                path: take("path")
+                    .map(PathBuf::from)
+                    .ok_or("the directory provider needs a path")?,
+            },
+            name => return Err(format!("unknown provider {name:?}")),
+        };
+        if let Some((key, _)) = options.first() {
+            return Err(format!("unknown option {key:?} for provider {name}"));
+        }
+        Ok(Self { kind, timeout })
+    }
+}
+
+impl Spec {
+    /// The chain used when no provider is configured
+    #[must_use]
+    pub fn defaults() -> Vec<Self> {
+        [Kind::Pinned { dir: None }, Kind::Sidecar, Kind::Embedded]
+            .into_iter()
+            .map(|kind| Self {
+                kind,
+                timeout: None,
+            })
+            .collect()
+    }
+
+    /// Build the provider, preferring lyrics in `language` if set, and giving it `io_timeout` to
+    /// answer unless the provider has its own timeout. Lyric directories accept files written for
+    /// another version of the song within `version_tolerance`.
+    #[must_use]
+    pub fn build(
+        &self,
+        language: Option<&str>,
+        io_timeout: Option<Duration>,
+        version_tolerance: Option<f64>,
+    ) -> Option<Box<dyn Provider>> {
+        let language = language.map(str::to_owned);
+        let provider: Box<dyn Provider> = match &self.kind {
+            Kind::Pinned { dir } => Box::new(provider::Pinned::new(
+                dir.clone().or_else(crate::arg::pins)?,
+            )),
+            Kind::Sidecar => Box::new(provider::Sidecar::new(language)),
+            Kind::Embedded => Box::new(provider::Embedded),
+            Kind::Directory { path } => Box::new(
+                provider::Directory::new(path.clone())
+                    .with_language(language)
+                    .with_version_tolerance(version_tolerance),
+            ),
+        };
+        Some(match self.timeout.or(io_timeout) {
+            Some(timeout) if !timeout.is_zero() => {
+                Box::new(provider::WithTimeout::new(provider, timeout))
+            }
+            _ => provider,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specs() {
+        assert_eq!(
+            "directory:path=/music/lyrics, timeout=500".parse(),
+            Ok(Spec {
+                kind: Kind::Directory {
+                    path: PathBuf::from("/music/lyrics")
+                },
+                timeout: Some(Duration::from_millis(500)),
+            })
+        );
+        assert_eq!(
+            "sidecar".parse(),
+            Ok(Spec {
+                kind: Kind::Sidecar,
+                timeout: None
+            })
+        );
+        assert_eq!(
+            format!("directory:path={}", escape(r"/lyrics\a,b")).parse(),
+            Ok(Spec {
+                kind: Kind::Directory {
+                    path: PathBuf::from(r"/lyrics\a,b")
+                },
+                timeout: None,
+            }),
+            "an escaped comma is part of the value"
+        );
+        assert!("directory".parse::<Spec>().is_err());
+        assert!("sidecar:path=/x".parse::<Spec>().is_err());
+        assert!("genius".parse::<Spec>().is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! Publishing of lyrics to LRCLIB, so that others find the lyrics synchronised locally
+//!
+//! LRCLIB asks publishers to solve a proof-of-work challenge: a nonce whose SHA-256 hash,
+//! appended to a given prefix, is below a given target. Requests are sent with `curl`, and only
+//! when `waylrc publish-lrc` is run.
+
+use core::{fmt::Write as _, time::Duration};
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use sha2::{Digest, Sha256};
+use waylrc_core::parser::Lrc;
+
+/// Base URL of the LRCLIB API
+const API: &str = "https://lrclib.net/api";
+
+/// The song lyrics are published for, overriding the ID tags of the lyric file
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct Song {
+    #[clap(long)]
+    pub title: Option<String>,
+    #[clap(long)]
+    pub artist: Option<String>,
+    #[clap(long)]
+    pub album: Option<String>,
+    /// Length of the song in seconds
+    #[clap(long, value_name = "SECONDS")]
+    pub duration: Option<u64>,
+}
+
+/// Build the body of a publish request, failing if the song is not fully known
+fn request(lrc: &Lrc, song: &Song) -> Result<serde_json::Value, String> {
+    let field = |given: &Option<String>, tag: &str, name: &str| {
+        given
+            .clone()
+            .or_else(|| lrc.tag(tag).map(str::to_owned))
+            .ok_or(format!("the {name} is unknown, give it with --{name}"))
+    };
+    let duration = song
+        .duration
+        .map(Duration::from_secs)
+        .or_else(|| lrc.length())
+        .ok_or("the duration is unknown, give it with --duration")?;
+    let Some(original) = lrc.tracks().first() else {
+        return Err("the lyrics are empty".to_owned());
+    };
+    let plain: Vec<_> = original.lines.iter().map(|l| l.text.as_str()).collect();
+    let synced = lrc.is_synced().then(|| {
+        let mut synced = String::new();
+        for line in &original.lines {
+            let _ = writeln!(synced, "[{}]{}", line.time, line.text);
+        }
+        synced
+    });
+    Ok(serde_json::json!({
+        "trackName": field(&song.title, "ti", "title")?,
+        "artistName": field(&song.artist, "ar", "artist")?,
+        "albumName": field(&song.album, "al", "album")?,
+        "duration": duration.as_secs(),
+        "plainLyrics": plain.join("\n"),
+        "syncedLyrics": synced,
+    }))
+}
+
+/// Find the nonce solving a challenge
+fn solve(prefix: &str, target: &str) -> Result<u64, String> {
+    let target = (0..target.len())
+        .step_by(2)
+        .map(|i| {
+            target
+                .get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or("invalid challenge target")?;
+    (0..u64::MAX)
+        .find(|nonce| Sha256::digest(format!("{prefix}{nonce}")).as_slice() < target.as_slice())
+        .ok_or_else(|| "no solution to the challenge".to_owned())
+}
+
+/// Send a POST request to the API and return the body of the response
+fn post(endpoint: &str, body: &str, token: Option<&str>) -> Result<String, String> {
+    let mut command = Command::new("curl");
+    command.args(["--silent", "--show-error", "--fail", "--max-time", "30"]);
+    command.args(["--header", "Content-Type: application/json"]);
+    command.args([
+        "--header",
+        concat!("User-Agent: waylrc/", env!("CARGO_PKG_VERSION")),
+    ]);
+    if let Some(token) = token {
+        command.args(["--header", &format!("X-Publish-Token: {token}")]);
+    }
+    let mut child = command
+        .args(["--data-binary", "@-", &format!("{API}/{endpoint}")])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run curl: {e}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(body.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Publish a lyric file to LRCLIB, or only print the request if `dry_run`
+///
+/// # Errors
+///
+/// Returns an error if the lyrics cannot be read, the song is not fully known, or LRCLIB refuses
+/// the lyrics.
+pub fn run(file: &Path, song: &Song, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let lrc = Lrc::from_file(&file)?;
+    let body = request(&lrc, song)?;
+    if dry_run {
+        println!("{}", serde_json::to_string_pretty(&body)?);
+        return Ok(());
+    }
+    let challenge: serde_json::Value = serde_json::from_str(&post("request-challenge", "", None)?)?;
+    let (Some(prefix), Some(target)) = (challenge["prefix"].as_str(), challenge["target"].as_str())
+    else {
+        return Err("unexpected challenge".into());
+    };
+    println!("solving the publishing challenge, this may take a minute");
+    let nonce = solve(prefix, target)?;
+    post(
+        "publish",
+        &body.to_string(),
+        Some(&format!("{prefix}:{nonce}")),
+    )?;
+    println!("published {} to LRCLIB", file.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publishing() {
+        let lrc = Lrc::from_str("[ti:Song]\n[ar:Artist]\n[00:01.00]One\n[00:03.50]Two\n").unwrap();
+        assert!(request(&lrc, &Song::default()).is_err());
+        let song = Song {
+            album: Some("Album".to_owned()),
+            duration: Some(200),
+            ..Song::default()
+        };
+        let body = request(&lrc, &song).unwrap();
+        assert_eq!(body["trackName"], "Song");
+        assert_eq!(body["duration"], 200);
+        assert_eq!(body["plainLyrics"], "One\nTwo");
+        assert_eq!(body["syncedLyrics"], "[00:01.00]One\n[00:03.50]Two\n");
+
+        // A target this easy is met by about one nonce in 16
+        let nonce = solve("prefix", "1000000000000000").unwrap();
+        let hash = Sha256::digest(format!("prefix{nonce}"));
+        assert!(hash[0] < 0x10, "the hash must be below the target");
+    }
+}
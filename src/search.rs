@@ -0,0 +1,142 @@
+//! Search of the lyrics on disk for a phrase, to find a song by a line remembered from it
+//!
+//! The lyric directories and the pinned lyrics are searched, as well as any directory given on
+//! the command line. The phrase is matched case-insensitively within each line.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use waylrc_core::{
+    parser::{Lrc, TimeTag},
+    provider::LYRIC_EXTENSIONS,
+};
+
+use crate::{arg::Args, providers};
+
+/// How deep directories are searched, so that symbolic link loops end
+const MAX_DEPTH: usize = 8;
+
+/// Directories of lyric files known from the configuration
+fn configured_dirs(args: &Args) -> Vec<PathBuf> {
+    let specs = if args.provider.is_empty() {
+        providers::Spec::defaults()
+    } else {
+        args.provider.clone()
+    };
+    specs
+        .into_iter()
+        .filter_map(|spec| match spec.kind {
+            providers::Kind::Directory { path } => Some(path),
+            providers::Kind::Pinned { dir } => dir.or_else(crate::arg::pins),
+            providers::Kind::Sidecar | providers::Kind::Embedded => None,
+        })
+        .chain(args.lyrics_dir.iter().cloned())
+        .collect()
+}
+
+/// Collect the lyric files under a directory into `files`
+fn lyric_files(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        tracing::debug!("cannot list {}", dir.display());
+        return;
+    };
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        if path.is_dir() {
+            if depth < MAX_DEPTH {
+                lyric_files(&path, depth + 1, files);
+            }
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| LYRIC_EXTENSIONS.iter().any(|l| e.eq_ignore_ascii_case(l)))
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Lines of the lyrics containing the phrase, ignoring case
+fn matching_lines<'a>(lrc: &'a Lrc, phrase: &str) -> Vec<(TimeTag, &'a str)> {
+    let phrase = phrase.to_lowercase();
+    lrc.iter_lines()
+        .filter(|(_, text)| text.to_lowercase().contains(&phrase))
+        .collect()
+}
+
+/// Name of the song of a lyric file, from its tags or else its path
+fn song_name(lrc: &Lrc, path: &Path) -> String {
+    match (lrc.tag("ar"), lrc.tag("ti")) {
+        (Some(artist), Some(title)) => format!("{artist} - {title} ({})", path.display()),
+        (None, Some(title)) => format!("{title} ({})", path.display()),
+        _ => path.display().to_string(),
+    }
+}
+
+/// Print the lyric files containing the phrase, with the matching lines and their times
+///
+/// # Errors
+///
+/// Returns an error if no lyrics contain the phrase.
+pub fn run(args: &Args, phrase: &str, dirs: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for dir in dirs.iter().cloned().chain(configured_dirs(args)) {
+        lyric_files(&dir, 0, &mut files);
+    }
+    files.sort();
+    files.dedup();
+    let mut found = false;
+    for path in files {
+        let lrc = match Lrc::from_file(&path) {
+            Ok(lrc) => lrc,
+            Err(e) => {
+                tracing::debug!("skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let lines = matching_lines(&lrc, phrase);
+        if lines.is_empty() {
+            continue;
+        }
+        if found {
+            println!();
+        }
+        found = true;
+        println!("{}", song_name(&lrc, &path));
+        for (time, text) in lines {
+            println!("  [{time}] {text}");
+        }
+    }
+    if !found {
+        return Err(format!("no lyrics contain {phrase:?}").into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search() {
+        let lrc = Lrc::from_str(
+            "[ti:Song]\n[00:01.00]Hello World\n[00:02.50]goodbye\n[00:03.00]hello again\n",
+        )
+        .unwrap();
+        let lines = matching_lines(&lrc, "HELLO");
+        assert_eq!(
+            lines,
+            [
+                (TimeTag(core::time::Duration::from_secs(1)), "Hello World"),
+                (TimeTag(core::time::Duration::from_secs(3)), "hello again"),
+            ]
+        );
+        assert!(matching_lines(&lrc, "la la").is_empty());
+        assert_eq!(
+            song_name(&lrc, Path::new("a.lrc")),
+            "Song (a.lrc)",
+            "the title is shown without an artist tag"
+        );
+    }
+}
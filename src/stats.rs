@@ -0,0 +1,350 @@
+//! Statistics of what was played, kept in the state directory and shown by `waylrc stats`
+//!
+//! The module counts plays, lyric sources and lines shown as it runs, and adds them to
+//! `waylrc/stats.json` every minute. Nothing is recorded unless `--stats` is given.
+
+use core::time::Duration;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use waylrc_core::{metrics, state::Snapshot};
+
+use crate::storage;
+
+/// How often the statistics are saved
+const INTERVAL: Duration = Duration::from_mins(1);
+/// How many songs are listed as most played
+const TOP: usize = 10;
+/// How many lyric lines are kept, the most shown ones, so that the file does not grow with every
+/// song played
+const MAX_LINES: usize = 1000;
+
+/// Requests to a provider, by outcome
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Requests {
+    pub found: u64,
+    pub not_found: u64,
+    pub failed: u64,
+}
+
+/// Everything counted across runs
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Stats {
+    /// Times each song was played, by `artists - title`
+    pub plays: BTreeMap<String, u64>,
+    /// Songs played, by provider of their lyrics, or `none`
+    pub sources: BTreeMap<String, u64>,
+    /// Requests to each provider
+    pub providers: BTreeMap<String, Requests>,
+    /// Times each lyric line was shown
+    pub lines: BTreeMap<String, u64>,
+}
+
+/// Location of the statistics file
+#[must_use]
+pub fn path() -> Option<PathBuf> {
+    Some(storage::state_dir()?.join("stats.json"))
+}
+
+impl Stats {
+    /// Read the statistics from a file, which may not exist yet
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or parsed.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Forget the least shown lyric lines beyond [`MAX_LINES`]
+    fn trim_lines(&mut self) {
+        if self.lines.len() <= MAX_LINES {
+            return;
+        }
+        let mut lines: Vec<_> = core::mem::take(&mut self.lines).into_iter().collect();
+        lines.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        lines.truncate(MAX_LINES);
+        self.lines = lines.into_iter().collect();
+    }
+
+    /// Summary of the statistics for `waylrc stats`
+    #[must_use]
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let total: u64 = self.plays.values().sum();
+        let _ = writeln!(out, "{total} plays of {} songs", self.plays.len());
+        let mut songs: Vec<_> = self.plays.iter().collect();
+        songs.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        if !songs.is_empty() {
+            let _ = writeln!(out, "\nMost played:");
+        }
+        for (song, plays) in songs.into_iter().take(TOP) {
+            let _ = writeln!(out, "{plays:>6}  {song}");
+        }
+        let sourced: u64 = self.sources.values().sum();
+        if sourced != 0 {
+            let _ = writeln!(out, "\nLyrics from:");
+        }
+        for (source, plays) in &self.sources {
+            let _ = writeln!(out, "{:>5.1}%  {source}", percentage(*plays, sourced));
+        }
+        if !self.providers.is_empty() {
+            let _ = writeln!(out, "\nProvider hit rates:");
+        }
+        for (provider, requests) in &self.providers {
+            let asked = requests.found + requests.not_found + requests.failed;
+            let _ = writeln!(
+                out,
+                "{:>5.1}%  {provider} ({} of {asked} requests, {} failed)",
+                percentage(requests.found, asked),
+                requests.found,
+                requests.failed
+            );
+        }
+        if let Some((line, shown)) = self
+            .lines
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+        {
+            let _ = writeln!(out, "\nMost played lyric, shown {shown} times:\n  {line}");
+        }
+        out
+    }
+}
+
+/// Share of a total in percent
+#[allow(
+    clippy::cast_precision_loss,
+    reason = "counts are far below the precision of a float"
+)]
+fn percentage(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 * 100.0 / total as f64
+}
+
+/// Song a snapshot is of, as shown in the statistics
+fn song_name(snapshot: &Snapshot) -> Option<String> {
+    let title = snapshot.title.as_deref()?;
+    if snapshot.artists.is_empty() {
+        return Some(title.to_owned());
+    }
+    Some(format!("{} - {title}", snapshot.artists.join(", ")))
+}
+
+/// Counts what the module shows, adding it to the statistics file periodically
+pub struct Recorder {
+    path: PathBuf,
+    /// Counted since the statistics were last saved
+    pending: Stats,
+    /// Song of the last snapshot showing one, how many times it was played in a row, and whether
+    /// the source of its lyrics was counted
+    song: Option<(String, u32, bool)>,
+    /// Number of the last line counted
+    line: Option<usize>,
+    /// Provider requests already counted
+    requests: BTreeMap<&'static str, metrics::Outcomes>,
+    /// When the statistics were last saved
+    saved: Instant,
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            pending: Stats::default(),
+            song: None,
+            line: None,
+            requests: BTreeMap::new(),
+            saved: Instant::now(),
+        }
+    }
+
+    /// Count what a snapshot shows, then save the statistics if they were not saved in the last
+    /// [`INTERVAL`]
+    pub fn record(&mut self, snapshot: &Snapshot) {
+        self.count(snapshot);
+        if self.saved.elapsed() >= INTERVAL {
+            self.saved = Instant::now();
+            if let Err(e) = self.save() {
+                tracing::warn!(
+                    "Failed to save statistics to {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    fn count(&mut self, snapshot: &Snapshot) {
+        // Snapshots without a song, e.g. while paused, do not end the play
+        let Some(name) = song_name(snapshot) else {
+            return;
+        };
+        if self
+            .song
+            .as_ref()
+            .is_none_or(|(song, play, _)| *song != name || *play != snapshot.plays)
+        {
+            *self.pending.plays.entry(name.clone()).or_default() += 1;
+            self.song = Some((name, snapshot.plays, false));
+            self.line = None;
+        }
+        if let Some((_, _, sourced)) = &mut self.song {
+            // The source is known once the lookup is over, successful or not
+            let looked_up =
+                snapshot.source.is_some() || snapshot.diagnostic.is_some() || snapshot.instrumental;
+            if !*sourced && looked_up {
+                *sourced = true;
+                let source = snapshot
+                    .source
+                    .as_deref()
+                    .unwrap_or(if snapshot.instrumental {
+                        "instrumental"
+                    } else {
+                        "none"
+                    });
+                *self.pending.sources.entry(source.to_owned()).or_default() += 1;
+            }
+        }
+        if snapshot.paused || snapshot.line_index == self.line {
+            return;
+        }
+        self.line = snapshot.line_index;
+        if let Some((_, text)) = snapshot.lines.first().filter(|(_, t)| !t.trim().is_empty()) {
+            *self.pending.lines.entry(text.clone()).or_default() += 1;
+        }
+    }
+
+    /// Add what was counted since the last save to the statistics file
+    fn save(&mut self) -> io::Result<()> {
+        let outcomes = metrics::provider_outcomes();
+        for (provider, now) in &outcomes {
+            let before = self.requests.get(provider).copied().unwrap_or_default();
+            let requests = self
+                .pending
+                .providers
+                .entry((*provider).to_owned())
+                .or_default();
+            requests.found += now.success - before.success;
+            requests.not_found += now.no_lyrics - before.no_lyrics;
+            requests.failed += now.failure - before.failure;
+        }
+        self.requests = outcomes;
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Modules running on several bars add to the same file, one at a time
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path.with_extension("lock"))?;
+        lock.lock()?;
+        let mut stats = Stats::load(&self.path)?;
+        let pending = core::mem::take(&mut self.pending);
+        for (maps, pending) in [
+            (&mut stats.plays, pending.plays),
+            (&mut stats.sources, pending.sources),
+            (&mut stats.lines, pending.lines),
+        ] {
+            for (key, count) in pending {
+                *maps.entry(key).or_default() += count;
+            }
+        }
+        for (provider, new) in pending.providers {
+            let requests = stats.providers.entry(provider).or_default();
+            requests.found += new.found;
+            requests.not_found += new.not_found;
+            requests.failed += new.failed;
+        }
+        stats.trim_lines();
+        storage::replace(&self.path, serde_json::to_string(&stats)?)
+    }
+}
+
+/// Print the statistics for `waylrc stats`
+///
+/// # Errors
+///
+/// Returns an error if the state directory cannot be found, or the statistics cannot be read.
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let path = path().ok_or("cannot find the state directory")?;
+    print!("{}", Stats::load(&path)?.report());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use waylrc_core::parser::TrackKind;
+
+    #[test]
+    fn recording() {
+        let path = std::env::temp_dir().join(format!("waylrc-stats-{}.json", std::process::id()));
+        let mut recorder = Recorder::new(path.clone());
+        let mut snapshot = Snapshot {
+            title: Some("Song".to_owned()),
+            artists: vec!["Artist".to_owned()],
+            plays: 1,
+            ..Snapshot::default()
+        };
+        recorder.count(&snapshot);
+        snapshot.source = Some("lrclib".to_owned());
+        for (index, line) in [(1, "la"), (2, "la la"), (3, "la")] {
+            snapshot.line_index = Some(index);
+            snapshot.lines = vec![(TrackKind::Original, line.to_owned())];
+            recorder.count(&snapshot);
+            recorder.count(&snapshot);
+        }
+        recorder.save().unwrap();
+        // Paused, then resumed
+        recorder.count(&Snapshot::default());
+        recorder.count(&snapshot);
+        // Played again on repeat
+        snapshot.plays = 2;
+        recorder.count(&snapshot);
+        recorder.save().unwrap();
+        let stats = Stats::load(&path);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("lock")).unwrap();
+
+        let stats = stats.unwrap();
+        assert_eq!(stats.plays["Artist - Song"], 2);
+        assert_eq!(stats.sources["lrclib"], 2);
+        assert_eq!(stats.lines["la"], 3);
+        assert!(
+            stats
+                .report()
+                .contains("Most played lyric, shown 3 times:\n  la\n"),
+            "the most shown line is reported"
+        );
+
+        let mut many = Stats {
+            lines: (0..=MAX_LINES as u64)
+                .map(|shown| (shown.to_string(), shown))
+                .collect(),
+            ..Stats::default()
+        };
+        many.trim_lines();
+        assert_eq!(many.lines.len(), MAX_LINES);
+        assert!(
+            !many.lines.contains_key("0"),
+            "the least shown line is forgotten"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Location of the files waylrc keeps on disk
+//!
+//! Files are stored in a `waylrc` directory under the XDG base directories, falling back to the
+//! locations the XDG specification gives when the variables are unset.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
+    time::SystemTime,
+};
+
+/// Get the `waylrc` directory under an XDG base directory, given its variable and its default
+/// path relative to the home directory
+fn xdg_dir(var: &str, default: &str) -> Option<PathBuf> {
+    let base = std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(default)))?;
+    Some(base.join("waylrc"))
+}
+
+/// Directory of files that can be deleted at any time, such as translations
+#[must_use]
+pub fn cache_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// Directory of the configuration file
+#[must_use]
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Directory of data that should persist across restarts but is not configuration
+#[must_use]
+pub fn state_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// Number of temporary files created by this process, to name the next one
+static TEMP_FILES: AtomicU32 = AtomicU32::new(0);
+
+/// Replace the contents of a file atomically, so that it is never read half written.
+///
+/// The contents are written to a new file of a unique name in the same directory, which is then
+/// renamed over the file. The temporary file is created anew rather than opened, so that a link
+/// left in its place is not followed.
+///
+/// # Errors
+///
+/// Returns an error if the temporary file cannot be created or written, or cannot replace the
+/// file.
+pub fn replace(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let name = path.file_name().ok_or(io::ErrorKind::InvalidInput)?;
+    let (temp, mut file) = loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp = dir.map_or_else(|| PathBuf::from(&temp_name), |dir| dir.join(&temp_name));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+        {
+            Ok(file) => break (temp, file),
+            // Left over by a process of the same id that did not finish writing
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    };
+    let written = file
+        .write_all(contents.as_ref())
+        .and_then(|()| file.sync_all())
+        .and_then(|()| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Delete the oldest files of a directory until their total size is at most `max_bytes`.
+///
+/// # Errors
+///
+/// Returns an error if the directory cannot be listed or a file cannot be deleted.
+pub fn enforce_quota(dir: &Path, max_bytes: u64) -> io::Result<()> {
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((modified, metadata.len(), entry.path()));
+        }
+    }
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort();
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        tracing::info!("removing {} to stay within the cache quota", path.display());
+        fs::remove_file(&path)?;
+        total -= len;
+    }
+    Ok(())
+}
+
+/// Delete everything in the cache directory, returning the directory if there was one
+///
+/// # Errors
+///
+/// Returns an error if the cache directory exists but cannot be deleted.
+pub fn clear_cache() -> io::Result<Option<PathBuf>> {
+    let Some(dir) = cache_dir() else {
+        return Ok(None);
+    };
+    match fs::remove_dir_all(&dir) {
+        Ok(()) => Ok(Some(dir)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota() {
+        let dir = std::env::temp_dir().join(format!("waylrc-quota-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, age) in [("old", 20), ("new", 0), ("mid", 10)] {
+            let path = dir.join(name);
+            fs::write(&path, [0; 100]).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - core::time::Duration::from_secs(age))
+                .unwrap();
+        }
+
+        enforce_quota(&dir, 250).unwrap();
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        left.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, ["mid", "new"]);
+    }
+
+    #[test]
+    fn replaces() {
+        let dir = std::env::temp_dir().join(format!("waylrc-replace-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lyrics.txt");
+        let target = dir.join("target");
+        fs::write(&target, "untouched").unwrap();
+        // A link at the name of the next temporary file is not followed, the one after is used
+        let link = format!(
+            ".lyrics.txt.{}.{}.tmp",
+            std::process::id(),
+            TEMP_FILES.load(Ordering::Relaxed)
+        );
+        std::os::unix::fs::symlink(&target, dir.join(&link)).unwrap();
+        replace(&path, "one").unwrap();
+        replace(&path, "two").unwrap();
+        let read = fs::read_to_string(&path).unwrap();
+        let untouched = fs::read_to_string(&target).unwrap();
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        left.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(read, "two");
+        assert_eq!(untouched, "untouched");
+        assert_eq!(left, [link.as_str(), "lyrics.txt", "target"]);
+    }
+}
@@ -0,0 +1,55 @@
+//! Substitution of `{placeholder}`s in user-provided format strings
+
+/// Replace each `{name}` in the template by the value `lookup` gives for it.
+///
+/// Placeholders `lookup` does not know are kept as they are, and `{{` and `}}` stand for literal
+/// braces.
+pub fn render(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        let (before, after) = rest.split_at(i);
+        result.push_str(before);
+        if let Some(after) = after.strip_prefix("{{") {
+            result.push('{');
+            rest = after;
+        } else if let Some(after) = after.strip_prefix("}}") {
+            result.push('}');
+            rest = after;
+        } else if let Some((name, after)) = after
+            .strip_prefix('{')
+            .and_then(|a| a.split_once('}'))
+            .filter(|(name, _)| !name.contains('{'))
+        {
+            if let Some(value) = lookup(name) {
+                result.push_str(&value);
+            } else {
+                result.push('{');
+                result.push_str(name);
+                result.push('}');
+            }
+            rest = after;
+        } else {
+            // A lone brace
+            let (brace, after) = after.split_at(1);
+            result.push_str(brace);
+            rest = after;
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders() {
+        let lookup = |name: &str| (name == "a").then(|| "1".to_owned());
+        assert_eq!(render("{a}/{b}", lookup), "1/{b}");
+        assert_eq!(render("{{a}} {a", lookup), "{a} {a");
+        assert_eq!(render("} {{{a}}}", lookup), "} {1}");
+        assert_eq!(render("", lookup), "");
+    }
+}
@@ -0,0 +1,37 @@
+//! Output of the current lyrics to a plain text file, for tools that can only read files such as
+//! hyprlock labels or conky
+//!
+//! The file is replaced atomically, so that it is never read half written, and only when the
+//! lyrics change.
+
+use std::path::PathBuf;
+
+use crate::storage;
+
+/// A file holding the current lyrics
+pub struct TextFile {
+    path: PathBuf,
+    /// What was last written to the file
+    written: Option<String>,
+}
+
+impl TextFile {
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            written: None,
+        }
+    }
+
+    /// Write the lyrics to the file if they changed
+    pub fn write(&mut self, text: String) {
+        if self.written.as_ref() == Some(&text) {
+            return;
+        }
+        match storage::replace(&self.path, &text) {
+            Ok(()) => self.written = Some(text),
+            Err(e) => tracing::warn!("Failed to write lyrics to {}: {}", self.path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Post-processing of lyric lines before they are displayed
+
+use clap::ValueEnum;
+use waylrc_core::lang::Script;
+
+/// A text filter, applied after the other rules in the order given
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Convert to upper case
+    Uppercase,
+    /// Convert to lower case
+    Lowercase,
+    /// Remove punctuation at the end of the line, such as `,` or `。`
+    StripTrailingPunctuation,
+    /// Remove emoji
+    StripEmoji,
+    /// Replace runs of whitespace with a single space
+    CollapseWhitespace,
+}
+
+/// Punctuation ending a clause, which can be dropped at the end of a line.
+///
+/// Closing brackets and quotes are kept, as removing them would unbalance the line.
+const TRAILING_PUNCTUATION: &[char] = &[
+    '.', ',', ';', ':', '!', '?', '…', '。', '、', '，', '！', '？', '；', '：', '～',
+];
+
+/// Symbols of the Basic Multilingual Plane shown as emoji by default. Other symbols there, such
+/// as `♪`, `★` or arrows, are only shown as emoji when followed by U+FE0F.
+const BMP_EMOJI: &[(u32, u32)] = &[
+    (0x231A, 0x231B),
+    (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0),
+    (0x23F3, 0x23F3),
+    (0x25FD, 0x25FE),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267F, 0x267F),
+    (0x2693, 0x2693),
+    (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB),
+    (0x26BD, 0x26BE),
+    (0x26C4, 0x26C5),
+    (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4),
+    (0x26EA, 0x26EA),
+    (0x26F2, 0x26F3),
+    (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA),
+    (0x26FD, 0x26FD),
+    (0x2705, 0x2705),
+    (0x270A, 0x270B),
+    (0x2728, 0x2728),
+    (0x274C, 0x274C),
+    (0x274E, 0x274E),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27B0, 0x27B0),
+    (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C),
+    (0x2B50, 0x2B50),
+    (0x2B55, 0x2B55),
+];
+
+/// Whether a character is shown as an emoji by default, or joins or modifies emoji
+fn is_emoji(c: char) -> bool {
+    let c = u32::from(c);
+    matches!(
+        c,
+        0x1F000..=0x1FAFF | 0xFE0F | 0x200D | 0x20E3 | 0xE0020..=0xE007F
+    ) || BMP_EMOJI
+        .iter()
+        .any(|&(start, end)| (start..=end).contains(&c))
+}
+
+/// Remove emoji, including symbols followed by U+FE0F, which asks for them to be shown as emoji
+fn strip_emoji(line: &str) -> String {
+    let mut chars = line.chars().peekable();
+    let mut result = String::with_capacity(line.len());
+    while let Some(c) = chars.next() {
+        if !is_emoji(c) && chars.peek() != Some(&'\u{fe0f}') {
+            result.push(c);
+        }
+    }
+    result
+}
+
+impl Filter {
+    /// Apply the filter to a line
+    fn apply(self, line: &str) -> String {
+        match self {
+            Self::Uppercase => line.to_uppercase(),
+            Self::Lowercase => line.to_lowercase(),
+            Self::StripTrailingPunctuation => line
+                .trim_end()
+                .trim_end_matches(TRAILING_PUNCTUATION)
+                .to_owned(),
+            Self::StripEmoji => strip_emoji(line),
+            Self::CollapseWhitespace => line.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+/// Rules applied to every displayed line, each of which can be toggled
+#[derive(Clone, Debug, Default)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "each rule is an independent toggle"
+)]
+pub struct Transforms {
+    /// Remove readings in parentheses after kanji, e.g. `漢字(かんじ)` becomes `漢字`
+    pub strip_readings: bool,
+    /// Replace kanji annotated with a reading by the reading, e.g. `漢字(かんじ)` becomes `かんじ`
+    pub kana_only: bool,
+    /// Filters applied after the rules above, in order
+    pub filters: Vec<Filter>,
+}
+
+/// Whether a character is part of a word that may be annotated with a reading
+fn is_kanji(c: char) -> bool {
+    Script::of(c) == Some(Script::Han) || c == '々'
+}
+
+/// Get the reading and the length in characters of a parenthesised group of kana at the start of
+/// the text, such as `(かんじ)`.
+fn reading(chars: &[char]) -> Option<(String, usize)> {
+    let close = match chars.first()? {
+        '(' => ')',
+        '（' => '）',
+        _ => return None,
+    };
+    let end = chars.iter().position(|&c| c == close)?;
+    let reading = &chars[1..end];
+    let is_kana = |&c: &char| Script::of(c) == Some(Script::Kana) || c == ' ';
+    (!reading.is_empty() && reading.iter().all(is_kana))
+        .then(|| (reading.iter().collect(), end + 1))
+}
+
+impl Transforms {
+    /// Whether no rule is enabled
+    fn is_empty(&self) -> bool {
+        !(self.strip_readings || self.kana_only) && self.filters.is_empty()
+    }
+
+    /// Handle readings following kanji according to the enabled rules
+    fn readings(&self, text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut result: Vec<char> = Vec::with_capacity(chars.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let annotated = result.last().is_some_and(|&c| is_kanji(c));
+            if let Some((reading, len)) = annotated.then(|| reading(&chars[i..])).flatten() {
+                if self.kana_only {
+                    let start = result
+                        .iter()
+                        .rposition(|&c| !is_kanji(c))
+                        .map_or(0, |p| p + 1);
+                    result.truncate(start);
+                    result.extend(reading.chars());
+                }
+                i += len;
+                continue;
+            }
+            result.push(chars[i]);
+            i += 1;
+        }
+        result.into_iter().collect()
+    }
+
+    /// Apply the enabled rules to a line
+    #[must_use]
+    pub fn apply(&self, line: &str) -> String {
+        if self.is_empty() {
+            return line.to_owned();
+        }
+        let mut line = if self.strip_readings || self.kana_only {
+            self.readings(line)
+        } else {
+            line.to_owned()
+        };
+        for filter in &self.filters {
+            line = filter.apply(&line);
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readings() {
+        let strip = Transforms {
+            strip_readings: true,
+            ..Transforms::default()
+        };
+        assert_eq!(strip.apply("漢字(かんじ)を書（か）く"), "漢字を書く");
+        assert_eq!(strip.apply("愛 (love)"), "愛 (love)");
+        assert_eq!(strip.apply("(あ)"), "(あ)");
+
+        let kana = Transforms {
+            kana_only: true,
+            ..Transforms::default()
+        };
+        assert_eq!(kana.apply("漢字(かんじ)を書（か）く"), "かんじをかく");
+        assert_eq!(kana.apply("時々(ときどき)"), "ときどき");
+    }
+
+    #[test]
+    fn filters() {
+        let filters = |filters: &[Filter]| Transforms {
+            filters: filters.to_vec(),
+            ..Transforms::default()
+        };
+        assert_eq!(
+            filters(&[Filter::StripTrailingPunctuation]).apply("Hello (world)... "),
+            "Hello (world)"
+        );
+        assert_eq!(
+            filters(&[Filter::StripTrailingPunctuation]).apply("愛してる。"),
+            "愛してる"
+        );
+        assert_eq!(
+            filters(&[Filter::StripEmoji, Filter::CollapseWhitespace])
+                .apply("Love ❤\u{fe0f} you 👨\u{200d}👩"),
+            "Love you"
+        );
+        assert_eq!(
+            filters(&[Filter::StripEmoji]).apply("♪ ★☆ ← →⭐ 1\u{fe0f}\u{20e3}"),
+            "♪ ★☆ ← → ",
+            "symbols are only emoji when shown as such by default or asked to"
+        );
+        assert_eq!(
+            filters(&[Filter::CollapseWhitespace]).apply("  a \t b  "),
+            "a b"
+        );
+        assert_eq!(
+            filters(&[Filter::Lowercase, Filter::Uppercase]).apply("Ça va"),
+            "ÇA VA"
+        );
+    }
+}
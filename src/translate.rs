@@ -0,0 +1,316 @@
+//! Machine translation of lyrics through a web service
+//!
+//! Requests are sent with `curl`, at most one every `interval`, and their results are cached on
+//! disk so that each song is only translated once.
+
+use core::time::Duration;
+use std::{
+    fmt::Write as _,
+    fs,
+    hash::Hasher as _,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::Instant,
+};
+
+use clap::ValueEnum;
+use waylrc_core::{
+    metrics,
+    provider::{error, Fnv1a, Translator},
+};
+
+use crate::storage;
+
+/// Translation web service
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Service {
+    /// A `LibreTranslate` instance
+    Libretranslate,
+    /// The `DeepL` API
+    Deepl,
+}
+
+impl Service {
+    /// Endpoint used if none is given
+    #[must_use]
+    pub fn default_url(self) -> &'static str {
+        match self {
+            Self::Libretranslate => "https://libretranslate.com/translate",
+            Self::Deepl => "https://api-free.deepl.com/v2/translate",
+        }
+    }
+
+    /// Endpoint answering a `GET` without translating anything, next to the translation endpoint
+    fn check_url(self, url: &str) -> String {
+        let base = url.rsplit_once('/').map_or(url, |(base, _)| base);
+        match self {
+            Self::Libretranslate => format!("{base}/languages"),
+            Self::Deepl => format!("{base}/usage"),
+        }
+    }
+
+    /// Build the body of a request translating the lines
+    fn request(self, lines: &[&str], target: &str, api_key: Option<&str>) -> serde_json::Value {
+        match self {
+            Self::Libretranslate => serde_json::json!({
+                "q": lines,
+                "source": "auto",
+                "target": target,
+                "format": "text",
+                "api_key": api_key,
+            }),
+            Self::Deepl => serde_json::json!({
+                "text": lines,
+                "target_lang": target.to_uppercase(),
+            }),
+        }
+    }
+
+    /// Get the translated lines out of the body of a response
+    fn response(self, body: &str) -> Option<Vec<String>> {
+        let body: serde_json::Value = serde_json::from_str(body).ok()?;
+        let texts: Vec<_> = match self {
+            Self::Libretranslate => body["translatedText"].as_array()?.iter().collect(),
+            Self::Deepl => body["translations"]
+                .as_array()?
+                .iter()
+                .map(|t| &t["text"])
+                .collect(),
+        };
+        texts
+            .into_iter()
+            .map(|text| text.as_str().map(str::to_owned))
+            .collect()
+    }
+}
+
+/// Translator sending lyrics to a web service
+#[derive(Debug)]
+pub struct Http {
+    pub service: Service,
+    pub url: String,
+    pub api_key: Option<String>,
+    /// Language to translate to (e.g. `en`)
+    pub target: String,
+    /// Minimum time between two requests
+    pub interval: Duration,
+    /// When the last request was sent
+    last_request: Mutex<Option<Instant>>,
+}
+
+/// Quote a value for a configuration file of curl
+fn curl_quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Directory translations are cached in
+fn cache_dir() -> Option<PathBuf> {
+    Some(storage::cache_dir()?.join("translations"))
+}
+
+/// Maximum total size of cached translations
+const CACHE_QUOTA: u64 = 16 * 1024 * 1024;
+
+impl Http {
+    #[must_use]
+    pub fn new(
+        service: Service,
+        url: Option<String>,
+        api_key: Option<String>,
+        target: String,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            url: url.unwrap_or_else(|| service.default_url().to_owned()),
+            service,
+            api_key,
+            target,
+            interval,
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Cache file of the translation of the lines
+    fn cache_file(&self, lines: &[&str]) -> Option<PathBuf> {
+        let mut hasher = Fnv1a::default();
+        for line in [self.name(), self.target.as_str()]
+            .into_iter()
+            .chain(lines.iter().copied())
+        {
+            hasher.write(line.as_bytes());
+            hasher.write(b"\n");
+        }
+        Some(cache_dir()?.join(format!("{:016x}.json", hasher.finish())))
+    }
+
+    /// Send a request, unless the previous one was less than `interval` ago
+    fn send(&self, lines: &[&str]) -> Result<Vec<String>, error::Resolve> {
+        {
+            let mut last_request = self
+                .last_request
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if last_request.is_some_and(|last| last.elapsed() < self.interval) {
+                return Err(self.error("rate limited, skipping translation".into()));
+            }
+            *last_request = Some(Instant::now());
+        }
+        self.post(lines)
+    }
+
+    /// Send a request translating the lines
+    fn post(&self, lines: &[&str]) -> Result<Vec<String>, error::Resolve> {
+        let body = self
+            .service
+            .request(lines, &self.target, self.api_key.as_deref());
+        let response = self.curl(&[
+            ("header", "Content-Type: application/json".to_owned()),
+            ("data-binary", body.to_string()),
+            ("url", self.url.clone()),
+        ])?;
+        self.service
+            .response(&response)
+            .ok_or_else(|| self.error("unexpected response".into()))
+    }
+
+    /// Run curl with options given as a configuration file on its standard input, so that the API
+    /// key does not show in the list of processes, and return the body of the response
+    fn curl(&self, options: &[(&str, String)]) -> Result<String, error::Resolve> {
+        let authorization = match (self.service, &self.api_key) {
+            (Service::Deepl, Some(key)) => {
+                Some(("header", format!("Authorization: DeepL-Auth-Key {key}")))
+            }
+            _ => None,
+        };
+        let mut config = String::new();
+        for (name, value) in options.iter().cloned().chain(authorization) {
+            let _ = writeln!(config, "{name} = {}", curl_quote(&value));
+        }
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--max-time", "10"])
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(error::Resolve::IoError)?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(config.as_bytes())
+                .map_err(error::Resolve::IoError)?;
+        }
+        let output = child.wait_with_output().map_err(error::Resolve::IoError)?;
+        if !output.status.success() {
+            return Err(self.error(String::from_utf8_lossy(&output.stderr).trim().into()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn error(&self, source: Box<dyn std::error::Error + Send + Sync>) -> error::Resolve {
+        error::Resolve::ProviderError {
+            provider: self.name(),
+            source,
+        }
+    }
+}
+
+impl Translator for Http {
+    fn name(&self) -> &'static str {
+        match self.service {
+            Service::Libretranslate => "libretranslate",
+            Service::Deepl => "deepl",
+        }
+    }
+
+    fn translate(&self, lines: &[&str]) -> Result<Vec<String>, error::Resolve> {
+        let cache_file = self.cache_file(lines);
+        if let Some(cached) = cache_file
+            .as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .and_then(|cached| serde_json::from_str::<Vec<String>>(&cached).ok())
+        {
+            tracing::debug!("using cached translation");
+            metrics::CACHE_HITS.inc();
+            return Ok(cached);
+        }
+        metrics::CACHE_MISSES.inc();
+        let translated = self.send(lines)?;
+        if let Some(file) = cache_file {
+            let written = file
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::write(&file, serde_json::json!(translated).to_string()));
+            if let Err(e) = written {
+                tracing::warn!("Failed to cache translation in {}: {}", file.display(), e);
+            } else if let Some(Err(e)) = file
+                .parent()
+                .map(|dir| storage::enforce_quota(dir, CACHE_QUOTA))
+            {
+                tracing::warn!("Failed to clean the translation cache: {}", e);
+            }
+        }
+        Ok(translated)
+    }
+
+    /// Query the usage of the API key with `DeepL`, or the supported languages with
+    /// `LibreTranslate`, which fails if the service is unreachable or refuses the API key. Nothing
+    /// is translated, so the check is not counted against the quota or the rate limit.
+    fn check(&self) -> Result<(), error::Resolve> {
+        self.curl(&[("url", self.service.check_url(&self.url))])
+            .map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses() {
+        assert_eq!(
+            Service::Libretranslate.response(r#"{"translatedText":["Dream","Love"]}"#),
+            Some(vec!["Dream".to_owned(), "Love".to_owned()])
+        );
+        assert_eq!(
+            Service::Deepl
+                .response(r#"{"translations":[{"detected_source_language":"JA","text":"Dream"}]}"#),
+            Some(vec!["Dream".to_owned()])
+        );
+        assert_eq!(
+            Service::Deepl.response(r#"{"message":"Quota exceeded"}"#),
+            None
+        );
+        assert_eq!(
+            Service::Deepl.request(&["夢"], "en", Some("key")),
+            serde_json::json!({"text": ["夢"], "target_lang": "EN"})
+        );
+        assert_eq!(
+            Service::Deepl.check_url(Service::Deepl.default_url()),
+            "https://api-free.deepl.com/v2/usage"
+        );
+        assert_eq!(
+            Service::Libretranslate.check_url("https://lt.example.org/translate"),
+            "https://lt.example.org/languages"
+        );
+        assert_eq!(
+            curl_quote(r#"{"q":["say \"hi\"\n"]}"#),
+            r#""{\"q\":[\"say \\\"hi\\\"\\n\"]}""#
+        );
+    }
+}
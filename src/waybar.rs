@@ -0,0 +1,124 @@
+//! Signalling Waybar when the track changes
+//!
+//! Waybar refreshes a module configured with `"signal": N` when it receives `SIGRTMIN+N`, so
+//! companion modules such as album art can be updated together with the lyrics.
+
+use std::fs;
+
+use waylrc_core::state::Snapshot;
+
+/// Sends a real-time signal to Waybar whenever the track or the source of its lyrics changes
+pub struct Notifier {
+    /// Offset of the signal from `SIGRTMIN`
+    signal: i32,
+    /// Process of Waybar, found by name if unset
+    pid: Option<i32>,
+    /// Title, artists and source of the lyrics of the last snapshot
+    last: Option<(Option<String>, Vec<String>, Option<String>)>,
+}
+
+/// Find the processes running Waybar
+fn find_waybar() -> Vec<i32> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .filter(|pid: &i32| {
+            fs::read_to_string(format!("/proc/{pid}/comm"))
+                .is_ok_and(|comm| comm.trim() == "waybar")
+        })
+        .collect()
+}
+
+impl Notifier {
+    /// # Errors
+    ///
+    /// Returns an error if the process is not positive, as `kill` would then signal a whole
+    /// process group or every process.
+    pub fn new(signal: i32, pid: Option<i32>) -> Result<Self, String> {
+        if let Some(pid) = pid.filter(|pid| *pid <= 0) {
+            return Err(format!("cannot signal Waybar as process {pid}"));
+        }
+        Ok(Self {
+            signal,
+            pid,
+            last: None,
+        })
+    }
+
+    /// Whether the snapshot is of another track, or has lyrics from another source, than the
+    /// previous one
+    fn changed(&mut self, snapshot: &Snapshot) -> bool {
+        if snapshot.title.is_none() && snapshot.artists.is_empty() {
+            // The display is blank between tracks, or when nothing plays: Waybar is signalled
+            // once the next track is known
+            return false;
+        }
+        let current = (
+            snapshot.title.clone(),
+            snapshot.artists.clone(),
+            snapshot.source.clone(),
+        );
+        if self.last.as_ref() == Some(&current) {
+            return false;
+        }
+        // Nothing has changed for the first snapshot, as Waybar refreshes modules on startup
+        self.last.replace(current).is_some()
+    }
+
+    /// Signal Waybar if the track or the source of its lyrics changed
+    pub fn notify(&mut self, snapshot: &Snapshot) {
+        if !self.changed(snapshot) {
+            return;
+        }
+        let pids = self.pid.map_or_else(find_waybar, |pid| vec![pid]);
+        if pids.is_empty() {
+            tracing::warn!("Failed to find Waybar to signal");
+        }
+        let signal = libc::SIGRTMIN() + self.signal;
+        for pid in pids {
+            tracing::debug!("sending signal {} to {}", signal, pid);
+            // SAFETY: kill has no memory safety requirements
+            if unsafe { libc::kill(pid, signal) } != 0 {
+                tracing::warn!(
+                    "Failed to signal Waybar ({}): {}",
+                    pid,
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes() {
+        assert!(Notifier::new(8, Some(0)).is_err());
+        assert!(Notifier::new(8, Some(-1)).is_err());
+        let mut notifier = Notifier::new(8, None).unwrap();
+        let snapshot = |title: &str, source: Option<&str>| Snapshot {
+            title: Some(title.to_owned()),
+            source: source.map(str::to_owned),
+            ..Snapshot::default()
+        };
+        assert!(!notifier.changed(&snapshot("One", None)));
+        assert!(!notifier.changed(&snapshot("One", None)));
+        assert!(notifier.changed(&snapshot("One", Some("sidecar"))));
+        assert!(
+            !notifier.changed(&Snapshot::default()),
+            "blank between tracks"
+        );
+        assert!(notifier.changed(&snapshot("Two", Some("sidecar"))));
+        assert!(!notifier.changed(&snapshot("Two", Some("sidecar"))));
+        assert!(!notifier.changed(&Snapshot::default()));
+        assert!(
+            !notifier.changed(&snapshot("Two", Some("sidecar"))),
+            "paused and resumed"
+        );
+    }
+}
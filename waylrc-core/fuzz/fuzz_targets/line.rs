@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use waylrc_core::parser::Line;
+
+fuzz_target!(|data: &str| {
+    if let Ok(line) = data.parse::<Line>() {
+        let _ = line.time.to_string();
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use waylrc_core::parser::Lrc;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(lrc) = Lrc::from_reader(data) {
+        let _ = Lrc::from_str(&lrc.to_lrc_string());
+    }
+});
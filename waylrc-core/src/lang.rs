@@ -0,0 +1,126 @@
+//! Lightweight script detection for lyric text.
+//!
+//! This is not a real language detector: it only looks at the Unicode blocks of the letters in
+//! a text, which is enough to tell apart the tracks of a typical multilingual LRC file.
+
+/// A writing system
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Thai,
+    Hangul,
+    /// Japanese hiragana and katakana
+    Kana,
+    /// Chinese characters, also used in Japanese
+    Han,
+    Other,
+}
+
+impl Script {
+    /// Get the script of a character, or `None` if it is not a letter.
+    #[must_use]
+    pub fn of(c: char) -> Option<Self> {
+        if !c.is_alphabetic() {
+            return None;
+        }
+        Some(match u32::from(c) {
+            0x41..=0x5A | 0x61..=0x7A | 0xC0..=0x24F | 0x1E00..=0x1EFF => Self::Latin,
+            0x370..=0x3FF | 0x1F00..=0x1FFF => Self::Greek,
+            0x400..=0x52F => Self::Cyrillic,
+            0x590..=0x5FF => Self::Hebrew,
+            0x600..=0x6FF | 0x750..=0x77F => Self::Arabic,
+            0xE00..=0xE7F => Self::Thai,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Self::Hangul,
+            0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => Self::Kana,
+            0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FFFF => Self::Han,
+            _ => Self::Other,
+        })
+    }
+}
+
+/// Get the script most letters of the texts are written in.
+///
+/// Returns `None` if the texts contain no letters.
+pub fn dominant_script<'a>(texts: impl IntoIterator<Item = &'a str>) -> Option<Script> {
+    // Kept in order of first appearance so that ties are broken deterministically
+    let mut counts: Vec<(Script, usize)> = Vec::new();
+    for script in texts
+        .into_iter()
+        .flat_map(str::chars)
+        .filter_map(Script::of)
+    {
+        match counts.iter_mut().find(|(s, _)| *s == script) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((script, 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|&(_, count)| count)
+        .map(|(script, _)| script)
+}
+
+/// Get the scripts a language (e.g. `zh`) is written in, from its ISO 639-1 code.
+///
+/// Languages not listed are assumed to be written in the Latin alphabet.
+#[must_use]
+pub fn scripts_of(language: &str) -> &'static [Script] {
+    match language.to_ascii_lowercase().as_str() {
+        "zh" | "yue" => &[Script::Han],
+        "ja" => &[Script::Kana, Script::Han],
+        "ko" => &[Script::Hangul],
+        "ru" | "uk" | "be" | "bg" | "sr" | "mk" | "kk" | "mn" => &[Script::Cyrillic],
+        "el" => &[Script::Greek],
+        "he" | "yi" => &[Script::Hebrew],
+        "ar" | "fa" | "ur" => &[Script::Arabic],
+        "th" => &[Script::Thai],
+        _ => &[Script::Latin],
+    }
+}
+
+/// Get the language of a POSIX locale (e.g. `zh` for `zh_CN.UTF-8`).
+///
+/// Returns `None` for the `C` and `POSIX` locales, which do not name a language.
+#[must_use]
+pub fn language_of_locale(locale: &str) -> Option<&str> {
+    let language = locale.split(['_', '.', '@']).next()?;
+    if language.is_empty() || language == "C" || language == "POSIX" {
+        return None;
+    }
+    Some(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts() {
+        assert_eq!(dominant_script(["Hello, world!"]), Some(Script::Latin));
+        assert_eq!(dominant_script(["Привет", "мир"]), Some(Script::Cyrillic));
+        assert_eq!(
+            dominant_script(["ひらがなカタカナ漢字"]),
+            Some(Script::Kana)
+        );
+        assert_eq!(
+            dominant_script(["我爱你 (I love you)"]),
+            Some(Script::Latin)
+        );
+        assert_eq!(dominant_script(["사랑해"]), Some(Script::Hangul));
+        assert_eq!(dominant_script(["123 ... !"]), None);
+    }
+
+    #[test]
+    fn locales() {
+        assert_eq!(language_of_locale("zh_CN.UTF-8"), Some("zh"));
+        assert_eq!(language_of_locale("de"), Some("de"));
+        assert_eq!(language_of_locale("C.UTF-8"), None);
+        assert_eq!(scripts_of("JA"), [Script::Kana, Script::Han]);
+        assert_eq!(scripts_of("fr"), [Script::Latin]);
+    }
+}
@@ -0,0 +1,36 @@
+//! Reusable core of waylrc.
+//!
+//! This crate contains the LRC parser, the lyric providers and the MPRIS player tracking used by
+//! the waylrc Waybar module, independent of how the lyrics are eventually displayed.
+#![warn(
+    clippy::pedantic,
+    clippy::negative_feature_names,
+    clippy::redundant_feature_names,
+    clippy::wildcard_dependencies,
+    clippy::allow_attributes_without_reason,
+    clippy::clone_on_ref_ptr,
+    clippy::default_union_representation,
+    clippy::empty_structs_with_brackets,
+    clippy::fn_to_numeric_cast_any,
+    clippy::format_push_string,
+    clippy::if_then_some_else_none,
+    clippy::lossy_float_literal,
+    clippy::missing_assert_message,
+    clippy::mod_module_files,
+    clippy::rest_pat_in_fully_bound_structs,
+    clippy::string_slice,
+    clippy::suspicious_xor_used_as_pow,
+    clippy::tests_outside_test_module,
+    clippy::unneeded_field_pattern,
+    clippy::verbose_file_reads
+)]
+
+pub use mpris;
+
+pub mod lang;
+pub mod metadata;
+pub mod metrics;
+pub mod parser;
+pub mod player;
+pub mod provider;
+pub mod state;
@@ -0,0 +1,143 @@
+//! Typed access to the MPRIS metadata of a track.
+//!
+//! The specification gives each entry a type, but players do not all follow it: titles are sent
+//! as lists, lengths as 32-bit or unsigned integers, artists as a single string. The accessors of
+//! [`Tags`] accept all of these, so that the rest of the crate does not have to.
+
+use core::time::Duration;
+
+use mpris::{Metadata, MetadataValue};
+
+/// Text of an entry, or its first text if it is a list
+fn text(value: &MetadataValue) -> Option<&str> {
+    match value {
+        MetadataValue::String(text) => Some(text),
+        MetadataValue::Array(values) => values.iter().find_map(MetadataValue::as_str),
+        _ => None,
+    }
+}
+
+/// Texts of an entry, a single text being a list of one
+fn texts(value: &MetadataValue) -> Vec<&str> {
+    match value {
+        MetadataValue::String(text) => vec![text.as_str()],
+        MetadataValue::Array(values) => values.iter().filter_map(MetadataValue::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Number of microseconds in an entry, whatever its integer type
+fn microseconds(value: &MetadataValue) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_i64().and_then(|micros| u64::try_from(micros).ok()))
+}
+
+/// The entries of the metadata of a track that waylrc uses
+#[derive(Clone, Copy, Debug)]
+pub struct Tags<'a>(&'a Metadata);
+
+impl<'a> Tags<'a> {
+    /// Text of an entry by MPRIS key
+    fn text(self, key: &str) -> Option<&'a str> {
+        self.0.get(key).and_then(text)
+    }
+
+    /// Based on `xesam:title`
+    #[must_use]
+    pub fn title(self) -> Option<&'a str> {
+        self.text("xesam:title")
+    }
+
+    /// Based on `xesam:artist`, without empty names
+    #[must_use]
+    pub fn artists(self) -> Option<Vec<&'a str>> {
+        let artists = texts(self.0.get("xesam:artist")?);
+        Some(artists.into_iter().filter(|a| !a.is_empty()).collect())
+    }
+
+    /// Based on `mpris:trackid`
+    #[must_use]
+    pub fn track_id(self) -> Option<&'a str> {
+        self.text("mpris:trackid")
+    }
+
+    /// Based on `mpris:length`
+    #[must_use]
+    pub fn length(self) -> Option<Duration> {
+        self.0
+            .get("mpris:length")
+            .and_then(microseconds)
+            .map(Duration::from_micros)
+    }
+
+    /// Based on `xesam:url`
+    #[must_use]
+    pub fn url(self) -> Option<&'a str> {
+        self.text("xesam:url")
+    }
+
+    /// Lyrics given by the player, based on `xesam:asText`
+    #[must_use]
+    pub fn as_text(self) -> Option<&'a str> {
+        self.text("xesam:asText")
+    }
+
+    /// Based on `xesam:genre`
+    #[must_use]
+    pub fn genres(self) -> Vec<&'a str> {
+        self.0.get("xesam:genre").map(texts).unwrap_or_default()
+    }
+}
+
+/// Typed accessors of the entries of [`Metadata`]
+pub trait MetadataExt {
+    /// The entries waylrc uses, whatever type the player sends them as
+    fn tags(&self) -> Tags<'_>;
+}
+
+impl MetadataExt for Metadata {
+    fn tags(&self) -> Tags<'_> {
+        Tags(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loose_types() {
+        let metadata = Metadata::from(std::collections::HashMap::from([
+            (
+                "xesam:title".to_owned(),
+                MetadataValue::Array(vec![MetadataValue::String("Song".to_owned())]),
+            ),
+            (
+                "xesam:artist".to_owned(),
+                MetadataValue::String("Artist".to_owned()),
+            ),
+            ("mpris:length".to_owned(), MetadataValue::I32(2_000_000)),
+            (
+                "xesam:genre".to_owned(),
+                MetadataValue::String("Podcast".to_owned()),
+            ),
+        ]));
+        let tags = metadata.tags();
+        assert_eq!(tags.title(), Some("Song"), "a list of titles");
+        assert_eq!(tags.artists(), Some(vec!["Artist"]), "a single artist");
+        assert_eq!(
+            tags.length(),
+            Some(Duration::from_secs(2)),
+            "a 32-bit length"
+        );
+        assert_eq!(tags.genres(), ["Podcast"]);
+        assert_eq!(tags.url(), None);
+
+        let negative = Metadata::from(std::collections::HashMap::from([(
+            "mpris:length".to_owned(),
+            MetadataValue::I64(-1),
+        )]));
+        assert_eq!(negative.tags().length(), None);
+    }
+}
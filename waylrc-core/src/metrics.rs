@@ -0,0 +1,171 @@
+//! Counters of what waylrc has been doing, to debug performance regressions of long-running
+//! instances.
+//!
+//! The counters are process-wide and rendered in the Prometheus text exposition format.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// A value that only goes up, or that is set if used as a gauge
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    /// Add one to the counter
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Set the value of a gauge
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Times the state was updated, i.e. the main loop woke up
+pub static WAKEUPS: Counter = Counter::new();
+/// Times lyrics were looked up for a song
+pub static LOOKUPS: Counter = Counter::new();
+/// Times another player was followed
+pub static PLAYER_SWITCHES: Counter = Counter::new();
+/// Number of players considered the last time one was selected
+pub static ACTIVE_PLAYERS: Counter = Counter::new();
+/// Lookups answered from an on-disk cache
+pub static CACHE_HITS: Counter = Counter::new();
+/// Lookups not found in an on-disk cache
+pub static CACHE_MISSES: Counter = Counter::new();
+
+/// Outcome of the requests to each provider
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Outcomes {
+    pub success: u64,
+    pub no_lyrics: u64,
+    pub failure: u64,
+}
+
+/// Outcomes of provider requests, by provider name
+static PROVIDERS: Mutex<BTreeMap<&'static str, Outcomes>> = Mutex::new(BTreeMap::new());
+
+/// Record the outcome of a provider request: lyrics found, none found, or a failure
+pub fn record_provider(provider: &'static str, found: Option<bool>) {
+    let mut providers = PROVIDERS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let outcomes = providers.entry(provider).or_default();
+    match found {
+        Some(true) => outcomes.success += 1,
+        Some(false) => outcomes.no_lyrics += 1,
+        None => outcomes.failure += 1,
+    }
+}
+
+/// Outcomes of the provider requests made so far, by provider name
+#[must_use]
+pub fn provider_outcomes() -> BTreeMap<&'static str, Outcomes> {
+    PROVIDERS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .clone()
+}
+
+/// Render all counters in the Prometheus text exposition format
+#[must_use]
+pub fn render() -> String {
+    let mut out = String::new();
+    let counters = [
+        (
+            "wakeups_total",
+            "counter",
+            "Times the main loop woke up",
+            &WAKEUPS,
+        ),
+        (
+            "lookups_total",
+            "counter",
+            "Songs whose lyrics were looked up",
+            &LOOKUPS,
+        ),
+        (
+            "player_switches_total",
+            "counter",
+            "Times another player was followed",
+            &PLAYER_SWITCHES,
+        ),
+        (
+            "active_players",
+            "gauge",
+            "Players considered when last selecting one",
+            &ACTIVE_PLAYERS,
+        ),
+        (
+            "cache_hits_total",
+            "counter",
+            "Lookups answered from the cache",
+            &CACHE_HITS,
+        ),
+        (
+            "cache_misses_total",
+            "counter",
+            "Lookups not found in the cache",
+            &CACHE_MISSES,
+        ),
+    ];
+    for (name, kind, help, counter) in counters {
+        let _ = writeln!(out, "# HELP waylrc_{name} {help}");
+        let _ = writeln!(out, "# TYPE waylrc_{name} {kind}");
+        let _ = writeln!(out, "waylrc_{name} {}", counter.get());
+    }
+    let _ = writeln!(
+        out,
+        "# HELP waylrc_provider_requests_total Provider requests by outcome"
+    );
+    let _ = writeln!(out, "# TYPE waylrc_provider_requests_total counter");
+    let providers = PROVIDERS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    for (provider, outcomes) in providers.iter() {
+        for (outcome, count) in [
+            ("success", outcomes.success),
+            ("no_lyrics", outcomes.no_lyrics),
+            ("failure", outcomes.failure),
+        ] {
+            let _ = writeln!(
+                out,
+                "waylrc_provider_requests_total{{provider=\"{provider}\",outcome=\"{outcome}\"}} {count}"
+            );
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposition() {
+        record_provider("metrics-test", Some(true));
+        record_provider("metrics-test", None);
+        record_provider("metrics-test", None);
+        let rendered = render();
+        assert!(rendered.contains("# TYPE waylrc_wakeups_total counter\n"));
+        assert!(rendered.contains("# TYPE waylrc_active_players gauge\n"));
+        assert!(rendered.contains(
+            "waylrc_provider_requests_total{provider=\"metrics-test\",outcome=\"failure\"} 2\n"
+        ));
+    }
+}
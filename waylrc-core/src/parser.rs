@@ -0,0 +1,874 @@
+//! A small parser for LRC files.
+//!
+//! # TODO
+//!
+//! Repeating tags are not currently supported. For example, following line of lyric will not be
+//! parsed correctly:
+//!
+//! ```text
+//! [00:21.10][00:45.10]Repeating lyrics (e.g. chorus)
+//! ```
+
+use core::{
+    fmt::{self, Debug, Display, Formatter},
+    str::FromStr,
+    time::Duration,
+};
+use std::{
+    io::{BufRead, BufReader, Read},
+    sync::LazyLock,
+};
+
+use itertools::Itertools;
+use regex::Regex;
+use tracing::instrument;
+
+use crate::lang;
+
+#[cfg(test)]
+#[allow(
+    clippy::needless_raw_string_hashes,
+    reason = "the lyrics of the examples are kept as they were written"
+)]
+mod tests;
+
+/// Largest lyric file parsed, in bytes, so that a runaway response cannot exhaust memory
+pub const MAX_SIZE: u64 = 1024 * 1024;
+/// Largest number of lines parsed in a lyric file
+pub const MAX_LINES: usize = 20_000;
+
+/// Lines lyric sites put in place of the lyrics of instrumental tracks, in lowercase and without
+/// surrounding brackets or notes
+const INSTRUMENTAL_MARKERS: [&str; 5] = [
+    "instrumental",
+    "纯音乐，请欣赏",
+    "純音樂，請欣賞",
+    "此歌曲为没有填词的纯音乐，请您欣赏",
+    "インストゥルメンタル",
+];
+
+/// A2 extension word time tags, e.g. `<00:01.00>`
+static WORD_TIME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<\d{2}:\d{2}\.\d{2}>\s?").unwrap());
+
+/// `LyricsX` word timings of the previous line, e.g. `[tt]<0,0><300,1>`
+static LYRICSX_WORD_TIMES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[tt\](<\d+,\d+>)*$").unwrap());
+
+/// A time offset from the start of the song.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeTag(pub Duration);
+impl AsRef<Duration> for TimeTag {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+impl From<Duration> for TimeTag {
+    fn from(d: Duration) -> Self {
+        Self(d)
+    }
+}
+impl From<TimeTag> for Duration {
+    fn from(t: TimeTag) -> Self {
+        t.0
+    }
+}
+
+/// A line of lyrics with a time tag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub time: TimeTag,
+    pub text: String,
+}
+
+/// The role of a track within an LRC file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackKind {
+    /// The lyrics as sung
+    Original,
+    /// A translation of the original lyrics
+    Translation,
+    /// The original lyrics transliterated into the Latin alphabet
+    Romanization,
+}
+
+/// A single version of the lyrics (e.g. the original or a translation).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Track {
+    pub kind: TrackKind,
+    /// Lines of the track, sorted by time
+    pub lines: Vec<Line>,
+}
+
+/// A collection of lines of lyrics.
+///
+/// Lyrics may have multiple tracks (typically the original and its translations), each of which
+/// is a list of lines sorted by time. A merged index of the time tags of all tracks allows the
+/// next line to be found in a single lookup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lrc {
+    tracks: Vec<Track>,
+    /// Sorted and deduplicated time tags of all tracks
+    index: Vec<TimeTag>,
+    /// ID tags from the header of the file (e.g. `[ti:Title]`), in order of appearance
+    tags: Vec<(String, String)>,
+    /// Whether the time tags come from the file, rather than the lyrics being plain text
+    synced: bool,
+    /// Whether the lyrics are the raw text of a file none of whose time tags could be parsed
+    recovered: bool,
+}
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Error, Debug)]
+    pub enum TimeTagFromStr {
+        #[error("invalid format: {0}")]
+        InvalidFormat(String),
+        #[error("invalid integer {0}: {1}")]
+        InvalidInteger(String, #[source] std::num::ParseIntError),
+        #[error("invalid float {0}: {1}")]
+        InvalidFloat(String, #[source] std::num::ParseFloatError),
+        #[error("time out of range: {0}")]
+        OutOfRange(String),
+    }
+
+    #[derive(Error, Debug)]
+    pub enum LineFromStr {
+        #[error("no tag present")]
+        NoTag,
+        #[error("tag is not a valid time tag: {0}")]
+        InvalidTimeTag(#[from] TimeTagFromStr),
+        #[error("empty text")]
+        EmptyText,
+        #[error("word timings of the previous line")]
+        WordTimings,
+    }
+
+    #[derive(Error, Debug)]
+    pub enum LrcFromReader {
+        #[error("failed to read lyrics: {0}")]
+        Io(#[from] std::io::Error),
+        #[error("no lyric lines found")]
+        NoLines,
+        #[error(
+            "lyrics are longer than {} bytes or {} lines",
+            super::MAX_SIZE,
+            super::MAX_LINES
+        )]
+        TooLarge,
+    }
+}
+
+impl Display for TimeTag {
+    /// Format the time tag as `mm:ss.xx`, or `mm:ss.xxx` if it has millisecond precision.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let minutes = self.0.as_secs() / 60;
+        let seconds = self.0.as_secs() % 60;
+        let millis = self.0.subsec_millis();
+        if millis.is_multiple_of(10) {
+            write!(f, "{minutes:02}:{seconds:02}.{:02}", millis / 10)
+        } else {
+            write!(f, "{minutes:02}:{seconds:02}.{millis:03}")
+        }
+    }
+}
+
+impl FromStr for TimeTag {
+    type Err = error::TimeTagFromStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // We should parse [mm:ss.xx] and [mm:ss.xxx] formats.
+        let [minutes, seconds]: [&str; 2] = s
+            .split(':')
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| error::TimeTagFromStr::InvalidFormat(s.to_owned()))?;
+        let minutes = minutes
+            .parse::<u64>()
+            .map_err(|e| error::TimeTagFromStr::InvalidInteger(minutes.to_owned(), e))?;
+        let seconds = seconds
+            .parse::<f64>()
+            .map_err(|e| error::TimeTagFromStr::InvalidFloat(seconds.to_owned(), e))?;
+        let out_of_range = || error::TimeTagFromStr::OutOfRange(s.to_owned());
+        let seconds = Duration::try_from_secs_f64(seconds).map_err(|_| out_of_range())?;
+        minutes
+            .checked_mul(60)
+            .and_then(|minutes| Duration::from_secs(minutes).checked_add(seconds))
+            .map(TimeTag::from)
+            .ok_or_else(out_of_range)
+    }
+}
+
+impl FromStr for Line {
+    type Err = error::LineFromStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(error::LineFromStr::EmptyText);
+        }
+
+        // Each line should be in the format [mm:ss.xx]text.
+
+        // Remove the leading '['
+        let s = s.strip_prefix('[').ok_or(error::LineFromStr::NoTag)?;
+        // Split the time tag and the text
+        let (tag, text) = s.split_once(']').ok_or(error::LineFromStr::NoTag)?;
+        // Parse the time tag
+        let time = tag.parse::<TimeTag>()?;
+        // LyricsX stores word timings as separate lines, which are not supported
+        if LYRICSX_WORD_TIMES.is_match(text.trim_end()) {
+            return Err(error::LineFromStr::WordTimings);
+        }
+        // Remove LyricsX translation tag (e.g. `[tr:zh-Hans]`), leaving the line to be detected
+        // as a translation by its repeated timestamp
+        let text = match text.strip_prefix("[tr:").and_then(|t| t.split_once(']')) {
+            Some((_, text)) => text,
+            None => text,
+        };
+        // Remove Walaoke gender extension
+        let text = text
+            .trim_start_matches("F:")
+            .trim_start_matches("M:")
+            .trim_start_matches("D:");
+        // Remove A2 world time extension
+        // Each line may have many World Time tags with format <mm:ss.xx>
+        let text = WORD_TIME.replace_all(text, "");
+        // A line without text is kept: it clears the display between verses
+        let text = text.trim();
+
+        Ok(Line {
+            time,
+            text: text.to_string(),
+        })
+    }
+}
+
+/// Parse an ID tag line such as `[ar:Artist]` into its key and value.
+fn parse_id_tag(s: &str) -> Option<(String, String)> {
+    let (key, value) = s
+        .trim()
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split_once(':')?;
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphabetic() || c == '#') {
+        return None;
+    }
+    Some((key.to_owned(), value.trim().to_owned()))
+}
+
+/// Text of a line whose time tag could not be parsed, without its leading tags
+fn strip_tags(s: &str) -> &str {
+    let mut text = s.trim();
+    while let Some((_, rest)) = text.strip_prefix('[').and_then(|t| t.split_once(']')) {
+        text = rest.trim_start();
+    }
+    text
+}
+
+impl Line {
+    /// Append text to the end of the line.
+    ///
+    /// Some LRC files have lines that are split into multiple lines, but the parser by design
+    /// only recognizes one lrc line per file line. This function allows you to append text to
+    /// the end of the line.
+    pub fn push_text(&mut self, text: &str) {
+        if !self.text.is_empty() {
+            self.text.push(' ');
+        }
+        self.text.push_str(text);
+    }
+}
+
+impl Lrc {
+    /// Build lyrics from lines grouped by track, detecting the kind of each track.
+    ///
+    /// Tracks without any text are dropped. The first remaining track is assumed to be the original
+    /// lyrics. Lines within each track must be sorted by time.
+    #[must_use]
+    pub fn new(tracks: Vec<Vec<Line>>) -> Self {
+        let mut tracks = tracks
+            .into_iter()
+            .filter(|lines| lines.iter().any(|line| !line.text.is_empty()));
+        let Some(original) = tracks.next() else {
+            return Self {
+                tracks: Vec::new(),
+                index: Vec::new(),
+                tags: Vec::new(),
+                synced: true,
+                recovered: false,
+            };
+        };
+        let original_script = lang::dominant_script(original.iter().map(|l| l.text.as_str()));
+        let tracks: Vec<_> = core::iter::once(Track {
+            kind: TrackKind::Original,
+            lines: original,
+        })
+        .chain(tracks.map(|lines| Track {
+            kind: TrackKind::detect(original_script, &lines),
+            lines,
+        }))
+        .collect();
+        let index = tracks
+            .iter()
+            .flat_map(|track| track.lines.iter().map(|line| line.time))
+            .sorted()
+            .dedup()
+            .collect();
+        Self {
+            tracks,
+            index,
+            tags: Vec::new(),
+            synced: true,
+            recovered: false,
+        }
+    }
+
+    /// Parse an LRC file from a reader.
+    ///
+    /// Lines are split into tracks in two ways: a timestamp going backwards starts a new block of
+    /// tracks (translation appended after the original), and a timestamp repeating within a
+    /// block moves the line to the next track of the block (translation interleaved with the
+    /// original).
+    ///
+    /// If no line has a valid time tag, the text of the lines with an invalid one is kept as
+    /// plain text lyrics, so that a partially broken file still shows something. See
+    /// [`Lrc::is_recovered`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lyrics cannot be read, contain no lyric lines, or are larger than
+    /// [`MAX_SIZE`] or [`MAX_LINES`].
+    pub fn from_reader<R: BufRead>(s: R) -> Result<Self, error::LrcFromReader> {
+        let mut tracks: Vec<Vec<Line>> = vec![Vec::new()];
+        // Index of the first track of the current block
+        let mut block_start = 0;
+        // Number of consecutive lines sharing the last timestamp
+        let mut repeats = 0;
+        // Track the last line was added to
+        let mut current = 0;
+        let mut last_timestamp: Option<TimeTag> = None;
+        let mut tags = Vec::new();
+        // Lines without a time tag before the first one with a tag
+        let mut plain = Vec::new();
+        // Text of the lines with an invalid time tag
+        let mut broken = Vec::new();
+
+        // Read one byte more than allowed to tell a file of exactly the maximum size apart
+        let mut buffer = Vec::new();
+        s.take(MAX_SIZE + 1).read_to_end(&mut buffer)?;
+        if buffer.len() as u64 > MAX_SIZE {
+            return Err(error::LrcFromReader::TooLarge);
+        }
+        for (count, raw_string) in buffer.lines().enumerate() {
+            let raw_string = raw_string?;
+            if count >= MAX_LINES {
+                return Err(error::LrcFromReader::TooLarge);
+            }
+            match raw_string.parse::<Line>() {
+                Ok(line) => {
+                    match last_timestamp {
+                        Some(last) if last > line.time => {
+                            // The timestamp went backwards: start a new block of tracks
+                            block_start = tracks.len();
+                            repeats = 0;
+                        }
+                        Some(last) if last == line.time => repeats += 1,
+                        _ => repeats = 0,
+                    }
+                    last_timestamp = Some(line.time);
+                    current = block_start + repeats;
+                    if tracks.len() <= current {
+                        tracks.resize_with(current + 1, Vec::new);
+                    }
+                    tracing::trace!("parsed line for track {}: {}", current, raw_string);
+                    tracks[current].push(line);
+                }
+                Err(error::LineFromStr::NoTag) if last_timestamp.is_none() => {
+                    plain.push(raw_string.trim().to_owned());
+                }
+                Err(error::LineFromStr::NoTag) => {
+                    // If the line has no tag, append it to the last line, which was added to the
+                    // current track along with the last timestamp.
+                    if let Some(last) = tracks.get_mut(current).and_then(|t| t.last_mut()) {
+                        last.push_text(&raw_string);
+                        tracing::trace!("appended text to last line: {}", raw_string);
+                    }
+                }
+                Err(e @ error::LineFromStr::InvalidTimeTag(_)) => {
+                    if let Some(tag) = parse_id_tag(&raw_string) {
+                        tags.push(tag);
+                    } else {
+                        tracing::warn!("failed to parse line: {}", e);
+                        let text = strip_tags(&raw_string);
+                        if !text.is_empty() {
+                            broken.push(text.to_owned());
+                        }
+                    }
+                }
+                Err(error::LineFromStr::WordTimings) => {
+                    tracing::trace!("skipped word timings: {}", raw_string);
+                }
+                Err(e) => {
+                    tracing::warn!("failed to parse line: {}", e);
+                }
+            }
+        }
+
+        let synced = last_timestamp.is_some();
+        if synced && !plain.is_empty() {
+            tracing::warn!("no time tag present on first line");
+            tracks[0].insert(
+                0,
+                Line {
+                    time: TimeTag(Duration::ZERO),
+                    text: plain.join(" "),
+                },
+            );
+        } else if !synced {
+            tracing::debug!("no time tags present, lyrics are not synchronised");
+            tracks[0] = plain
+                .into_iter()
+                .map(|text| Line {
+                    time: TimeTag(Duration::ZERO),
+                    text,
+                })
+                .collect();
+        }
+
+        let mut lrc = Self::new(tracks);
+        if lrc.tracks.is_empty() && !synced {
+            lrc = Self::recover(broken);
+        }
+        if lrc.tracks.is_empty() {
+            return Err(error::LrcFromReader::NoLines);
+        }
+        lrc.tags = tags;
+        lrc.synced = synced;
+        Ok(lrc)
+    }
+
+    /// Plain text lyrics made of the text of lines whose time tag could not be parsed
+    fn recover(broken: Vec<String>) -> Self {
+        let mut lrc = Self::new(vec![broken
+            .into_iter()
+            .map(|text| Line {
+                time: TimeTag(Duration::ZERO),
+                text,
+            })
+            .collect()]);
+        lrc.recovered = !lrc.tracks.is_empty();
+        if lrc.recovered {
+            tracing::warn!("no valid time tag present, showing the text as plain lyrics");
+        }
+        lrc
+    }
+
+    /// Whether the lyrics are the text of a file whose time tags all failed to parse, shown as
+    /// plain text lyrics instead of failing
+    #[must_use]
+    pub fn is_recovered(&self) -> bool {
+        self.recovered
+    }
+
+    /// Whether the lyrics only say that the track is instrumental, as some lyric sites write in
+    /// place of lyrics
+    #[must_use]
+    pub fn is_instrumental(&self) -> bool {
+        let Some(track) = self.tracks.first() else {
+            return false;
+        };
+        let mut lines = track
+            .lines
+            .iter()
+            .map(|line| {
+                line.text
+                    .trim_matches(|c: char| c.is_whitespace() || "()[]（）【】♪♫~-*".contains(c))
+                    .to_lowercase()
+            })
+            .filter(|text| !text.is_empty())
+            .peekable();
+        lines.peek().is_some() && lines.all(|text| INSTRUMENTAL_MARKERS.contains(&text.as_str()))
+    }
+
+    /// Whether the lyrics have time tags. Plain text lyrics have all their lines at the start
+    /// until [`Lrc::spread`] is called.
+    #[must_use]
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    /// Number of lines of the original lyrics
+    #[must_use]
+    pub fn line_count(&self) -> usize {
+        self.tracks.first().map_or(0, |track| track.lines.len())
+    }
+
+    /// Give the lines of unsynchronised lyrics evenly spaced time tags, `interval` apart.
+    ///
+    /// Synchronised lyrics are returned unchanged.
+    #[must_use]
+    pub fn spread(self, interval: Duration) -> Self {
+        if self.synced {
+            return self;
+        }
+        let tags = self.tags;
+        let mut lrc = Self::new(
+            self.tracks
+                .into_iter()
+                .map(|track| {
+                    (0..)
+                        .zip(track.lines)
+                        .map(|(i, line)| Line {
+                            time: TimeTag(interval * i),
+                            ..line
+                        })
+                        .collect()
+                })
+                .collect(),
+        );
+        lrc.tags = tags;
+        lrc
+    }
+
+    /// Multiply all time tags by `factor`, e.g. to fit lyrics written for a longer version of a
+    /// song to a shorter one. The `[length:]` tag is rescaled too.
+    #[must_use]
+    pub fn rescale(self, factor: f64) -> Self {
+        let scale = |time: TimeTag| {
+            Duration::try_from_secs_f64(time.0.as_secs_f64() * factor).map_or(time, TimeTag)
+        };
+        let tags = self
+            .tags
+            .into_iter()
+            .map(|(key, value)| match value.parse::<TimeTag>() {
+                Ok(length) if key.eq_ignore_ascii_case("length") => {
+                    let length = scale(length).to_string();
+                    (key, length)
+                }
+                _ => (key, value),
+            })
+            .collect();
+        let synced = self.synced;
+        let mut lrc = Self::new(
+            self.tracks
+                .into_iter()
+                .map(|track| {
+                    track
+                        .lines
+                        .into_iter()
+                        .map(|line| Line {
+                            time: scale(line.time),
+                            ..line
+                        })
+                        .collect()
+                })
+                .collect(),
+        );
+        lrc.tags = tags;
+        lrc.synced = synced;
+        lrc
+    }
+
+    /// Remove the lines without text, so that each line stays displayed until the next one.
+    #[must_use]
+    pub fn without_blank_lines(self) -> Self {
+        let (tags, synced) = (self.tags, self.synced);
+        let mut lrc = Self::new(
+            self.tracks
+                .into_iter()
+                .map(|track| {
+                    track
+                        .lines
+                        .into_iter()
+                        .filter(|line| !line.text.is_empty())
+                        .collect()
+                })
+                .collect(),
+        );
+        lrc.tags = tags;
+        lrc.synced = synced;
+        lrc
+    }
+
+    /// Get the value of an ID tag (e.g. `ti` for the title), ignoring case.
+    #[must_use]
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Get the length of the song the lyrics were written for, from the `[length:]` tag.
+    #[must_use]
+    pub fn length(&self) -> Option<Duration> {
+        self.tag("length")?.parse::<TimeTag>().ok().map(|t| t.0)
+    }
+
+    /// Parse an LRC file from a file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or contains no lyric lines.
+    #[instrument]
+    pub fn from_file<P: AsRef<std::path::Path> + Debug>(
+        path: &P,
+    ) -> Result<Self, error::LrcFromReader> {
+        let mut file = BufReader::new(std::fs::File::open(path)?);
+        Self::from_reader(&mut file)
+    }
+
+    /// Parse an LRC file from a string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the string contains no lyric lines.
+    #[instrument(skip(s))]
+    pub fn from_str(s: &str) -> Result<Self, error::LrcFromReader> {
+        Self::from_reader(s.as_bytes())
+    }
+
+    /// Iterate over the lines of all tracks in time order.
+    ///
+    /// Lines sharing a time tag are yielded in track order, the original first.
+    pub fn iter_lines(&self) -> impl Iterator<Item = (TimeTag, &str)> {
+        self.index.iter().flat_map(move |&time| {
+            self.tracks.iter().flat_map(move |track| {
+                let start = track.lines.partition_point(|line| line.time < time);
+                track.lines[start..]
+                    .iter()
+                    .take_while(move |line| line.time == time)
+                    .map(|line| (line.time, line.text.as_str()))
+            })
+        })
+    }
+
+    /// Serialize the lyrics back into LRC format.
+    ///
+    /// A track whose time tags are all found in the track before it is interleaved with it by
+    /// time, its lines following the ones with the same time tag. Any other track starts a new
+    /// block, which parses back into a separate track when its first time tag goes back from the
+    /// last one written.
+    #[must_use]
+    pub fn to_lrc_string(&self) -> String {
+        let mut result = String::new();
+        for (key, value) in &self.tags {
+            result.push('[');
+            result.push_str(key);
+            result.push(':');
+            result.push_str(value);
+            result.push_str("]\n");
+        }
+        // Lines of the current block, sorted by time and then by track
+        let mut block: Vec<&Line> = Vec::new();
+        let mut previous: Option<&Track> = None;
+        for track in &self.tracks {
+            let aligned = self.synced
+                && previous.is_some_and(|previous| {
+                    track.lines.iter().all(|line| {
+                        previous
+                            .lines
+                            .binary_search_by_key(&line.time, |l| l.time)
+                            .is_ok()
+                    })
+                });
+            if !aligned {
+                self.write_lines(&mut result, block.drain(..));
+            }
+            block.extend(&track.lines);
+            // The sort is stable, so lines sharing a time tag stay in track order
+            block.sort_by_key(|line| line.time);
+            previous = Some(track);
+        }
+        self.write_lines(&mut result, block);
+        result
+    }
+
+    /// Append lines in LRC format
+    fn write_lines<'a>(&self, result: &mut String, lines: impl IntoIterator<Item = &'a Line>) {
+        for line in lines {
+            // Plain text lyrics have no meaningful time tags to write
+            if self.synced {
+                result.push('[');
+                result.push_str(&line.time.to_string());
+                result.push(']');
+            }
+            result.push_str(&line.text);
+            result.push('\n');
+        }
+    }
+
+    /// Add a translation of the original lyrics, one line of text per original line.
+    ///
+    /// The translated lines share the time tags of the original ones.
+    #[must_use]
+    pub fn with_translation(mut self, texts: Vec<String>) -> Self {
+        let Some(original) = self.tracks.first() else {
+            return self;
+        };
+        let lines = original
+            .lines
+            .iter()
+            .zip(texts)
+            .map(|(line, text)| Line {
+                time: line.time,
+                text,
+            })
+            .collect();
+        self.tracks.push(Track {
+            kind: TrackKind::Translation,
+            lines,
+        });
+        self
+    }
+
+    /// Keep only the version of the lyrics written in the language, if there is one besides the
+    /// original.
+    ///
+    /// The original is kept if it is written in the script of the language, or if no other track
+    /// is. Languages are told apart by script only, so e.g. an English translation of French
+    /// lyrics is never picked.
+    #[must_use]
+    pub fn select_language(mut self, language: &str) -> Self {
+        let scripts = lang::scripts_of(language);
+        let matches = |track: &Track| {
+            let texts = || track.lines.iter().map(|l| l.text.as_str());
+            let script = match lang::dominant_script(texts()) {
+                // Japanese is mostly written in kanji, but Chinese has no kana
+                Some(lang::Script::Han)
+                    if texts()
+                        .flat_map(str::chars)
+                        .any(|c| lang::Script::of(c) == Some(lang::Script::Kana)) =>
+                {
+                    Some(lang::Script::Kana)
+                }
+                script => script,
+            };
+            script.is_some_and(|script| scripts.contains(&script))
+        };
+        let chosen = if self.tracks.first().is_some_and(matches) {
+            0
+        } else {
+            self.tracks
+                .iter()
+                .position(|track| track.kind == TrackKind::Translation && matches(track))
+                .unwrap_or(0)
+        };
+        if self.tracks.len() > 1 {
+            let track = self.tracks.swap_remove(chosen);
+            self.tracks = vec![track];
+            self.index = self.tracks[0]
+                .lines
+                .iter()
+                .map(|line| line.time)
+                .dedup()
+                .collect();
+        }
+        self
+    }
+
+    /// All tracks of the lyrics, the original one first.
+    #[must_use]
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Get the time tag of the last line at or before the given time, across all tracks.
+    #[must_use]
+    pub fn current_timetag(&self, time: TimeTag) -> Option<TimeTag> {
+        let i = self.index.partition_point(|&t| t <= time);
+        i.checked_sub(1).and_then(|i| self.index.get(i)).copied()
+    }
+
+    /// Get the time tag of the last line, across all tracks.
+    #[must_use]
+    pub fn last_timetag(&self) -> Option<TimeTag> {
+        self.index.last().copied()
+    }
+
+    /// Get the time tag of the first line after the given time, across all tracks.
+    #[must_use]
+    pub fn next_timetag(&self, time: TimeTag) -> Option<TimeTag> {
+        let i = self.index.partition_point(|&t| t <= time);
+        self.index.get(i).copied()
+    }
+
+    /// Get the current line of each track at a given time.
+    pub fn lines_at(&self, time: TimeTag) -> impl Iterator<Item = (TrackKind, &Line)> {
+        self.tracks
+            .iter()
+            .filter_map(move |track| track.line_at(time).map(|line| (track.kind, line)))
+    }
+
+    /// Get lyrics for a given time, and the time tag of the next line.
+    #[must_use]
+    pub fn get_lyrics(&self, time: TimeTag) -> (Vec<&Line>, Option<TimeTag>) {
+        let lines = self.lines_at(time).map(|(_, line)| line).collect();
+        (lines, self.next_timetag(time))
+    }
+}
+
+impl Track {
+    /// Get the line being sung at a given time.
+    #[must_use]
+    pub fn line_at(&self, time: TimeTag) -> Option<&Line> {
+        self.index_at(time).and_then(|i| self.lines.get(i))
+    }
+
+    /// Get the lines before, at and after a given time, in a single lookup. Before the first
+    /// line, only the next one is returned.
+    #[must_use]
+    pub fn window_at(&self, time: TimeTag) -> [Option<&Line>; 3] {
+        let split = self.lines.partition_point(|line| line.time <= time);
+        let current = split.checked_sub(1);
+        [
+            current
+                .and_then(|i| i.checked_sub(1))
+                .and_then(|i| self.lines.get(i)),
+            current.and_then(|i| self.lines.get(i)),
+            self.lines.get(split),
+        ]
+    }
+
+    /// Get the index of the line being sung at a given time.
+    #[must_use]
+    pub fn index_at(&self, time: TimeTag) -> Option<usize> {
+        // Lines are sorted by time, so we can binary search for the first line after the given
+        // time
+        let split = self.lines.partition_point(|line| line.time <= time);
+        split.checked_sub(1)
+    }
+}
+
+impl TrackKind {
+    /// Guess the kind of a secondary track from its script and the script of the original.
+    ///
+    /// A Latin track accompanying lyrics in another script is a romanization if most of its
+    /// lines do not start with a capital letter (romaji and pinyin are conventionally written in
+    /// lower case, while translations are written as sentences). Anything else is a translation.
+    fn detect(original_script: Option<lang::Script>, lines: &[Line]) -> Self {
+        let script = lang::dominant_script(lines.iter().map(|l| l.text.as_str()));
+        if script != Some(lang::Script::Latin)
+            || original_script.is_none()
+            || original_script == Some(lang::Script::Latin)
+        {
+            return Self::Translation;
+        }
+        let lowercase = lines
+            .iter()
+            .filter(|l| {
+                l.text
+                    .chars()
+                    .find(|c| c.is_alphabetic())
+                    .is_some_and(char::is_lowercase)
+            })
+            .count();
+        if lowercase * 2 > lines.len() {
+            Self::Romanization
+        } else {
+            Self::Translation
+        }
+    }
+}
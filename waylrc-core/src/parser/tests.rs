@@ -0,0 +1,419 @@
+use super::*;
+
+#[test]
+fn example() {
+    const LYRIC: &[u8] = r#"[00:12.00]Line 1 lyrics
+[00:17.20]Line 2 lyrics"#
+        .as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert_eq!(
+        lrc,
+        Lrc::new(vec![vec![
+            Line {
+                time: TimeTag(Duration::from_secs(12)),
+                text: "Line 1 lyrics".to_string(),
+            },
+            Line {
+                time: TimeTag(Duration::from_secs(17) + Duration::from_millis(200)),
+                text: "Line 2 lyrics".to_string(),
+            }
+        ]])
+    );
+}
+
+#[test]
+fn repeating_lyrics_regression() {
+    const LYRIC: &[u8] = r#"[00:12.00]Line 1 lyrics
+[00:21.10][00:45.10]Repeating lyrics (e.g. chorus)"#
+        .as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert_eq!(
+        lrc,
+        Lrc::new(vec![vec![
+            Line {
+                time: TimeTag(Duration::from_secs(12)),
+                text: "Line 1 lyrics".to_string(),
+            },
+            Line {
+                time: TimeTag(Duration::from_secs(21) + Duration::from_millis(100)),
+                text: "[00:45.10]Repeating lyrics (e.g. chorus)".to_string(),
+            }
+        ]])
+    );
+}
+
+#[test]
+fn walaoke_extension() {
+    const LYRIC: &[u8] = r#"[00:12.00]Line 1 lyrics
+[00:17.20]F: Line 2 lyrics
+[00:21.10]M: Line 3 lyrics
+[00:24.00]Line 4 lyrics
+[00:28.25]D: Line 5 lyrics
+[00:29.02]Line 6 lyrics"#
+        .as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert_eq!(
+        lrc,
+        Lrc::new(vec![vec![
+            Line {
+                time: TimeTag(Duration::from_secs(12)),
+                text: "Line 1 lyrics".to_string(),
+            },
+            Line {
+                time: TimeTag(Duration::from_secs(17) + Duration::from_millis(200)),
+                text: "Line 2 lyrics".to_string(),
+            },
+            Line {
+                time: TimeTag(Duration::from_secs(21) + Duration::from_millis(100)),
+                text: "Line 3 lyrics".to_string(),
+            },
+            Line {
+                time: TimeTag(Duration::from_secs(24)),
+                text: "Line 4 lyrics".to_string(),
+            },
+            Line {
+                time: TimeTag(Duration::from_secs(28) + Duration::from_millis(250)),
+                text: "Line 5 lyrics".to_string(),
+            },
+            Line {
+                time: TimeTag(Duration::from_secs(29) + Duration::from_millis(20)),
+                text: "Line 6 lyrics".to_string(),
+            }
+        ]])
+    );
+}
+
+#[test]
+fn exhanced_lrc() {
+    const LYRIC: &[u8] = r#"[ar: Jefferson Airplane]
+[al: Surrealistic Pillow]
+[au: Jefferson Airplane]
+[length: 2:58]
+[by: lrc-maker]
+[ti: Somebody to Love]
+
+[00:00.00] <00:00.04> When <00:00.16> the <00:00.82> truth <00:01.29> is <00:01.63> found <00:03.09> to <00:03.37> be <00:05.92> lies 
+[00:06.47] <00:07.67> And <00:07.94> all <00:08.36> the <00:08.63> joy <00:10.28> within <00:10.53> you <00:13.09> dies 
+[00:13.34] <00:14.32> Don't <00:14.73> you <00:15.14> want <00:15.57> somebody <00:16.09> to <00:16.46> love"#.as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert_eq!(lrc.tag("ti"), Some("Somebody to Love"));
+    assert_eq!(lrc.length(), Some(Duration::from_secs(178)));
+    assert_eq!(
+        lrc.tracks(),
+        Lrc::new(vec![vec![
+            Line {
+                time: TimeTag(Duration::from_secs(0)),
+                text: "When the truth is found to be lies".to_string(),
+            },
+            Line {
+                time: TimeTag(Duration::from_secs(6) + Duration::from_millis(470)),
+                text: "And all the joy within you dies".to_string(),
+            },
+            Line {
+                time: TimeTag(Duration::from_secs(13) + Duration::from_millis(340)),
+                text: "Don't you want somebody to love".to_string(),
+            }
+        ]])
+        .tracks()
+    );
+}
+
+#[test]
+fn no_lines() {
+    const LYRIC: &[u8] = b"[ar: Artist]\n\n[ti: Title]\n";
+
+    assert!(matches!(
+        Lrc::from_reader(LYRIC),
+        Err(error::LrcFromReader::NoLines)
+    ));
+}
+
+#[test]
+fn get_lyrics() {
+    const LYRIC: &[u8] = b"[00:01.00]One\n[00:03.00]Two\n[00:05.00]Three";
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+    let at = |secs| {
+        let (lines, next) = lrc.get_lyrics(TimeTag(Duration::from_secs(secs)));
+        (
+            lines
+                .into_iter()
+                .map(|l| l.text.as_str())
+                .collect::<Vec<_>>(),
+            next.map(|t| t.0.as_secs()),
+        )
+    };
+
+    assert_eq!(at(0), (vec![], Some(1)));
+    assert_eq!(at(1), (vec!["One"], Some(3)));
+    assert_eq!(at(4), (vec!["Two"], Some(5)));
+    assert_eq!(at(6), (vec!["Three"], None));
+
+    let window = |secs| {
+        lrc.tracks()[0]
+            .window_at(TimeTag(Duration::from_secs(secs)))
+            .map(|line| line.map(|l| l.text.as_str()))
+    };
+    assert_eq!(window(0), [None, None, Some("One")]);
+    assert_eq!(window(1), [None, Some("One"), Some("Two")]);
+    assert_eq!(window(4), [Some("One"), Some("Two"), Some("Three")]);
+    assert_eq!(window(6), [Some("Two"), Some("Three"), None]);
+}
+
+#[test]
+fn interleaved_translation() {
+    const LYRIC: &[u8] = "[00:01.00]君の名は\n[00:01.00]Your name\n[00:01.00]kimi no na wa\n[00:04.00]夢\n[00:04.00]A dream\n[00:04.00]yume".as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert_eq!(
+        lrc.tracks().iter().map(|t| t.kind).collect::<Vec<_>>(),
+        vec![
+            TrackKind::Original,
+            TrackKind::Translation,
+            TrackKind::Romanization
+        ]
+    );
+    let (lines, next) = lrc.get_lyrics(TimeTag(Duration::from_secs(2)));
+    assert_eq!(
+        lines
+            .into_iter()
+            .map(|l| l.text.as_str())
+            .collect::<Vec<_>>(),
+        vec!["君の名は", "Your name", "kimi no na wa"]
+    );
+    assert_eq!(next, Some(TimeTag(Duration::from_secs(4))));
+}
+
+#[test]
+fn appended_translation() {
+    const LYRIC: &[u8] = "[00:01.00]夢\n[00:04.00]愛\n[00:01.00]Dream\n[00:04.00]Love".as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert_eq!(lrc.tracks().len(), 2);
+    assert_eq!(lrc.tracks()[1].kind, TrackKind::Translation);
+    assert_eq!(
+        lrc.lines_at(TimeTag(Duration::from_secs(5)))
+            .map(|(_, l)| l.text.as_str())
+            .collect::<Vec<_>>(),
+        vec!["愛", "Love"]
+    );
+}
+
+#[test]
+fn round_trip() {
+    const LYRIC: &[u8] = "[00:01.00]夢\n[00:04.50]愛\n[01:02.345]光\n[00:01.00]Dream\n[00:04.50]Love\n[01:02.345]Light".as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert_eq!(
+        lrc.iter_lines().map(|(_, text)| text).collect::<Vec<_>>(),
+        vec!["夢", "Dream", "愛", "Love", "光", "Light"]
+    );
+    let serialized = lrc.to_lrc_string();
+    assert_eq!(
+        serialized,
+        "[00:01.00]夢\n[00:01.00]Dream\n[00:04.50]愛\n[00:04.50]Love\n[01:02.345]光\n[01:02.345]Light\n"
+    );
+    assert_eq!(Lrc::from_str(&serialized).unwrap(), lrc);
+}
+
+#[test]
+fn round_trip_late_track() {
+    let line = |secs, text: &str| Line {
+        time: TimeTag(Duration::from_secs(secs)),
+        text: text.to_owned(),
+    };
+    // Secondary tracks starting at the last time tag of the original
+    let lrc = Lrc::new(vec![
+        vec![line(1, "夢"), line(3, "愛")],
+        vec![line(3, "Love")],
+        vec![line(1, "Yume"), line(3, "Ai")],
+    ]);
+    assert_eq!(Lrc::from_str(&lrc.to_lrc_string()).unwrap(), lrc);
+    // A secondary track with time tags of its own
+    let lrc = Lrc::new(vec![
+        vec![line(1, "夢"), line(3, "愛")],
+        vec![line(2, "Dream"), line(4, "Love")],
+    ]);
+    assert_eq!(Lrc::from_str(&lrc.to_lrc_string()).unwrap(), lrc);
+}
+
+#[test]
+fn id_tags() {
+    const LYRIC: &[u8] = "[ti:Song]\n[ar: Artist ]\n[length: 03:25]\n[00:01.00]Line".as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert_eq!(lrc.tag("TI"), Some("Song"));
+    assert_eq!(lrc.tag("ar"), Some("Artist"));
+    assert_eq!(lrc.tag("al"), None);
+    assert_eq!(lrc.length(), Some(Duration::from_secs(205)));
+    assert_eq!(Lrc::from_str(&lrc.to_lrc_string()).unwrap(), lrc);
+}
+
+#[test]
+fn lyricsx_extension() {
+    const LYRIC: &[u8] = "[00:01.000]夢\n[00:01.000][tr:en]Dream\n[00:01.000][tt]<0,0><300,1>\n\
+                          [00:05.000][trumpet solo]\n[00:07.000][tt] marks a tag"
+        .as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert_eq!(
+        lrc.iter_lines().map(|(_, text)| text).collect::<Vec<_>>(),
+        vec!["夢", "Dream", "[trumpet solo]", "[tt] marks a tag"]
+    );
+}
+
+#[test]
+fn blank_lines() {
+    const LYRIC: &[u8] =
+        "[00:01.00]Verse\n[00:03.00]\n[00:05.00]\ncontinued\n[00:07.00]".as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+    let text_at = |lrc: &Lrc, secs| {
+        lrc.lines_at(TimeTag(Duration::from_secs(secs)))
+            .map(|(_, l)| l.text.clone())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(text_at(&lrc, 4), vec![""]);
+    assert_eq!(text_at(&lrc, 6), vec!["continued"]);
+    assert_eq!(text_at(&lrc, 8), vec![""]);
+    let lrc = lrc.without_blank_lines();
+    assert_eq!(text_at(&lrc, 4), vec!["Verse"]);
+    assert_eq!(text_at(&lrc, 8), vec!["continued"]);
+    assert!(matches!(
+        Lrc::from_reader(b"[00:01.00]\n[00:02.00]".as_slice()),
+        Err(error::LrcFromReader::NoLines)
+    ));
+}
+
+#[test]
+fn unsynced() {
+    const LYRIC: &[u8] = "[ti:Song]\nFirst line\n\nSecond line\nThird line".as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert!(!lrc.is_synced());
+    assert_eq!(lrc.line_count(), 3);
+    assert_eq!(Lrc::from_str(&lrc.to_lrc_string()).unwrap(), lrc);
+    let lrc = lrc.spread(Duration::from_secs(10));
+    assert!(lrc.is_synced());
+    assert_eq!(
+        lrc.get_lyrics(TimeTag(Duration::from_secs(15))),
+        (
+            vec![&Line {
+                time: TimeTag(Duration::from_secs(10)),
+                text: "Second line".to_owned(),
+            }],
+            Some(TimeTag(Duration::from_secs(20)))
+        )
+    );
+}
+
+#[test]
+fn select_language() {
+    const LYRIC: &[u8] =
+        "[00:01.00]夢を見た\n[00:01.00]我做了一个梦\n[00:01.00]I had a dream".as_bytes();
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+    let text = |lrc: Lrc| {
+        lrc.iter_lines()
+            .map(|(_, text)| text.to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(text(lrc.clone().select_language("zh")), ["我做了一个梦"]);
+    assert_eq!(text(lrc.clone().select_language("ja")), ["夢を見た"]);
+    assert_eq!(text(lrc.clone().select_language("en")), ["I had a dream"]);
+    assert_eq!(text(lrc.select_language("ko")), ["夢を見た"]);
+}
+
+#[test]
+fn malformed() {
+    for tag in [
+        "-1:00.00",
+        "00:-1",
+        "00:NaN",
+        "00:inf",
+        "00:1e300",
+        "307445734561825861:00",
+    ] {
+        assert!(tag.parse::<TimeTag>().is_err(), "{tag} must be rejected");
+    }
+    assert!(Lrc::from_str("[99999999999999999999:00]a\n[00:00.00]b").is_ok());
+
+    let long = "[00:01.00]la\n".repeat(MAX_LINES + 1);
+    assert!(matches!(
+        Lrc::from_str(&long),
+        Err(error::LrcFromReader::TooLarge)
+    ));
+    let large = format!(
+        "[00:01.00]{}",
+        "a".repeat(usize::try_from(MAX_SIZE).unwrap())
+    );
+    assert!(matches!(
+        Lrc::from_str(&large),
+        Err(error::LrcFromReader::TooLarge)
+    ));
+}
+
+#[test]
+fn recovered() {
+    const LYRIC: &[u8] = b"[ti:Song]\n[00:01:00]First line\n[--:--]\n[1:03,5][x]Second line\n";
+
+    let lrc = Lrc::from_reader(LYRIC).unwrap();
+
+    assert!(lrc.is_recovered());
+    assert!(!lrc.is_synced());
+    assert_eq!(lrc.tag("ti"), Some("Song"));
+    assert_eq!(
+        lrc.tracks()[0]
+            .lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>(),
+        ["First line", "Second line"]
+    );
+    let partial = Lrc::from_reader(b"[00:01.00]First\n[00:02:00]Second".as_slice()).unwrap();
+    assert!(!partial.is_recovered());
+    assert_eq!(partial.line_count(), 1);
+}
+
+#[test]
+fn rescale() {
+    let lrc = Lrc::from_str("[length:04:00]\n[00:10.00]One\n[02:00.00]Two").unwrap();
+
+    let lrc = lrc.rescale(0.5);
+
+    assert_eq!(lrc.last_timetag(), Some(TimeTag(Duration::from_mins(1))));
+    assert_eq!(
+        lrc.iter_lines().next().unwrap().0,
+        TimeTag(Duration::from_secs(5))
+    );
+    assert_eq!(lrc.length(), Some(Duration::from_mins(2)));
+}
+
+#[test]
+fn instrumental() {
+    for lyric in [
+        "[00:00.00]纯音乐，请欣赏\n",
+        "[ti:Song]\n[00:00.00]♪ Instrumental ♪\n[00:30.00]\n",
+        "(Instrumental)",
+    ] {
+        assert!(Lrc::from_str(lyric).unwrap().is_instrumental(), "{lyric}");
+    }
+    let lrc = Lrc::from_str("[00:00.00](Instrumental)\n[00:30.00]Words at last").unwrap();
+    assert!(!lrc.is_instrumental());
+}
@@ -0,0 +1,134 @@
+//! Access to the media players followed by the [`State`](crate::state::State).
+//!
+//! The players are reached through the [`MediaPlayer`] and [`Bus`] traits rather than through
+//! `mpris` directly, so that the state can also follow players that are not on a D-Bus session,
+//! such as the simulated players of the tests.
+
+use mpris::{DBusError, LoopStatus, Metadata, PlaybackStatus, Player, PlayerFinder};
+
+use crate::metadata::MetadataExt as _;
+
+/// A media player, as followed by the state
+pub trait MediaPlayer {
+    /// Bus name of the player, e.g. `org.mpris.MediaPlayer2.mpd`
+    fn bus_name(&self) -> &str;
+    /// Part of the bus name identifying the player, e.g. `mpd`
+    fn bus_name_player_name_part(&self) -> &str;
+    /// Name of the process owning the bus name, which changes when the player restarts
+    fn unique_name(&self) -> &str;
+    /// Name of the player, e.g. `Music Player Daemon`
+    fn identity(&self) -> &str;
+    /// Metadata of the current track
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the player cannot be queried.
+    fn get_metadata(&self) -> Result<Metadata, DBusError>;
+    /// Whether the player is playing, paused or stopped
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the player cannot be queried.
+    fn get_playback_status(&self) -> Result<PlaybackStatus, DBusError>;
+    /// Position in the current track, normally in microseconds
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the player cannot be queried.
+    fn get_position_in_microseconds(&self) -> Result<u64, DBusError>;
+    /// Speed of the playback, 1 being the normal speed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the player cannot be queried or does not support changing the rate.
+    fn get_playback_rate(&self) -> Result<f64, DBusError>;
+    /// Metadata of the track after the current one, if the player tells which one it is
+    fn next_track_metadata(&self, metadata: &Metadata) -> Option<Metadata>;
+}
+
+/// Where the players are found
+pub trait Bus {
+    /// The players found on the bus
+    type Player: MediaPlayer;
+
+    /// List the players, none being found not being an error
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bus cannot be queried.
+    fn find_all(&self) -> Result<Vec<Self::Player>, DBusError>;
+    /// Connect to the bus again, after it failed to answer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bus cannot be reached.
+    fn reconnect(&mut self) -> Result<(), DBusError>;
+}
+
+impl MediaPlayer for Player {
+    fn bus_name(&self) -> &str {
+        Player::bus_name(self)
+    }
+
+    fn bus_name_player_name_part(&self) -> &str {
+        Player::bus_name_player_name_part(self)
+    }
+
+    fn unique_name(&self) -> &str {
+        Player::unique_name(self)
+    }
+
+    fn identity(&self) -> &str {
+        Player::identity(self)
+    }
+
+    fn get_metadata(&self) -> Result<Metadata, DBusError> {
+        Player::get_metadata(self)
+    }
+
+    fn get_playback_status(&self) -> Result<PlaybackStatus, DBusError> {
+        Player::get_playback_status(self)
+    }
+
+    fn get_position_in_microseconds(&self) -> Result<u64, DBusError> {
+        Player::get_position_in_microseconds(self)
+    }
+
+    fn get_playback_rate(&self) -> Result<f64, DBusError> {
+        Player::get_playback_rate(self)
+    }
+
+    /// Taken from the track list, if the player exposes one and does not repeat the track
+    fn next_track_metadata(&self, metadata: &Metadata) -> Option<Metadata> {
+        if !self.supports_track_lists() || self.get_loop_status().ok() == Some(LoopStatus::Track) {
+            return None;
+        }
+        let current = metadata.tags().track_id()?;
+        let tracks = self
+            .get_track_list()
+            .map_err(|e| tracing::debug!("Failed to get track list: {}", e))
+            .ok()?;
+        let index = tracks.ids().iter().position(|id| id.as_str() == current)?;
+        let next = tracks.get(index + 1)?;
+        self.get_track_metadata(next)
+            .map_err(|e| tracing::debug!("Failed to get metadata of the next track: {}", e))
+            .ok()
+    }
+}
+
+impl Bus for PlayerFinder {
+    type Player = Player;
+
+    fn find_all(&self) -> Result<Vec<Player>, DBusError> {
+        match PlayerFinder::find_all(self) {
+            Ok(players) => Ok(players),
+            Err(mpris::FindingError::NoPlayerFound) => Ok(Vec::new()),
+            Err(mpris::FindingError::DBusError(err)) => Err(err),
+        }
+    }
+
+    fn reconnect(&mut self) -> Result<(), DBusError> {
+        *self = Self::new()?;
+        Ok(())
+    }
+}
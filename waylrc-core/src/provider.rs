@@ -0,0 +1,1625 @@
+//! Lyric providers
+//!
+//! A provider looks up the lyrics of a track from a single source. Providers are tried in order
+//! by [`resolve`] until one of them returns lyrics.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    hash::Hasher,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use itertools::Itertools;
+use lofty::TaggedFileExt;
+use mpris::Metadata;
+
+use crate::{
+    metadata::MetadataExt as _,
+    metrics,
+    parser::{Lrc, TimeTag, TrackKind},
+};
+
+pub mod error {
+    use thiserror::Error;
+
+    use crate::parser;
+
+    #[derive(Error, Debug)]
+    pub enum Resolve {
+        #[error("no lyrics found")]
+        NoLyrics,
+        #[error("url is not a file:// url")]
+        NotLocalFile,
+        #[error("failed to parse lyrics: {0}")]
+        ParseError(#[source] parser::error::LrcFromReader),
+        #[error("{provider}: {source}")]
+        ProviderError {
+            provider: &'static str,
+            #[source]
+            source: Box<dyn std::error::Error + Send + Sync>,
+        },
+        #[error("io error: {0}")]
+        IoError(#[source] std::io::Error),
+        #[error("not lyrics but {0}")]
+        NotLyrics(&'static str),
+        #[error("instrumental track")]
+        Instrumental,
+    }
+
+    impl From<parser::error::LrcFromReader> for Resolve {
+        fn from(e: parser::error::LrcFromReader) -> Self {
+            match e {
+                parser::error::LrcFromReader::Io(e) => Self::IoError(e),
+                e @ (parser::error::LrcFromReader::NoLines
+                | parser::error::LrcFromReader::TooLarge) => Self::ParseError(e),
+            }
+        }
+    }
+
+    impl Resolve {
+        /// Whether the lookup may succeed if retried later.
+        ///
+        /// Parse errors and missing lyrics are permanent for a given track, while I/O errors
+        /// (e.g. a network filesystem timing out) are worth another try.
+        #[must_use]
+        pub fn is_transient(&self) -> bool {
+            match self {
+                Self::IoError(e) => !matches!(
+                    e.kind(),
+                    std::io::ErrorKind::NotFound
+                        | std::io::ErrorKind::PermissionDenied
+                        | std::io::ErrorKind::InvalidData
+                ),
+                Self::NoLyrics
+                | Self::NotLocalFile
+                | Self::NotLyrics(_)
+                | Self::Instrumental
+                | Self::ParseError(_)
+                | Self::ProviderError { .. } => false,
+            }
+        }
+    }
+
+    /// Outcome of every provider tried while resolving lyrics
+    #[derive(Error, Debug, Default)]
+    pub struct Chain {
+        pub attempts: Vec<(&'static str, Resolve)>,
+    }
+
+    impl Chain {
+        /// Whether any of the providers may succeed if retried later.
+        #[must_use]
+        pub fn is_transient(&self) -> bool {
+            self.attempts.iter().any(|(_, e)| e.is_transient())
+        }
+
+        /// Whether a provider knows the track to be instrumental
+        #[must_use]
+        pub fn is_instrumental(&self) -> bool {
+            self.attempts
+                .iter()
+                .any(|(_, e)| matches!(e, Resolve::Instrumental))
+        }
+    }
+
+    impl std::fmt::Display for Chain {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            if self.attempts.is_empty() {
+                return write!(f, "no lyric provider configured");
+            }
+            for (i, (provider, e)) in self.attempts.iter().enumerate() {
+                if i != 0 {
+                    write!(f, "; ")?;
+                }
+                write!(f, "{provider}: {e}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// A source of lyrics
+pub trait Provider: Send + Sync {
+    /// Short name of the provider used in logs
+    fn name(&self) -> &'static str;
+    /// Look up the lyrics of the track described by the metadata
+    ///
+    /// # Errors
+    ///
+    /// Returns [`error::Resolve::NoLyrics`] if the provider has no lyrics for the track, or
+    /// another variant if the lookup failed.
+    fn fetch(&self, metadata: &Metadata) -> Result<Lrc, error::Resolve>;
+    /// Check that the provider is configured correctly, without looking up a track, so that a
+    /// misconfiguration is reported at startup rather than at the first lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns why the provider cannot work.
+    fn check(&self) -> Result<(), error::Resolve> {
+        Ok(())
+    }
+}
+
+/// A service translating lyrics that have no translation of their own
+pub trait Translator: Send + Sync {
+    /// Short name of the translator used in logs
+    fn name(&self) -> &'static str;
+    /// Translate lines of lyrics, returning one translated line per line
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the translation failed or was refused (e.g. because of rate limits).
+    fn translate(&self, lines: &[&str]) -> Result<Vec<String>, error::Resolve>;
+    /// Check that the service can be reached and accepts the credentials
+    ///
+    /// # Errors
+    ///
+    /// Returns why the service cannot be used.
+    fn check(&self) -> Result<(), error::Resolve> {
+        Ok(())
+    }
+}
+
+/// Add a machine translation to lyrics that do not have one.
+///
+/// The lyrics are returned unchanged if the translation fails.
+#[must_use]
+pub fn translate(translator: &dyn Translator, lyrics: Lrc) -> Lrc {
+    if lyrics
+        .tracks()
+        .iter()
+        .any(|track| track.kind == TrackKind::Translation)
+    {
+        return lyrics;
+    }
+    let Some(original) = lyrics.tracks().first() else {
+        return lyrics;
+    };
+    // Only lines with text are sent, blank lines stay blank
+    let texts: Vec<&str> = original
+        .lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .filter(|text| !text.is_empty())
+        .collect();
+    let translated = match translator.translate(&texts) {
+        Ok(translated) if translated.len() == texts.len() => translated,
+        Ok(translated) => {
+            tracing::warn!(
+                "{} returned {} lines for {} lines of lyrics",
+                translator.name(),
+                translated.len(),
+                texts.len()
+            );
+            return lyrics;
+        }
+        Err(e) => {
+            tracing::warn!("Failed to translate lyrics: {}", e);
+            return lyrics;
+        }
+    };
+    let mut translated = translated.into_iter();
+    let texts = original
+        .lines
+        .iter()
+        .map(|line| {
+            if line.text.is_empty() {
+                String::new()
+            } else {
+                translated.next().unwrap_or_default()
+            }
+        })
+        .collect();
+    tracing::info!("translated lyrics with {}", translator.name());
+    lyrics.with_translation(texts)
+}
+
+/// Get the local path of the track, if it is a local file
+fn local_path(metadata: &Metadata) -> Option<PathBuf> {
+    metadata.tags().url().and_then(url_to_path)
+}
+
+/// Convert a `file://` url into a local path.
+///
+/// The host is ignored, as players put the host name of the machine there. Windows paths from
+/// players running under Wine (e.g. `file:///C:/Music/song.mp3`) are mapped into the Wine prefix,
+/// with drive `Z:` being the root of the file system as configured by default.
+#[must_use]
+pub fn url_to_path(url: &str) -> Option<PathBuf> {
+    let mut url = url::Url::parse(url).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    if url.host().is_some() {
+        url = url::Url::parse(&format!("file://{}", url.path())).ok()?;
+    }
+    let path = url.to_file_path().ok()?;
+    let Some(drive) = path
+        .components()
+        .nth(1)
+        .and_then(|c| c.as_os_str().to_str())
+    else {
+        return Some(path);
+    };
+    let letter = match drive.as_bytes() {
+        [letter, b':'] if letter.is_ascii_alphabetic() => letter.to_ascii_lowercase(),
+        _ => return Some(path),
+    };
+    // Windows paths may also use backslashes once decoded
+    let rest = path.iter().skip(2).collect::<PathBuf>();
+    let rest = PathBuf::from(rest.to_str()?.replace('\\', "/"));
+    if letter == b'z' {
+        return Some(Path::new("/").join(rest));
+    }
+    let prefix = std::env::var_os("WINEPREFIX")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".wine")))?;
+    Some(
+        prefix
+            .join(format!("drive_{}", char::from(letter)))
+            .join(rest),
+    )
+}
+
+/// Parse a time of an SRT cue, such as `00:01:02,500`
+fn srt_time(time: &str) -> Option<Duration> {
+    let (hms, millis) = time.trim().split_once([',', '.'])?;
+    let mut parts = hms.split(':').map(str::parse::<u64>);
+    let (hours, minutes, seconds) = (
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+    );
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(
+        Duration::from_secs(hours * 3600 + minutes * 60 + seconds)
+            + Duration::from_millis(millis.parse().ok()?),
+    )
+}
+
+/// Convert SRT subtitles into LRC lyrics, with each cue on one line and a blank line where
+/// the subtitles disappear until the next cue.
+fn srt_to_lrc(srt: &str) -> String {
+    let mut cues: Vec<(Duration, Duration, String)> = Vec::new();
+    for block in srt.replace("\r\n", "\n").split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some((start, end)) = lines.next().and_then(|times| times.split_once("-->")) else {
+            continue;
+        };
+        // Positions may follow the end time, e.g. `00:00:04,000 X1:40`
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (Some(start), Some(end)) = (srt_time(start), srt_time(end)) else {
+            continue;
+        };
+        let text = lines.map(str::trim).filter(|l| !l.is_empty()).join(" ");
+        cues.push((start, end, text));
+    }
+    let mut lrc = String::new();
+    for (i, (start, end, text)) in cues.iter().enumerate() {
+        let _ = writeln!(lrc, "[{}]{text}", TimeTag(*start));
+        if cues.get(i + 1).is_none_or(|(next, _, _)| next > end) {
+            let _ = writeln!(lrc, "[{}]", TimeTag(*end));
+        }
+    }
+    lrc
+}
+
+/// Lyrics stored in a `.lrc` file next to the audio file, or in a `.srt` subtitle file next to a
+/// music video.
+///
+/// If a language is preferred, a file with the language before the extension (e.g. `song.zh.lrc`)
+/// is used over the others.
+#[derive(Default)]
+pub struct Sidecar {
+    language: Option<String>,
+}
+
+impl Sidecar {
+    #[must_use]
+    pub fn new(language: Option<String>) -> Self {
+        Self { language }
+    }
+}
+
+impl Provider for Sidecar {
+    fn name(&self) -> &'static str {
+        "sidecar"
+    }
+    fn fetch(&self, metadata: &Metadata) -> Result<Lrc, error::Resolve> {
+        let path = local_path(metadata).ok_or(error::Resolve::NotLocalFile)?;
+        if let Some(language) = &self.language {
+            let lrc = path.with_extension(format!("{language}.lrc"));
+            if lrc.exists() {
+                return Ok(Lrc::from_file(&lrc)?);
+            }
+        }
+        let lrc = path.with_extension("lrc");
+        if lrc.exists() {
+            return Ok(Lrc::from_file(&lrc)?);
+        }
+        let srt = path.with_extension("srt");
+        if srt.exists() {
+            let content = fs::read_to_string(&srt).map_err(error::Resolve::IoError)?;
+            return Ok(Lrc::from_str(&srt_to_lrc(&content))?);
+        }
+        Err(error::Resolve::NoLyrics)
+    }
+}
+
+/// Extensions of video containers, whose tags are not read for lyrics
+const VIDEO_EXTENSIONS: [&str; 10] = [
+    "mkv", "webm", "avi", "mov", "wmv", "flv", "ogv", "ts", "mpg", "mpeg",
+];
+
+/// Whether a file is a video that lyrics are not embedded in, such as a music video played by
+/// mpv
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.iter().any(|v| v.eq_ignore_ascii_case(ext)))
+}
+
+/// Lyrics embedded in the tags of the audio file
+pub struct Embedded;
+
+impl Provider for Embedded {
+    fn name(&self) -> &'static str {
+        "embedded"
+    }
+    fn fetch(&self, metadata: &Metadata) -> Result<Lrc, error::Resolve> {
+        let path = local_path(metadata).ok_or(error::Resolve::NotLocalFile)?;
+        if is_video(&path) {
+            tracing::debug!("not reading the tags of video {}", path.display());
+            return Err(error::Resolve::NoLyrics);
+        }
+        let file = lofty::read_from_path(path).map_err(|e| match e.kind() {
+            lofty::error::ErrorKind::UnknownFormat => error::Resolve::NoLyrics,
+            _ => error::Resolve::ProviderError {
+                provider: self.name(),
+                source: Box::new(e),
+            },
+        })?;
+        let tags = file
+            .tags()
+            .iter()
+            .filter_map(|tag| tag.get(&lofty::ItemKey::Lyrics))
+            .filter_map(|item| item.value().text())
+            .join("\n");
+        if tags.trim().is_empty() {
+            return Err(error::Resolve::NoLyrics);
+        }
+        Ok(Lrc::from_str(&tags)?)
+    }
+}
+
+/// How far the `[length:]` tag of a lyric file may be from the length of the track
+const LENGTH_TOLERANCE: Duration = Duration::from_secs(5);
+/// Extensions of lyric files, in order of preference
+pub const LYRIC_EXTENSIONS: [&str; 2] = ["lrcx", "lrc"];
+/// Number of lines at the start of a lyric file searched for ID tags
+const HEADER_LINES: usize = 20;
+
+/// Lyrics stored in a central directory, as kept by `LyricsX` or the `ESLyric` foobar2000 plugin.
+///
+/// Files are looked up by the naming schemes of these tools (`Artist - Title`, `Title - Artist`,
+/// `Artist/Title` or `Title`, ignoring case), then by the `[ti:]` and `[ar:]` tags in their
+/// headers. Lyrics whose `[length:]` tag does not match the track are skipped, unless they were
+/// written for another version of the song within the version tolerance. If a language is
+/// preferred, files named with the language before the extension (e.g. `Artist - Title.zh.lrc`)
+/// come first.
+pub struct Directory {
+    root: PathBuf,
+    language: Option<String>,
+    /// How much the `[length:]` tag of a file may differ from the length of the track, for the
+    /// file to be rescaled by [`fit_version`] rather than skipped
+    version_tolerance: Option<f64>,
+    /// What was found in the root and its subdirectories, by directory
+    listings: Mutex<HashMap<PathBuf, Listing>>,
+}
+
+/// The lyric files and subdirectories of a directory, as of a modification time of it
+#[derive(Clone)]
+struct Listing {
+    modified: SystemTime,
+    files: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+}
+
+impl Directory {
+    #[must_use]
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            language: None,
+            version_tolerance: None,
+            listings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Prefer files in a language
+    #[must_use]
+    pub fn with_language(self, language: Option<String>) -> Self {
+        Self { language, ..self }
+    }
+
+    /// Accept files written for another version of the song, whose `[length:]` tag differs from
+    /// the length of the track by at most `tolerance`, as [`fit_version`] rescales them
+    #[must_use]
+    pub fn with_version_tolerance(self, tolerance: Option<f64>) -> Self {
+        Self {
+            version_tolerance: tolerance,
+            ..self
+        }
+    }
+
+    /// File names the lyrics of a track may be stored under, without extension
+    fn candidate_names(title: &str, artists: &[&str]) -> Vec<String> {
+        let title = sanitize_file_name(title);
+        let mut names = Vec::new();
+        let joined = artists.join(", ");
+        for artist in core::iter::once(joined.as_str())
+            .chain(artists.iter().copied())
+            .filter(|a| !a.is_empty())
+            .unique()
+        {
+            let artist = sanitize_file_name(artist);
+            names.push(format!("{artist} - {title}"));
+            names.push(format!("{title} - {artist}"));
+            names.push(format!("{artist}/{title}"));
+        }
+        names.push(title);
+        names
+    }
+
+    /// List a directory, unless it was not modified since it was last listed.
+    ///
+    /// Libraries can hold thousands of files, which would otherwise be listed at every lookup.
+    fn list(&self, dir: &Path) -> std::io::Result<Listing> {
+        let modified = fs::metadata(dir)?.modified()?;
+        let lock = || self.listings.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(listing) = lock().get(dir).filter(|l| l.modified == modified) {
+            return Ok(listing.clone());
+        }
+        let mut listing = Listing {
+            modified,
+            files: Vec::new(),
+            dirs: Vec::new(),
+        };
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                listing.dirs.push(path);
+            } else if path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| LYRIC_EXTENSIONS.iter().any(|l| e.eq_ignore_ascii_case(l)))
+            {
+                listing.files.push(path);
+            }
+        }
+        lock().insert(dir.to_owned(), listing.clone());
+        Ok(listing)
+    }
+
+    /// Lyric files in the directory, by lowercase file name without extension
+    fn lyric_files(&self) -> std::io::Result<Vec<(String, PathBuf)>> {
+        let root = self.list(&self.root)?;
+        let mut paths = root.files;
+        // The root and its direct subdirectories, as used by `Artist/Title`
+        for dir in &root.dirs {
+            paths.extend(self.list(dir)?.files);
+        }
+        let mut files: Vec<_> = paths
+            .into_iter()
+            .filter_map(|path| {
+                let name = path
+                    .strip_prefix(&self.root)
+                    .ok()?
+                    .with_extension("")
+                    .to_str()?
+                    .to_lowercase();
+                Some((name, path))
+            })
+            .collect();
+        // Prefer LyricsX files, which may contain translations
+        files.sort_by_key(|(name, path)| {
+            let extension = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default();
+            (
+                name.clone(),
+                LYRIC_EXTENSIONS
+                    .iter()
+                    .position(|l| extension.eq_ignore_ascii_case(l)),
+            )
+        });
+        Ok(files)
+    }
+}
+
+/// Replace characters that are not allowed in file names, as lyric tools do when saving
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Whether the ID tags at the start of a lyric file name the track
+fn header_matches(path: &Path, title: &str, artists: &[&str]) -> bool {
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    let (mut found_title, mut found_artist) = (None, None);
+    for line in BufReader::new(file).lines().take(HEADER_LINES) {
+        let Ok(line) = line else {
+            return false;
+        };
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("[ti:").and_then(|l| l.strip_suffix(']')) {
+            found_title = Some(value.trim().to_lowercase());
+        } else if let Some(value) = line.strip_prefix("[ar:").and_then(|l| l.strip_suffix(']')) {
+            found_artist = Some(value.trim().to_lowercase());
+        }
+    }
+    found_title.is_some_and(|t| t == title.to_lowercase())
+        && found_artist.is_some_and(|found| {
+            artists
+                .iter()
+                .any(|a| !a.is_empty() && found.contains(&a.to_lowercase()))
+        })
+}
+
+/// Whether the lyrics were written for a track of the given length
+fn length_matches(lrc: &Lrc, length: Option<Duration>) -> bool {
+    match (lrc.length(), length) {
+        (Some(expected), Some(length)) => expected.abs_diff(length) <= LENGTH_TOLERANCE,
+        _ => true,
+    }
+}
+
+impl Provider for Directory {
+    fn name(&self) -> &'static str {
+        "directory"
+    }
+    fn fetch(&self, metadata: &Metadata) -> Result<Lrc, error::Resolve> {
+        let title = metadata.tags().title().ok_or(error::Resolve::NoLyrics)?;
+        let artists = metadata.tags().artists().unwrap_or_default();
+        let files = self.lyric_files().map_err(error::Resolve::IoError)?;
+
+        let names = Self::candidate_names(title, &artists);
+        let localized = self.language.iter().flat_map(|language| {
+            names
+                .iter()
+                .map(move |name| format!("{name}.{}", language.to_lowercase()))
+        });
+        let by_name = localized
+            .chain(names.iter().cloned())
+            .flat_map(|name| {
+                let name = name.to_lowercase();
+                files.iter().filter(move |(n, _)| *n == name)
+            })
+            .map(|(_, path)| path);
+        let by_header = files
+            .iter()
+            .map(|(_, path)| path)
+            .filter(|path| header_matches(path, title, &artists));
+        for path in by_name.chain(by_header).unique() {
+            let lrc = match Lrc::from_file(path) {
+                Ok(lrc) => lrc,
+                Err(e) => {
+                    tracing::warn!("Failed to parse {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let length = metadata.tags().length();
+            if length_matches(&lrc, length)
+                || self
+                    .version_tolerance
+                    .is_some_and(|tolerance| version_factor(&lrc, length, tolerance).is_some())
+            {
+                tracing::debug!("found lyrics at {}", path.display());
+                return Ok(lrc);
+            }
+            tracing::debug!("skipping {} as its length does not match", path.display());
+        }
+        Err(error::Resolve::NoLyrics)
+    }
+    fn check(&self) -> Result<(), error::Resolve> {
+        fs::read_dir(&self.root).map(drop).map_err(|e| {
+            error::Resolve::IoError(std::io::Error::new(
+                e.kind(),
+                format!("{}: {e}", self.root.display()),
+            ))
+        })
+    }
+}
+
+/// Compute the keys identifying the content of an audio file, most robust first.
+///
+/// If `fpcalc` from Chromaprint is installed, the first key is derived from the acoustic
+/// fingerprint, which survives retagging. The last key is a hash of the bytes of the file, which
+/// only survives renaming.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read.
+pub fn content_keys(path: &Path) -> std::io::Result<Vec<String>> {
+    let mut keys = Vec::with_capacity(2);
+    match std::process::Command::new("fpcalc")
+        .arg("-plain")
+        .arg(path)
+        .stderr(std::process::Stdio::null())
+        .output()
+    {
+        Ok(output) if output.status.success() && !output.stdout.trim_ascii().is_empty() => {
+            let mut hasher = Fnv1a::default();
+            hasher.write(output.stdout.trim_ascii());
+            keys.push(format!("fp-{:016x}", hasher.finish()));
+        }
+        Ok(_) => tracing::debug!("fpcalc failed to fingerprint {}", path.display()),
+        Err(e) => tracing::debug!("not fingerprinting {}: {}", path.display(), e),
+    }
+    let mut hasher = Fnv1a::default();
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        hasher.write(buf);
+        let len = buf.len();
+        reader.consume(len);
+    }
+    keys.push(format!("file-{:016x}", hasher.finish()));
+    Ok(keys)
+}
+
+/// The 64-bit FNV-1a hash, which is stable across versions and platforms so that it can name
+/// files
+#[derive(Clone, Copy, Debug)]
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Maximum number of audio files whose content keys are remembered by [`Pinned`]
+const MAX_CACHED_KEYS: usize = 256;
+
+/// Modification time and size of a file, which change when it is written to
+type Stamp = (SystemTime, u64);
+
+/// Lyrics confirmed for an audio file, found by the content of the file rather than its name or
+/// tags.
+///
+/// Each pinned file is stored as `<key>.lrc` in the directory, for every key given by
+/// [`content_keys`].
+pub struct Pinned {
+    root: PathBuf,
+    /// Content keys of the audio files looked up, with the stamp of the file they were computed
+    /// from
+    keys: Mutex<HashMap<PathBuf, (Stamp, Vec<String>)>>,
+}
+
+impl Pinned {
+    #[must_use]
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Content keys of an audio file, only computed again if the file changed since they were
+    /// last computed, as fingerprinting and hashing the whole file are slow
+    fn content_keys(&self, path: &Path) -> std::io::Result<Vec<String>> {
+        let metadata = fs::metadata(path)?;
+        let stamp = (metadata.modified()?, metadata.len());
+        let lock = || self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, keys)) = lock().get(path).filter(|(at, _)| *at == stamp) {
+            return Ok(keys.clone());
+        }
+        let keys = content_keys(path)?;
+        let mut cache = lock();
+        if cache.len() >= MAX_CACHED_KEYS {
+            cache.clear();
+        }
+        cache.insert(path.to_owned(), (stamp, keys.clone()));
+        Ok(keys)
+    }
+
+    /// Pin lyrics to an audio file, returning the stored lyric files
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the audio file cannot be read or the lyrics cannot be stored.
+    pub fn pin(&self, audio: &Path, lyrics: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let content = fs::read(lyrics)?;
+        fs::create_dir_all(&self.root)?;
+        content_keys(audio)?
+            .into_iter()
+            .map(|key| {
+                let path = self.root.join(key).with_extension("lrc");
+                fs::write(&path, &content)?;
+                Ok(path)
+            })
+            .collect()
+    }
+}
+
+impl Provider for Pinned {
+    fn name(&self) -> &'static str {
+        "pinned"
+    }
+    fn fetch(&self, metadata: &Metadata) -> Result<Lrc, error::Resolve> {
+        let path = local_path(metadata).ok_or(error::Resolve::NotLocalFile)?;
+        if !self.root.is_dir() {
+            return Err(error::Resolve::NoLyrics);
+        }
+        for key in self.content_keys(&path).map_err(error::Resolve::IoError)? {
+            let pinned = self.root.join(key).with_extension("lrc");
+            if pinned.exists() {
+                tracing::debug!("found pinned lyrics at {}", pinned.display());
+                return Ok(Lrc::from_file(&pinned)?);
+            }
+        }
+        Err(error::Resolve::NoLyrics)
+    }
+}
+
+/// A provider given a limited time to answer, so that a stalled file system (e.g. an
+/// unreachable NFS mount) does not freeze the display.
+///
+/// The lookup runs on its own thread. If it takes longer than the timeout, a transient error is
+/// returned so that the lookup is retried, and a retry for the same track waits for the lookup
+/// already running instead of starting another one.
+pub struct WithTimeout {
+    inner: Arc<dyn Provider>,
+    timeout: Duration,
+    /// Url of the track of the lookup still running, and where its result will be sent
+    pending: Mutex<Option<(String, Lookup)>>,
+}
+
+/// Where the result of a lookup running on its own thread is sent
+type Lookup = mpsc::Receiver<Result<Lrc, error::Resolve>>;
+
+impl WithTimeout {
+    #[must_use]
+    pub fn new(inner: Box<dyn Provider>, timeout: Duration) -> Self {
+        Self {
+            inner: Arc::from(inner),
+            timeout,
+            pending: Mutex::new(None),
+        }
+    }
+
+    /// Start a lookup on its own thread
+    fn spawn(&self, metadata: &Metadata) -> Lookup {
+        let (tx, rx) = mpsc::channel();
+        let (inner, metadata) = (Arc::clone(&self.inner), metadata.clone());
+        thread::spawn(move || {
+            let _ = tx.send(inner.fetch(&metadata));
+        });
+        rx
+    }
+}
+
+impl Provider for WithTimeout {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    fn fetch(&self, metadata: &Metadata) -> Result<Lrc, error::Resolve> {
+        let url = metadata.tags().url().unwrap_or_default().to_owned();
+        let mut pending = self
+            .pending
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let rx = match pending.take() {
+            Some((pending_url, rx)) if pending_url == url => rx,
+            Some((pending_url, rx)) => {
+                if let Err(mpsc::TryRecvError::Empty) = rx.try_recv() {
+                    // Another lookup is still stuck, do not pile up threads behind it
+                    *pending = Some((pending_url, rx));
+                    return Err(timed_out());
+                }
+                self.spawn(metadata)
+            }
+            None => self.spawn(metadata),
+        };
+        match rx.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                tracing::warn!("{} did not answer within {:?}", self.name(), self.timeout);
+                *pending = Some((url, rx));
+                Err(timed_out())
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(error::Resolve::NoLyrics),
+        }
+    }
+    fn check(&self) -> Result<(), error::Resolve> {
+        self.inner.check()
+    }
+}
+
+/// A provider rejecting lyrics recovered from a file without valid time tags, instead of
+/// showing them as plain text
+pub struct Strict {
+    inner: Box<dyn Provider>,
+}
+
+impl Strict {
+    #[must_use]
+    pub fn new(inner: Box<dyn Provider>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Provider for Strict {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    fn fetch(&self, metadata: &Metadata) -> Result<Lrc, error::Resolve> {
+        let lyrics = self.inner.fetch(metadata)?;
+        if lyrics.is_recovered() {
+            return Err(error::Resolve::ParseError(
+                crate::parser::error::LrcFromReader::NoLines,
+            ));
+        }
+        Ok(lyrics)
+    }
+    fn check(&self) -> Result<(), error::Resolve> {
+        self.inner.check()
+    }
+}
+
+/// The error of a lookup that took too long
+fn timed_out() -> error::Resolve {
+    error::Resolve::IoError(std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        "lookup timed out",
+    ))
+}
+
+/// The default chain of providers, in order of preference
+#[must_use]
+pub fn default_providers(language: Option<&str>) -> Vec<Box<dyn Provider>> {
+    vec![
+        Box::new(Sidecar::new(language.map(str::to_owned))),
+        Box::new(Embedded),
+    ]
+}
+
+/// How well lyrics cover a track
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quality {
+    /// Whether the lyrics have time tags
+    pub synced: bool,
+    /// Number of lines of the original lyrics
+    pub lines: usize,
+    /// Percentage of the track before the last time tag, if the length of the track is known
+    pub coverage: Option<usize>,
+    /// Whether the `[length:]` tag of the lyrics, if any, agrees with the length of the track
+    pub length_matches: bool,
+}
+
+/// How far past the end of the track the last line of complete lyrics may be
+const COVERAGE_OVERSHOOT: Duration = Duration::from_secs(10);
+
+impl Quality {
+    /// Estimate the quality of lyrics for a track of the given length
+    #[must_use]
+    pub fn of(lyrics: &Lrc, length: Option<Duration>) -> Self {
+        let last = lyrics.last_timetag().map(|t| t.0);
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            reason = "the ratio is positive and small"
+        )]
+        let coverage = last
+            .zip(length.filter(|l| !l.is_zero()))
+            .filter(|_| lyrics.is_synced())
+            .map(|(last, length)| (last.as_secs_f64() / length.as_secs_f64() * 100.0) as usize);
+        Self {
+            synced: lyrics.is_synced(),
+            lines: lyrics.line_count(),
+            coverage,
+            length_matches: length_matches(lyrics, length),
+        }
+    }
+
+    /// Whether the lyrics are synchronised and plausibly written for a track of this length.
+    ///
+    /// Lyrics whose last line comes well after the end of the track were written for a longer
+    /// version of the song, and lyrics whose `[length:]` tag disagrees with the length of the
+    /// track are likely for another song with the same title.
+    #[must_use]
+    pub fn is_complete(&self, lyrics: &Lrc, length: Option<Duration>) -> bool {
+        let overshoots = lyrics
+            .last_timetag()
+            .zip(length.filter(|l| !l.is_zero()))
+            .is_some_and(|(last, length)| last.0 > length + COVERAGE_OVERSHOOT);
+        self.synced && self.length_matches && !overshoots
+    }
+}
+
+impl std::fmt::Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let synced = if self.synced { "synced" } else { "unsynced" };
+        write!(f, "{synced}, {} lines", self.lines)?;
+        if let Some(coverage) = self.coverage {
+            write!(f, ", last line at {coverage}% of the track")?;
+        }
+        if !self.length_matches {
+            write!(f, ", written for a track of another length")?;
+        }
+        Ok(())
+    }
+}
+
+/// Stretch or shrink synchronised lyrics whose last line comes well after the end of the track,
+/// as if they were written for a longer version of the song, so that they span the track.
+///
+/// The lyrics are taken to span the length in their `[length:]` tag, or up to their last line
+/// if it has none. Other lyrics are returned unchanged.
+#[must_use]
+pub fn fit_to_length(lyrics: Lrc, length: Option<Duration>) -> Lrc {
+    let (Some(last), Some(length)) = (lyrics.last_timetag(), length.filter(|l| !l.is_zero()))
+    else {
+        return lyrics;
+    };
+    if !lyrics.is_synced() || last.0 <= length + COVERAGE_OVERSHOOT {
+        return lyrics;
+    }
+    let written_for = lyrics.length().filter(|l| *l >= last.0).unwrap_or(last.0);
+    let factor = length.as_secs_f64() / written_for.as_secs_f64();
+    tracing::info!(
+        "lyrics were written for a track of {:?}, rescaling them by {:.2} to fit {:?}",
+        written_for,
+        factor,
+        length
+    );
+    lyrics.rescale(factor)
+}
+
+/// How much to rescale synchronised lyrics whose `[length:]` tag differs from the length of the
+/// track by at most `tolerance`, if it does not match it
+fn version_factor(lyrics: &Lrc, length: Option<Duration>, tolerance: f64) -> Option<f64> {
+    let (Some(written_for), Some(length)) = (lyrics.length(), length.filter(|l| !l.is_zero()))
+    else {
+        return None;
+    };
+    if !lyrics.is_synced() || written_for.is_zero() || length_matches(lyrics, Some(length)) {
+        return None;
+    }
+    let factor = length.as_secs_f64() / written_for.as_secs_f64();
+    ((factor - 1.0).abs() <= tolerance).then_some(factor)
+}
+
+/// Rescale synchronised lyrics whose `[length:]` tag differs from the length of the track by at
+/// most `tolerance` (e.g. `0.1` for 10%), as they were likely written for another master or a
+/// radio edit of the same song. Other lyrics are returned unchanged.
+#[must_use]
+pub fn fit_version(lyrics: Lrc, length: Option<Duration>, tolerance: f64) -> Lrc {
+    let Some(factor) = version_factor(&lyrics, length, tolerance) else {
+        return lyrics;
+    };
+    let written_for = lyrics.length().unwrap_or_default();
+    tracing::info!(
+        "lyrics were written for a version of {:?}, rescaling them by {:.3} to fit {:?}",
+        written_for,
+        factor,
+        length.unwrap_or_default()
+    );
+    lyrics.rescale(factor)
+}
+
+/// Lyrics found by a provider
+#[derive(Debug)]
+pub struct Resolved {
+    /// Name of the provider the lyrics come from
+    pub provider: &'static str,
+    pub lyrics: Lrc,
+    pub quality: Quality,
+    /// Whether the lyrics are good enough to stop looking for others
+    complete: bool,
+}
+
+impl Resolved {
+    fn new(provider: &'static str, lyrics: Lrc, metadata: &Metadata) -> Self {
+        let quality = Quality::of(&lyrics, metadata.tags().length());
+        let complete = quality.is_complete(&lyrics, metadata.tags().length());
+        tracing::info!("{} provided lyrics ({})", provider, quality);
+        tracing::trace!("lyrics: {:?}", lyrics);
+        Self {
+            provider,
+            lyrics,
+            quality,
+            complete,
+        }
+    }
+}
+
+/// Try each provider in order and return the first lyrics found.
+///
+/// Unsynchronised lyrics, or lyrics written for a longer version of the track, are only returned
+/// if no other provider has complete lyrics. Providers after one knowing the track to be
+/// instrumental are not asked.
+///
+/// # Errors
+///
+/// If no provider has lyrics, returns the outcome of each provider for diagnostics.
+pub fn resolve(
+    providers: &[Box<dyn Provider>],
+    metadata: &Metadata,
+) -> Result<Resolved, error::Chain> {
+    let mut chain = error::Chain::default();
+    let mut fallback = None;
+    for provider in providers {
+        match fetch(provider.as_ref(), metadata) {
+            Ok(lrc) => {
+                let resolved = Resolved::new(provider.name(), lrc, metadata);
+                if resolved.complete {
+                    return Ok(resolved);
+                }
+                tracing::debug!("trying other providers for better lyrics");
+                fallback.get_or_insert(resolved);
+            }
+            Err(error::Resolve::Instrumental) => {
+                tracing::info!("{} knows the track to be instrumental", provider.name());
+                chain
+                    .attempts
+                    .push((provider.name(), error::Resolve::Instrumental));
+                break;
+            }
+            Err(e @ (error::Resolve::NoLyrics | error::Resolve::NotLocalFile)) => {
+                tracing::debug!("{}: {}", provider.name(), e);
+                chain.attempts.push((provider.name(), e));
+            }
+            Err(e) => {
+                tracing::warn!("{} failed: {}", provider.name(), e);
+                chain.attempts.push((provider.name(), e));
+            }
+        }
+    }
+    fallback.ok_or(chain)
+}
+
+/// Reject content that is not lyrics, such as an HTML error page or a JSON error body returned
+/// by a server in place of a lyric file.
+///
+/// Such content has no time tags, so it would otherwise be shown as plain text lyrics.
+///
+/// # Errors
+///
+/// Returns [`error::Resolve::NotLyrics`] if the content is not lyrics.
+pub fn validate(lrc: Lrc) -> Result<Lrc, error::Resolve> {
+    if lrc.is_synced() {
+        return Ok(lrc);
+    }
+    let Some(track) = lrc.tracks().first() else {
+        return Ok(lrc);
+    };
+    let mut lines = track
+        .lines
+        .iter()
+        .map(|line| line.text.trim())
+        .filter(|text| !text.is_empty());
+    let first = lines.next().unwrap_or_default().to_ascii_lowercase();
+    let last = lines.next_back().unwrap_or(&first);
+    if first.starts_with("<!doctype html")
+        || first.starts_with("<html")
+        || first.starts_with("<?xml")
+    {
+        return Err(error::Resolve::NotLyrics("a web page"));
+    }
+    if first.starts_with('{') && last.ends_with('}') {
+        return Err(error::Resolve::NotLyrics("a JSON document"));
+    }
+    Ok(lrc)
+}
+
+/// Look up lyrics with a provider, checking that they are lyrics and counting the outcome.
+///
+/// Lyrics only saying that the track is instrumental are turned into
+/// [`error::Resolve::Instrumental`].
+fn fetch(provider: &dyn Provider, metadata: &Metadata) -> Result<Lrc, error::Resolve> {
+    let result = provider.fetch(metadata).and_then(validate).and_then(|lrc| {
+        if lrc.is_instrumental() {
+            Err(error::Resolve::Instrumental)
+        } else {
+            Ok(lrc)
+        }
+    });
+    let found = match &result {
+        Ok(_) | Err(error::Resolve::Instrumental) => Some(true),
+        Err(error::Resolve::NoLyrics | error::Resolve::NotLocalFile) => Some(false),
+        Err(_) => None,
+    };
+    metrics::record_provider(provider.name(), found);
+    result
+}
+
+/// Query all providers concurrently and return the most preferred lyrics found.
+///
+/// Once a provider returns lyrics, the providers preferred over it are given `grace` to finish,
+/// and the first complete lyrics in order of preference are returned, or the first incomplete
+/// ones if there are none. Providers still running at that point are left to finish in the
+/// background and their results are discarded.
+///
+/// # Errors
+///
+/// If no provider has lyrics, returns the outcome of each provider for diagnostics.
+pub fn resolve_parallel(
+    providers: &Arc<Vec<Box<dyn Provider>>>,
+    metadata: &Metadata,
+    grace: Duration,
+) -> Result<Resolved, error::Chain> {
+    let (tx, rx) = mpsc::channel();
+    for i in 0..providers.len() {
+        let (providers, metadata, tx) = (Arc::clone(providers), metadata.clone(), tx.clone());
+        thread::spawn(move || {
+            // The receiver is gone if a preferred provider already won
+            let _ = tx.send((i, fetch(providers[i].as_ref(), &metadata)));
+        });
+    }
+    drop(tx);
+
+    let mut results: Vec<Option<Result<Resolved, error::Resolve>>> =
+        providers.iter().map(|_| None).collect();
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let first_complete = results
+            .iter()
+            .position(|r| matches!(r, Some(Ok(resolved)) if resolved.complete));
+        if let Some(first_complete) = first_complete {
+            if results.iter().take(first_complete).all(Option::is_some) {
+                // No preferred provider is still running
+                break;
+            }
+        }
+        let received = match deadline {
+            Some(deadline) => rx
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .ok(),
+            None => rx.recv().ok(),
+        };
+        let Some((i, result)) = received else {
+            // Either every provider finished or the grace period is over
+            break;
+        };
+        let answered = matches!(result, Ok(_) | Err(error::Resolve::Instrumental));
+        if answered && deadline.is_none() {
+            deadline = Some(Instant::now() + grace);
+        }
+        results[i] = Some(result.map(|lrc| Resolved::new(providers[i].name(), lrc, metadata)));
+    }
+
+    let mut chain = error::Chain::default();
+    let mut found = Vec::new();
+    for (provider, result) in providers.iter().zip(results) {
+        match result {
+            Some(Ok(resolved)) => found.push(resolved),
+            Some(Err(e)) => {
+                tracing::debug!("{}: {}", provider.name(), e);
+                let instrumental = matches!(e, error::Resolve::Instrumental);
+                chain.attempts.push((provider.name(), e));
+                if instrumental {
+                    break;
+                }
+            }
+            None => {}
+        }
+    }
+    match found.iter().position(|resolved| resolved.complete) {
+        Some(i) => Ok(found.swap_remove(i)),
+        None => found.into_iter().next().ok_or(chain),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A provider answering with fixed lyrics after a delay
+    struct Fake {
+        name: &'static str,
+        delay: Duration,
+        lyrics: Option<&'static str>,
+    }
+
+    impl Provider for Fake {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn fetch(&self, _: &Metadata) -> Result<Lrc, error::Resolve> {
+            thread::sleep(self.delay);
+            let lyrics = self.lyrics.ok_or(error::Resolve::NoLyrics)?;
+            Ok(Lrc::from_str(lyrics)?)
+        }
+    }
+
+    fn fake(name: &'static str, delay_ms: u64, lyrics: Option<&'static str>) -> Box<dyn Provider> {
+        Box::new(Fake {
+            name,
+            delay: Duration::from_millis(delay_ms),
+            lyrics,
+        })
+    }
+
+    /// A translator shouting the lyrics
+    struct Upper;
+
+    impl Translator for Upper {
+        fn name(&self) -> &'static str {
+            "upper"
+        }
+        fn translate(&self, lines: &[&str]) -> Result<Vec<String>, error::Resolve> {
+            Ok(lines.iter().map(|line| line.to_uppercase()).collect())
+        }
+    }
+
+    #[test]
+    fn translation() {
+        let lrc = Lrc::from_str("[00:01.00]one\n[00:02.00]\n[00:03.00]two").unwrap();
+        let lrc = translate(&Upper, lrc);
+        assert_eq!(
+            lrc.lines_at(TimeTag(Duration::from_secs(3)))
+                .map(|(kind, line)| (kind, line.text.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (TrackKind::Original, "two"),
+                (TrackKind::Translation, "TWO")
+            ]
+        );
+        // Lyrics with a translation are left alone
+        assert_eq!(translate(&Upper, lrc.clone()), lrc);
+    }
+
+    #[test]
+    fn file_urls() {
+        let path = |url| url_to_path(url).map(|p| p.to_string_lossy().into_owned());
+        assert_eq!(
+            path("file:///music/A%20B.flac").as_deref(),
+            Some("/music/A B.flac")
+        );
+        assert_eq!(
+            path("file://localhost/music/song.mp3").as_deref(),
+            Some("/music/song.mp3")
+        );
+        assert_eq!(
+            path("file://desktop/music/song.mp3").as_deref(),
+            Some("/music/song.mp3")
+        );
+        assert_eq!(
+            path("file:///Z:/home/me/song.mp3").as_deref(),
+            Some("/home/me/song.mp3")
+        );
+        assert!(path("file:///C:/Music/song.mp3")
+            .unwrap()
+            .ends_with("/drive_c/Music/song.mp3"));
+        assert_eq!(path("https://example.com/song.mp3"), None);
+    }
+
+    #[test]
+    fn directory() {
+        use mpris::MetadataValue;
+
+        let root = std::env::temp_dir().join(format!("waylrc-directory-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join("Artist - Song.lrc"),
+            "[length:10:00]\n[00:01.00]wrong",
+        )
+        .unwrap();
+        fs::write(
+            root.join("other.lrc"),
+            "[ti:Song]\n[ar:Artist]\n[00:01.00]right",
+        )
+        .unwrap();
+        let metadata = Metadata::from(std::collections::HashMap::from([
+            (
+                "xesam:title".to_owned(),
+                MetadataValue::String("Song".to_owned()),
+            ),
+            (
+                "xesam:artist".to_owned(),
+                MetadataValue::Array(vec![MetadataValue::String("Artist".to_owned())]),
+            ),
+            ("mpris:length".to_owned(), MetadataValue::I64(200_000_000)),
+        ]));
+
+        fs::write(root.join("artist - song.ZH.lrc"), "[00:01.00]localized").unwrap();
+        let lrc = Directory::new(root.clone()).fetch(&metadata);
+        let localized = Directory::new(root.clone())
+            .with_language(Some("zh".to_owned()))
+            .fetch(&metadata);
+        let checked = Directory::new(root.clone()).check();
+        fs::write(
+            root.join("Artist - Song.lrc"),
+            "[length:03:30]\n[00:01.00]other version",
+        )
+        .unwrap();
+        let strict = Directory::new(root.clone()).fetch(&metadata);
+        let version = Directory::new(root.clone())
+            .with_version_tolerance(Some(0.1))
+            .fetch(&metadata);
+        let listed = Directory::new(root.clone());
+        let before = listed.lyric_files().unwrap().len();
+        fs::write(root.join("New.lrc"), "[00:01.00]new").unwrap();
+        let after = listed.lyric_files().unwrap().len();
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(after, before + 1, "a new file is listed");
+        assert!(checked.is_ok(), "the directory exists");
+        assert!(
+            WithTimeout::new(
+                Box::new(Directory::new(root.clone())),
+                Duration::from_secs(1)
+            )
+            .check()
+            .is_err(),
+            "a missing directory is reported through wrappers"
+        );
+        assert_eq!(lrc.unwrap().tracks()[0].lines[0].text, "right");
+        assert_eq!(localized.unwrap().tracks()[0].lines[0].text, "localized");
+        assert_eq!(strict.unwrap().tracks()[0].lines[0].text, "right");
+        assert_eq!(
+            version.unwrap().tracks()[0].lines[0].text,
+            "other version",
+            "another version is accepted to be rescaled"
+        );
+        assert_eq!(
+            Directory::candidate_names("A/B", &["X", "Y"]),
+            vec![
+                "X, Y - A_B",
+                "A_B - X, Y",
+                "X, Y/A_B",
+                "X - A_B",
+                "A_B - X",
+                "X/A_B",
+                "Y - A_B",
+                "A_B - Y",
+                "Y/A_B",
+                "A_B"
+            ]
+        );
+    }
+
+    #[test]
+    fn pinned() {
+        use mpris::MetadataValue;
+
+        let root = std::env::temp_dir().join(format!("waylrc-pinned-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let (audio, renamed, lyrics) = (root.join("a.mp3"), root.join("b.mp3"), root.join("a.lrc"));
+        fs::write(&audio, "not really audio").unwrap();
+        fs::write(&lyrics, "[00:01.00]pinned").unwrap();
+        let pinned = Pinned::new(root.join("pins"));
+        pinned.pin(&audio, &lyrics).unwrap();
+        fs::rename(&audio, &renamed).unwrap();
+
+        let metadata = |path: &Path| {
+            Metadata::from(std::collections::HashMap::from([(
+                "xesam:url".to_owned(),
+                MetadataValue::String(format!("file://{}", path.display())),
+            )]))
+        };
+        let found = pinned.fetch(&metadata(&renamed));
+        let again = pinned.fetch(&metadata(&renamed));
+        fs::write(&renamed, "other content").unwrap();
+        let other = pinned.fetch(&metadata(&renamed));
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(found.unwrap().tracks()[0].lines[0].text, "pinned");
+        assert!(
+            again.is_ok(),
+            "the keys of an unchanged file are remembered"
+        );
+        assert!(matches!(other, Err(error::Resolve::NoLyrics)));
+    }
+
+    #[test]
+    fn subtitles() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:02,500\r\nHello\r\nworld\r\n\r\n\
+                   2\r\n00:00:02,500 --> 00:00:04,000 X1:40\r\n<i>Again</i>\r\n\r\n\
+                   3\r\n00:01:05,000 --> 00:01:06,000\r\nLast\r\n";
+        assert_eq!(
+            srt_to_lrc(srt),
+            "[00:01.00]Hello world\n[00:02.50]<i>Again</i>\n[00:04.00]\n[01:05.00]Last\n[01:06.00]\n"
+        );
+        assert!(is_video(Path::new("/videos/Song.MKV")));
+        assert!(!is_video(Path::new("/music/song.flac")));
+    }
+
+    #[test]
+    fn timeout() {
+        use mpris::MetadataValue;
+
+        let provider = WithTimeout::new(
+            fake("slow", 100, Some("[00:01.00]slow")),
+            Duration::from_millis(10),
+        );
+        let metadata = |url: &str| {
+            Metadata::from(std::collections::HashMap::from([(
+                "xesam:url".to_owned(),
+                MetadataValue::String(url.to_owned()),
+            )]))
+        };
+        let err = provider.fetch(&metadata("file:///a.mp3")).unwrap_err();
+        assert!(err.is_transient());
+        // Another track is not looked up while the first lookup is stuck
+        let started = Instant::now();
+        assert!(provider.fetch(&metadata("file:///b.mp3")).is_err());
+        assert!(started.elapsed() < Duration::from_millis(10));
+        // Retrying the first track picks up the result of the running lookup
+        thread::sleep(Duration::from_millis(100));
+        let lrc = provider.fetch(&metadata("file:///a.mp3")).unwrap();
+        assert_eq!(lrc.tracks()[0].lines[0].text, "slow");
+    }
+
+    #[test]
+    fn fit_length() {
+        let lrc = Lrc::from_str("[00:10.00]One\n[07:00.00]Two").unwrap();
+        let fitted = fit_to_length(lrc.clone(), Some(Duration::from_mins(3)));
+        assert_eq!(fitted.last_timetag(), Some(TimeTag(Duration::from_mins(3))));
+        assert_eq!(fit_to_length(lrc.clone(), None), lrc);
+        assert_eq!(
+            fit_to_length(lrc.clone(), Some(Duration::from_mins(7))),
+            lrc
+        );
+    }
+
+    #[test]
+    fn fit_other_version() {
+        let lrc = Lrc::from_str("[length:03:20]\n[00:10.00]One\n[03:00.00]Two").unwrap();
+        let fitted = fit_version(lrc.clone(), Some(Duration::from_mins(3)), 0.15);
+        assert_eq!(
+            fitted.last_timetag(),
+            Some(TimeTag(Duration::from_secs(162)))
+        );
+        assert_eq!(
+            fit_version(lrc.clone(), Some(Duration::from_mins(3)), 0.05),
+            lrc,
+            "the versions differ too much"
+        );
+        assert_eq!(
+            fit_version(lrc.clone(), Some(Duration::from_secs(198)), 0.15),
+            lrc,
+            "the length matches"
+        );
+
+        // Both fits are set: the second one finds the lyrics already fitted
+        let long = Lrc::from_str("[length:03:20]\n[00:10.00]One\n[03:18.00]Two").unwrap();
+        let fitted = fit_to_length(long, Some(Duration::from_mins(3)));
+        assert_eq!(fitted.length(), Some(Duration::from_mins(3)));
+        assert_eq!(
+            fit_version(fitted.clone(), Some(Duration::from_mins(3)), 0.15),
+            fitted,
+            "the lyrics are not rescaled twice"
+        );
+    }
+
+    #[test]
+    fn not_lyrics() {
+        let check = |content| validate(Lrc::from_str(content).unwrap()).map(|_| ());
+        assert!(check("<!DOCTYPE html>\n<html><body>502 Bad Gateway</body></html>").is_err());
+        assert!(check("{\"code\": 404,\n\"message\": \"not found\"}").is_err());
+        assert!(check("Plain {text}\nlyrics").is_ok());
+        assert!(check("[00:01.00]<html>").is_ok());
+
+        let providers = vec![
+            fake("broken", 0, Some("<html><body>Error</body></html>")),
+            fake("good", 0, Some("[00:01.00]good")),
+        ];
+        let resolved = resolve(&providers, &Metadata::default()).unwrap();
+        assert_eq!(resolved.provider, "good");
+    }
+
+    #[test]
+    fn parallel_prefers_earlier_provider_within_grace() {
+        let providers = Arc::new(vec![
+            fake("slow", 50, Some("[00:01.00]slow")),
+            fake("fast", 0, Some("[00:01.00]fast")),
+        ]);
+        let resolved =
+            resolve_parallel(&providers, &Metadata::default(), Duration::from_secs(5)).unwrap();
+        assert_eq!(resolved.provider, "slow");
+        assert_eq!(resolved.lyrics.tracks()[0].lines[0].text, "slow");
+    }
+
+    #[test]
+    fn parallel_does_not_wait_past_grace() {
+        let providers = Arc::new(vec![
+            fake("slow", 5000, Some("[00:01.00]slow")),
+            fake("fast", 0, Some("[00:01.00]fast")),
+        ]);
+        let start = Instant::now();
+        let resolved =
+            resolve_parallel(&providers, &Metadata::default(), Duration::from_millis(10)).unwrap();
+        assert_eq!(resolved.lyrics.tracks()[0].lines[0].text, "fast");
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "waited for slow provider"
+        );
+    }
+
+    #[test]
+    fn synced_preferred_over_unsynced() {
+        let providers = vec![
+            fake("plain", 0, Some("Plain text")),
+            fake("synced", 0, Some("[00:01.00]Synced")),
+        ];
+        let resolved = resolve(&providers, &Metadata::default()).unwrap();
+        assert_eq!(resolved.provider, "synced");
+        assert_eq!(resolved.quality.to_string(), "synced, 1 lines".to_owned());
+        let resolved = resolve(&providers[..1], &Metadata::default()).unwrap();
+        assert_eq!(resolved.provider, "plain");
+        assert!(!resolved.quality.synced);
+    }
+
+    #[test]
+    fn mismatched_length() {
+        use mpris::MetadataValue;
+
+        let metadata = Metadata::from(std::collections::HashMap::from([(
+            "mpris:length".to_owned(),
+            MetadataValue::I64(200_000_000),
+        )]));
+        let providers = vec![
+            fake("other", 0, Some("[length:05:00]\n[00:01.00]Other song")),
+            fake("right", 0, Some("[length:03:20]\n[00:01.00]Right song")),
+        ];
+        let resolved = resolve(&providers, &metadata).unwrap();
+        assert_eq!(resolved.provider, "right");
+        let resolved = resolve(&providers[..1], &metadata).unwrap();
+        assert!(!resolved.quality.length_matches);
+    }
+
+    #[test]
+    fn parallel_reports_all_failures() {
+        let providers = Arc::new(vec![fake("a", 0, None), fake("b", 10, None)]);
+        let chain = resolve_parallel(&providers, &Metadata::default(), Duration::ZERO).unwrap_err();
+        assert_eq!(chain.to_string(), "a: no lyrics found; b: no lyrics found");
+    }
+
+    #[test]
+    fn instrumental() {
+        let providers = Arc::new(vec![
+            fake("site", 0, Some("[00:00.00]纯音乐，请欣赏")),
+            fake("other", 0, Some("[00:01.00]words")),
+        ]);
+        let chain = resolve(&providers, &Metadata::default()).unwrap_err();
+        assert!(chain.is_instrumental());
+        assert_eq!(
+            chain.attempts.len(),
+            1,
+            "the search ends at the instrumental track"
+        );
+        let chain = resolve_parallel(&providers, &Metadata::default(), Duration::ZERO).unwrap_err();
+        assert!(chain.is_instrumental());
+    }
+}
@@ -0,0 +1,2602 @@
+//! Internal state of the lyric daemon
+
+use core::time::Duration;
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Instant, SystemTime},
+};
+
+use mpris::{DBusError, Metadata, PlaybackStatus, PlayerFinder};
+
+use crate::{
+    metadata::MetadataExt as _,
+    metrics,
+    parser::{Lrc, TimeTag, TrackKind},
+    player::{Bus, MediaPlayer},
+    provider::{self, Provider, Quality, Translator},
+};
+
+/// Cached information about a song
+struct SongInfo {
+    /// Formatted metadata available for display, shared with the snapshots
+    pub metadata: Arc<str>,
+    /// The parsed lyrics
+    pub lyrics: Option<Lrc>,
+    /// Name of the provider the lyrics come from
+    pub source: Option<&'static str>,
+    /// How well the lyrics cover the song
+    pub quality: Option<Quality>,
+    /// Why no lyrics were found, if the lookup failed
+    pub diagnostic: Option<String>,
+    /// How long to wait before looking up the lyrics again, if the lookup failed transiently
+    pub retry: Option<Duration>,
+    /// Whether the song is a podcast or an audiobook, whose lyrics are not looked up
+    pub spoken_word: bool,
+    /// Whether a provider knows the song to be instrumental
+    pub instrumental: bool,
+    /// When the lyrics were looked up
+    pub looked_up: Instant,
+    /// The lyrics with a machine translation, while it is still being translated
+    pub translation: Option<JoinHandle<Lrc>>,
+}
+
+/// What should currently be displayed for the active player
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "each flag is an independent state of the display"
+)]
+pub struct Snapshot {
+    /// Lyric lines active at the current position, one per lyric track
+    pub lines: Vec<(TrackKind, String)>,
+    /// Formatted metadata of the current song
+    pub metadata: Arc<str>,
+    /// Why no lyrics were found for the current song, if that is the case
+    pub diagnostic: Option<String>,
+    /// Whether the player is paused, in which case the lines are frozen
+    pub paused: bool,
+    /// Title of the current song
+    pub title: Option<String>,
+    /// Artists of the current song
+    pub artists: Vec<String>,
+    /// Playback position in the current song
+    pub position: Duration,
+    /// Position reported by the player, before compensating for latency and jitter
+    pub reported_position: Option<Duration>,
+    /// When the current lines started, if there are lyrics
+    pub line_start: Option<Duration>,
+    /// When the next line starts, if there is one
+    pub line_end: Option<Duration>,
+    /// Number of the current line of the original lyrics, starting at 1
+    pub line_index: Option<usize>,
+    /// Number of lines of the original lyrics
+    pub line_total: usize,
+    /// The line of the original lyrics before the current one
+    pub prev_line: Option<String>,
+    /// The line of the original lyrics after the current one
+    pub next_line: Option<String>,
+    /// Name of the provider the lyrics come from
+    pub source: Option<String>,
+    /// How well the lyrics cover the current song
+    pub quality: Option<Quality>,
+    /// MPRIS track id of the current song
+    pub track_id: Option<String>,
+    /// How much earlier than reported the position was taken to be, to compensate for latency
+    pub offset: Duration,
+    /// Length of the current song, if known
+    pub length: Option<Duration>,
+    /// Whether the current song is a podcast or an audiobook, whose lyrics are not looked up
+    pub spoken_word: bool,
+    /// Whether the current song is known to be instrumental
+    pub instrumental: bool,
+    /// Number of times the current song was played in a row, counting the current play
+    pub plays: u32,
+    /// Whether a provider or the translator failed its check at startup
+    pub degraded: bool,
+}
+
+/// Unit of the positions reported by a player
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionUnit {
+    /// The unit required by MPRIS
+    Microseconds,
+    /// Used by some broken players
+    Milliseconds,
+}
+
+impl PositionUnit {
+    /// Convert a position in this unit into microseconds
+    #[must_use]
+    pub fn to_micros(self, position: u64) -> u64 {
+        match self {
+            Self::Microseconds => position,
+            Self::Milliseconds => position.saturating_mul(1000),
+        }
+    }
+}
+
+/// Which player to follow when several are playing at the same time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SwitchPolicy {
+    /// Prefer players earlier in the priority list, then players whose song has lyrics. The
+    /// followed player is kept while it plays.
+    #[default]
+    PreferWithLyrics,
+    /// Prefer players earlier in the priority list, without looking up lyrics. The followed
+    /// player is kept while it plays.
+    PreferPriorityList,
+    /// Follow the player that started playing last, switching as soon as another one starts
+    MostRecentStart,
+    /// Keep following the selected player even while it is paused, until it goes away
+    ManualOnly,
+}
+
+/// How to handle `playerctld`, which mirrors the most recently active player under its own name
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlayerctldPolicy {
+    /// Ignore `playerctld` and follow the players it mirrors directly
+    #[default]
+    Ignore,
+    /// Only follow `playerctld` if it is running, ignoring the players it mirrors
+    Prefer,
+}
+
+/// Tunables of the player tracking
+#[derive(Clone, Debug)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "each flag is an independent option"
+)]
+pub struct Options {
+    /// The maximum time to sleep between metadata updates
+    pub max_sleep: Duration,
+    /// How long the metadata of a new song must stay unchanged before its lyrics are looked up
+    pub debounce: Duration,
+    /// Identities or bus names of preferred players, most preferred first
+    pub priority: Vec<String>,
+    /// Keep displaying the lyrics of a paused player instead of blanking the display
+    pub show_when_paused: bool,
+    /// Query providers concurrently, giving preferred providers this long to finish after
+    /// another one found lyrics. Providers are queried one after another if unset.
+    pub provider_grace: Option<Duration>,
+    /// Ignore differences between the reported and the expected position smaller than this,
+    /// and spread larger ones over time instead of jumping. Disabled if zero.
+    pub sync_tolerance: Duration,
+    /// Show each line this much before it starts, to make up for bars taking time to redraw
+    pub render_advance: Duration,
+    /// Never update more often than this, for bars that poll the module. Disabled if zero.
+    pub min_redraw_interval: Duration,
+    /// Always use the position reported by the player, which is polled again shortly before
+    /// each line, for players reporting accurate positions. Disables the smoothing.
+    pub trust_player_position: bool,
+    /// How long after the player reports a position it is heard, which is subtracted from it
+    pub audio_latency: Duration,
+    /// Keep showing the previous line at lines without text, instead of clearing the display
+    pub ignore_blank_lines: bool,
+    /// Rescale lyrics whose last line comes well after the end of the track to fit it, instead
+    /// of showing them unchanged when no better lyrics are found
+    pub rescale_mismatch: bool,
+    /// Rescale lyrics written for a version of the song whose length differs from the track by
+    /// at most this fraction, e.g. `0.1`, to the length of the track. Disabled if unset.
+    pub rescale_versions: Option<f64>,
+    /// Also refresh this long after a line starts and before the next one, so that transitions
+    /// between lines can be shown. Disabled if zero.
+    pub line_transition: Duration,
+    /// Whether to follow `playerctld` or the players it mirrors
+    pub playerctld: PlayerctldPolicy,
+    /// Which player to follow when several are playing
+    pub switch_policy: SwitchPolicy,
+    /// How long each line of plain text lyrics is shown. If unset, the lines are spread evenly
+    /// over the length of the song.
+    pub unsynced_line_duration: Option<Duration>,
+    /// Unit of the positions reported by players, by identity or bus name. The unit of other
+    /// players is detected from how fast their position advances.
+    pub position_units: Vec<(String, PositionUnit)>,
+    /// Identities or bus names of players whose reported position sometimes stops advancing
+    /// while playing. When it does, the position is extrapolated from the playback instead.
+    pub stale_position_players: Vec<String>,
+    /// Also refresh every second during this long before the first line, for countdowns.
+    /// Disabled if zero.
+    pub lead_in: Duration,
+    /// Songs longer than this are taken to be podcasts or audiobooks, whose lyrics are not
+    /// looked up. Songs whose genre says so are always treated that way.
+    pub spoken_word_length: Option<Duration>,
+    /// Look up the lyrics of a song again if none were found this long ago, as lyrics are often
+    /// published some time after a release. Never looked up again if unset.
+    pub requery_interval: Option<Duration>,
+    /// Never send lyrics to the translation service. The lyric providers are all local, so they
+    /// are still used.
+    pub offline: bool,
+    /// Which metadata entries are shown in the tooltip
+    pub metadata_filter: MetadataFilter,
+    /// Only show the version of multilingual lyrics written in this language (e.g. `zh`),
+    /// falling back to the original
+    pub preferred_language: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            max_sleep: Duration::from_secs(1),
+            debounce: Duration::from_millis(300),
+            priority: Vec::new(),
+            show_when_paused: false,
+            provider_grace: None,
+            sync_tolerance: Duration::ZERO,
+            render_advance: Duration::ZERO,
+            min_redraw_interval: Duration::ZERO,
+            trust_player_position: false,
+            audio_latency: Duration::ZERO,
+            ignore_blank_lines: false,
+            rescale_mismatch: false,
+            rescale_versions: None,
+            line_transition: Duration::ZERO,
+            playerctld: PlayerctldPolicy::default(),
+            switch_policy: SwitchPolicy::default(),
+            unsynced_line_duration: None,
+            position_units: Vec::new(),
+            stale_position_players: Vec::new(),
+            lead_in: Duration::ZERO,
+            spoken_word_length: None,
+            requery_interval: None,
+            offline: false,
+            metadata_filter: MetadataFilter::default(),
+            preferred_language: None,
+        }
+    }
+}
+
+pub struct State<B: Bus = PlayerFinder> {
+    /// Where the players are found
+    bus: B,
+    /// An active MPRIS player
+    player: Option<B::Player>,
+    /// The current song's data, with its key
+    song: Option<(u64, SongInfo)>,
+    /// A song that has just started playing and whose metadata may still be settling, with the
+    /// time it was first seen
+    pending: Option<(u64, Instant)>,
+    options: Options,
+    /// Lyric providers, in order of preference
+    providers: Arc<Vec<Box<dyn Provider>>>,
+    /// Service translating lyrics without a translation, if enabled
+    translator: Option<Arc<dyn Translator>>,
+    /// The song expected to play after the current one, with its lyrics already looked up
+    next_song: Option<(Metadata, SongInfo)>,
+    /// When the current song is expected to end, if it is playing and its length is known
+    predicted_end: Option<Instant>,
+    /// Number of consecutive failed queries of the current player
+    player_failures: u32,
+    /// Unit of the positions reported by the current player, once detected
+    position_unit: Option<PositionUnit>,
+    /// Song, raw position and time at which the detection of the position unit started
+    unit_probe: Option<(u64, u64, Instant)>,
+    /// The smoothed position of the current song, if smoothing is enabled
+    smoother: Option<(u64, Smoother)>,
+    /// Playback of the current song, if its player may report stale positions
+    stale: Option<(u64, StaleDetector)>,
+    /// Unique bus names of players that failed to answer, and until when they are ignored. A
+    /// player restarting under the same bus name gets another unique name, and is not ignored.
+    cooldowns: Vec<(String, Instant)>,
+    /// Bus names of playing players, and when they were first seen playing
+    started: Vec<(String, Instant)>,
+    /// When players were last listed to look for one that started playing
+    last_scan: Option<Instant>,
+    /// Until when the display of the previous player is kept, after switching players
+    switching: Option<Instant>,
+    /// When the state was last updated, on the monotonic and the wall clock
+    last_update: Option<(Instant, SystemTime)>,
+    /// The current song, how many times it was played in a row and its last position
+    plays: Option<(u64, u32, Duration)>,
+    /// Checks of the providers and the translator still running, returning whether one failed
+    warm_up: Option<JoinHandle<bool>>,
+    /// Whether a provider or the translator failed its check
+    degraded: bool,
+    /// When the providers and the translator were last checked
+    checked: Option<Instant>,
+    /// Lookup of the lyrics of players tied with the followed one, still running
+    tie_break: Option<TieBreak>,
+    /// Song whose lyrics are looked up again after a transient failure, with how long was waited
+    retrying: Option<(u64, Duration)>,
+}
+
+/// Lookup of the lyrics of the songs of tied players, running in the background so that the
+/// display is not blocked meanwhile
+struct TieBreak {
+    /// Bus name of the player followed until the lookup finishes
+    chosen: String,
+    /// The bus name and song key of the first other player whose song has lyrics, with them
+    lookup: JoinHandle<Option<(String, u64, SongInfo)>>,
+}
+
+/// Position differences larger than this are seeks, which are followed immediately
+const SEEK_THRESHOLD: Duration = Duration::from_secs(2);
+/// How long a position correction is spread over
+const SLEW_DURATION: Duration = Duration::from_secs(1);
+/// How often the position is updated while a correction is spread, so that the time of the next
+/// line is recomputed as the position converges
+const SLEW_STEP: Duration = Duration::from_millis(250);
+
+/// Position in a track, following the playback from the last known position.
+///
+/// The position advances with the playback rate while playing, and stays put while paused.
+#[derive(Clone, Copy, Debug)]
+pub struct PlaybackClock {
+    /// The position at `at`
+    position: Duration,
+    /// How fast the position advances while playing
+    rate: f64,
+    /// Whether the position advances
+    playing: bool,
+    /// When the position was last set
+    at: Instant,
+}
+
+impl PlaybackClock {
+    /// Start a clock playing from `position` at `at`
+    #[must_use]
+    pub fn new(position: Duration, rate: f64, at: Instant) -> Self {
+        Self {
+            position,
+            rate: Self::valid_rate(rate),
+            playing: true,
+            at,
+        }
+    }
+
+    /// Rates the player cannot actually play at are taken to be the normal rate
+    fn valid_rate(rate: f64) -> f64 {
+        if rate.is_finite() && rate >= 0.0 {
+            rate
+        } else {
+            1.0
+        }
+    }
+
+    /// Get the position at `at`
+    #[must_use]
+    pub fn now(&self, at: Instant) -> Duration {
+        if !self.playing {
+            return self.position;
+        }
+        let elapsed = at.saturating_duration_since(self.at).as_secs_f64();
+        self.position + Duration::try_from_secs_f64(elapsed * self.rate).unwrap_or_default()
+    }
+
+    /// Stop the position from advancing after `at`
+    pub fn pause(&mut self, at: Instant) {
+        self.seek(self.now(at), at);
+        self.playing = false;
+    }
+
+    /// Let the position advance again from `at`
+    pub fn resume(&mut self, at: Instant) {
+        if !self.playing {
+            self.at = at;
+            self.playing = true;
+        }
+    }
+
+    /// Move to `position` at `at`
+    pub fn seek(&mut self, position: Duration, at: Instant) {
+        self.position = position;
+        self.at = at;
+    }
+
+    /// Change how fast the position advances from `at`
+    pub fn set_rate(&mut self, rate: f64, at: Instant) {
+        self.seek(self.now(at), at);
+        self.rate = Self::valid_rate(rate);
+    }
+
+    /// Whether the position advances
+    #[must_use]
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+}
+
+/// Number of consecutive polls reporting the same position while playing after which the
+/// position is taken to be stale
+const STALE_POLLS: u32 = 3;
+
+/// Follows the playback of players whose reported position sometimes stops advancing while they
+/// play, e.g. mpv with some versions of its MPRIS script.
+#[derive(Clone, Copy, Debug)]
+struct StaleDetector {
+    /// The position last reported
+    reported: Duration,
+    /// Number of consecutive polls that reported it again
+    repeats: u32,
+    /// The playback since the position was first reported
+    clock: PlaybackClock,
+}
+
+impl StaleDetector {
+    fn new(reported: Duration, rate: f64, now: Instant) -> Self {
+        Self {
+            reported,
+            repeats: 0,
+            clock: PlaybackClock::new(reported, rate, now),
+        }
+    }
+
+    /// Get the position to use: the reported one, or the one expected from the playback since
+    /// it was first reported once it is stale
+    fn position(&mut self, reported: Duration, rate: f64, now: Instant) -> Duration {
+        if reported != self.reported {
+            *self = Self::new(reported, rate, now);
+            return reported;
+        }
+        self.repeats += 1;
+        if self.repeats < STALE_POLLS {
+            return reported;
+        }
+        if self.repeats == STALE_POLLS {
+            tracing::info!("player position stopped advancing, following the playback instead");
+        }
+        self.clock.now(now)
+    }
+}
+
+/// Smooths the jitter of the positions reported by a player
+struct Smoother {
+    /// The last smoothed position, following the playback since
+    clock: PlaybackClock,
+    /// Whether the last smoothed position was still being corrected towards the reported one
+    correcting: bool,
+}
+
+impl Smoother {
+    fn new(position: Duration, at: Instant) -> Self {
+        Self {
+            clock: PlaybackClock::new(position, 1.0, at),
+            correcting: false,
+        }
+    }
+
+    /// Get the smoothed position from the one reported at `now`, then follow the playback
+    /// status and rate reported with it.
+    ///
+    /// The position expected from the previous one and the elapsed time is kept if the reported
+    /// one is within `tolerance` of it. Larger differences are corrected gradually over
+    /// [`SLEW_DURATION`], unless they are so large that the user must have seeked.
+    fn smooth(
+        &mut self,
+        reported: Duration,
+        rate: f64,
+        paused: bool,
+        tolerance: Duration,
+        now: Instant,
+    ) -> Duration {
+        let elapsed = now.saturating_duration_since(self.clock.at).as_secs_f64();
+        let expected = self.clock.now(now).as_secs_f64();
+        let error = reported.as_secs_f64() - expected;
+        let position = if error.abs() <= tolerance.as_secs_f64() {
+            expected
+        } else if error.abs() >= SEEK_THRESHOLD.max(tolerance).as_secs_f64() {
+            reported.as_secs_f64()
+        } else {
+            expected + error * (elapsed / SLEW_DURATION.as_secs_f64()).min(1.0)
+        };
+        self.correcting = (reported.as_secs_f64() - position).abs() > tolerance.as_secs_f64();
+        self.clock.seek(
+            Duration::try_from_secs_f64(position).unwrap_or_default(),
+            now,
+        );
+        self.clock.set_rate(rate, now);
+        if paused {
+            self.clock.pause(now);
+        } else {
+            self.clock.resume(now);
+        }
+        self.clock.now(now)
+    }
+}
+
+/// MPRIS key of the album name
+const ALBUM_KEY: &str = "xesam:album";
+/// MPRIS key of the title
+const TITLE_KEY: &str = "xesam:title";
+/// MPRIS key of the artists
+const ARTIST_KEY: &str = "xesam:artist";
+
+/// Which metadata entries are shown in the tooltip, by MPRIS key (e.g. `xesam:title`). Patterns
+/// may contain `*`, matching any text, and are matched ignoring case.
+#[derive(Clone, Debug, Default)]
+pub struct MetadataFilter {
+    /// Entries not shown
+    pub skip: Vec<String>,
+    /// If not empty, only these entries are shown, including ones not shown by default
+    pub only: Vec<String>,
+}
+
+impl MetadataFilter {
+    /// Whether the entry with the given key is shown
+    fn shows(&self, key: &str) -> bool {
+        !self.skip.iter().any(|pattern| glob_match(pattern, key))
+            && (self.only.is_empty() || self.only.iter().any(|pattern| glob_match(pattern, key)))
+    }
+}
+
+/// Whether the text matches the pattern, where `*` matches any text, ignoring case
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.to_lowercase(), text.to_lowercase());
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` in the pattern
+        return rest.is_empty();
+    };
+    for part in middle {
+        let Some(at) = rest.find(part) else {
+            return false;
+        };
+        rest = rest.get(at + part.len()..).unwrap_or_default();
+    }
+    rest.ends_with(last)
+}
+
+/// Format a metadata value for display, if it is not a map or of an unsupported type
+fn format_value(value: &mpris::MetadataValue) -> Option<String> {
+    use mpris::MetadataValue as V;
+    Some(match value {
+        V::String(s) => s.clone(),
+        V::I16(n) => n.to_string(),
+        V::I32(n) => n.to_string(),
+        V::I64(n) => n.to_string(),
+        V::U8(n) => n.to_string(),
+        V::U16(n) => n.to_string(),
+        V::U32(n) => n.to_string(),
+        V::U64(n) => n.to_string(),
+        V::F64(n) => n.to_string(),
+        V::Bool(b) => b.to_string(),
+        V::Array(values) => values
+            .iter()
+            .filter_map(format_value)
+            .collect::<Vec<_>>()
+            .join(", "),
+        V::Map(_) | V::Unsupported => return None,
+    })
+}
+
+impl SongInfo {
+    /// Format the metadata for display
+    fn format_metadata(metadata: &Metadata, filter: &MetadataFilter) -> String {
+        let mut result = String::new();
+        let mut push = |label: &str, value: &str| {
+            result.push_str(label);
+            result.push_str(": ");
+            result.push_str(value);
+            result.push('\n');
+        };
+        if let Some(name) = metadata.album_name().filter(|_| filter.shows(ALBUM_KEY)) {
+            push("album", name);
+        }
+        if let Some(name) = metadata.tags().title().filter(|_| filter.shows(TITLE_KEY)) {
+            push("title", name);
+        }
+        if let Some(name) = metadata
+            .tags()
+            .artists()
+            .filter(|_| filter.shows(ARTIST_KEY))
+        {
+            push("artists", &name.join(", "));
+        }
+        if !filter.only.is_empty() {
+            // Other entries are only shown when asked for
+            let mut others: Vec<_> = metadata
+                .iter()
+                .filter(|(key, _)| ![ALBUM_KEY, TITLE_KEY, ARTIST_KEY].contains(key))
+                .filter(|(key, _)| filter.shows(key))
+                .filter_map(|(key, value)| Some((key, format_value(value)?)))
+                .collect();
+            others.sort_unstable();
+            for (key, value) in others {
+                push(key, &value);
+            }
+        }
+        result
+    }
+    /// Give plain text lyrics estimated time tags so that they can be followed line by line
+    fn synchronise(lyrics: Lrc, metadata: &Metadata, options: &Options) -> Lrc {
+        if lyrics.is_synced() {
+            return lyrics;
+        }
+        let lines = u32::try_from(lyrics.line_count())
+            .unwrap_or(u32::MAX)
+            .max(1);
+        let interval = options
+            .unsynced_line_duration
+            .or_else(|| metadata.tags().length().map(|length| length / lines))
+            .unwrap_or(DEFAULT_UNSYNCED_LINE_DURATION);
+        tracing::debug!(
+            "lyrics are not synchronised, showing each line for {:?}",
+            interval
+        );
+        lyrics.spread(interval)
+    }
+
+    /// Keep the preferred language of the lyrics, and give them time tags if they have none
+    fn prepare(lyrics: Lrc, metadata: &Metadata, options: &Options) -> Lrc {
+        let lyrics = match &options.preferred_language {
+            Some(language) => lyrics.select_language(language),
+            None => lyrics,
+        };
+        Self::synchronise(lyrics, metadata, options)
+    }
+
+    /// Translate the lyrics in the background, unless offline. The lyrics are shown untranslated
+    /// until the translation is done.
+    fn translate(
+        lyrics: &Lrc,
+        metadata: &Metadata,
+        translator: Option<&Arc<dyn Translator>>,
+        options: &Options,
+    ) -> Option<JoinHandle<Lrc>> {
+        translator.filter(|_| !options.offline).map(|translator| {
+            let (translator, lyrics) = (Arc::clone(translator), lyrics.clone());
+            let (metadata, options) = (metadata.clone(), options.clone());
+            thread::spawn(move || {
+                let lyrics = provider::translate(translator.as_ref(), lyrics);
+                Self::prepare(lyrics, &metadata, &options)
+            })
+        })
+    }
+
+    /// Show the translated lyrics once they are translated
+    fn finish_translation(&mut self) {
+        if self
+            .translation
+            .as_ref()
+            .is_some_and(JoinHandle::is_finished)
+        {
+            if let Some(Ok(lyrics)) = self.translation.take().map(JoinHandle::join) {
+                self.lyrics = Some(lyrics);
+            }
+        }
+    }
+
+    /// Whether the lookup failed transiently long enough ago to be tried again
+    fn retry_due(&self) -> bool {
+        self.retry
+            .is_some_and(|delay| self.looked_up.elapsed() >= delay)
+    }
+
+    /// Wait longer before the next retry if the lookup failed again after waiting `waited`
+    fn back_off(mut self, waited: Option<Duration>) -> Self {
+        if let (Some(delay), Some(waited)) = (&mut self.retry, waited) {
+            *delay = (waited * 2).min(MAX_LOOKUP_RETRY_DELAY);
+        }
+        self
+    }
+
+    /// Whether no lyrics were found long enough ago that they may have been published since
+    fn requery_due(&self, interval: Option<Duration>) -> bool {
+        self.lyrics.is_none()
+            && !self.spoken_word
+            && !self.instrumental
+            && interval.is_some_and(|interval| self.looked_up.elapsed() >= interval)
+    }
+
+    /// Create a new ``SongInfo`` from metadata
+    pub fn new(
+        metadata: &Metadata,
+        providers: &Arc<Vec<Box<dyn Provider>>>,
+        translator: Option<&Arc<dyn Translator>>,
+        options: &Options,
+    ) -> Self {
+        if is_spoken_word(metadata, options.spoken_word_length) {
+            tracing::info!("not looking up lyrics of a podcast or audiobook");
+            return Self {
+                metadata: Self::format_metadata(metadata, &options.metadata_filter).into(),
+                lyrics: None,
+                source: None,
+                quality: None,
+                diagnostic: None,
+                retry: None,
+                spoken_word: true,
+                instrumental: false,
+                looked_up: Instant::now(),
+                translation: None,
+            };
+        }
+        metrics::LOOKUPS.inc();
+        let result = match options.provider_grace {
+            Some(grace) => provider::resolve_parallel(providers, metadata, grace),
+            None => provider::resolve(providers, metadata),
+        };
+        let formatted: Arc<str> = Self::format_metadata(metadata, &options.metadata_filter).into();
+        match result {
+            Ok(resolved) => {
+                let lyrics = if options.ignore_blank_lines {
+                    resolved.lyrics.without_blank_lines()
+                } else {
+                    resolved.lyrics
+                };
+                let lyrics = if options.rescale_mismatch {
+                    provider::fit_to_length(lyrics, metadata.tags().length())
+                } else {
+                    lyrics
+                };
+                let lyrics = match options.rescale_versions {
+                    Some(tolerance) => {
+                        provider::fit_version(lyrics, metadata.tags().length(), tolerance)
+                    }
+                    None => lyrics,
+                };
+                let translation = Self::translate(&lyrics, metadata, translator, options);
+                Self {
+                    metadata: formatted,
+                    lyrics: Some(Self::prepare(lyrics, metadata, options)),
+                    source: Some(resolved.provider),
+                    quality: Some(resolved.quality),
+                    diagnostic: None,
+                    retry: None,
+                    spoken_word: false,
+                    instrumental: false,
+                    looked_up: Instant::now(),
+                    translation,
+                }
+            }
+            Err(e) if e.is_instrumental() => Self {
+                metadata: formatted,
+                lyrics: None,
+                source: None,
+                quality: None,
+                diagnostic: None,
+                retry: None,
+                spoken_word: false,
+                instrumental: true,
+                looked_up: Instant::now(),
+                translation: None,
+            },
+            Err(e) => {
+                tracing::warn!("Failed to resolve lyrics: {}", e);
+                Self {
+                    metadata: formatted,
+                    lyrics: None,
+                    source: None,
+                    quality: None,
+                    diagnostic: Some(e.to_string()),
+                    retry: e.is_transient().then_some(LOOKUP_RETRY_DELAY),
+                    spoken_word: false,
+                    instrumental: false,
+                    looked_up: Instant::now(),
+                    translation: None,
+                }
+            }
+        }
+    }
+}
+
+/// How long to wait before looking up lyrics again after a transient failure, doubled after each
+/// failed retry up to [`MAX_LOOKUP_RETRY_DELAY`]
+const LOOKUP_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Longest wait between two lookups of the lyrics of a song failing transiently
+const MAX_LOOKUP_RETRY_DELAY: Duration = Duration::from_mins(5);
+/// How long to wait before querying a player again after it failed to answer
+const PLAYER_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// How long the display of the previous player is kept after switching to another one, while
+/// the lyrics of the new one are looked up
+const SWITCH_HOLD: Duration = Duration::from_secs(1);
+/// How much more wall-clock time than monotonic time must pass between two updates for the
+/// system to be taken to have been suspended in between
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(5);
+/// Number of consecutive failed queries after which a player is given up on
+const MAX_PLAYER_FAILURES: u32 = 5;
+
+/// How long each line of plain text lyrics is shown if the length of the song is unknown
+pub const DEFAULT_UNSYNCED_LINE_DURATION: Duration = Duration::from_secs(5);
+
+/// How far past the end of the track a reported position is still believable
+const POSITION_OVERSHOOT: Duration = Duration::from_secs(10);
+
+/// Keep the display unchanged after switching players until the new player has lines to show,
+/// so that the module is not blanked in between. Returns how long to sleep while the display is
+/// kept, or `None` once the lines are shown or the display has been kept until `deadline`.
+fn hold_during_switch(
+    (snapshot, sleep): &(Option<Snapshot>, Duration),
+    deadline: Instant,
+    now: Instant,
+) -> Option<Duration> {
+    match snapshot {
+        Some(snapshot) if snapshot.lines.is_empty() && now < deadline => {
+            Some((*sleep).min(deadline - now))
+        }
+        Some(_) => None,
+        None => Some(*sleep),
+    }
+}
+
+/// Whether the system was suspended between two updates, given the time elapsed on the monotonic
+/// clock, which stops during suspend, and on the wall clock, which does not
+fn suspended(monotonic: Duration, wall: Option<Duration>) -> bool {
+    wall.is_some_and(|wall| wall.saturating_sub(monotonic) > SUSPEND_THRESHOLD)
+}
+
+/// How close to the end a song must have been, and how close to the start it must be, for the
+/// jump between the two to be taken as the song playing again
+const REPLAY_MARGIN: Duration = Duration::from_secs(5);
+
+/// Whether a song played again, e.g. on repeat, going from `previous` to `position`
+fn replayed(previous: Duration, position: Duration, length: Option<Duration>) -> bool {
+    length.is_some_and(|length| {
+        previous + REPLAY_MARGIN >= length && position < REPLAY_MARGIN && position < previous
+    })
+}
+
+/// Turn the position reported by a player into a usable one.
+///
+/// Some players briefly report negative positions during track transitions, which reach us as
+/// huge unsigned values, and some report positions far beyond the length of the track. Both are
+/// treated as the start of the track.
+fn sanitize_position(raw_micros: u64, length: Option<Duration>) -> Duration {
+    if i64::try_from(raw_micros).is_err() {
+        tracing::warn!("player reported a negative position, clamping to 0");
+        return Duration::ZERO;
+    }
+    let position = Duration::from_micros(raw_micros);
+    if let Some(length) = length.filter(|l| !l.is_zero()) {
+        if position > length + POSITION_OVERSHOOT {
+            tracing::warn!(
+                "player reported position {:?} beyond track length {:?}, treating as 0",
+                position,
+                length
+            );
+            return Duration::ZERO;
+        }
+    }
+    position
+}
+
+/// How long before the end of a song the lyrics of the next one are looked up
+const PREFETCH_WINDOW: Duration = Duration::from_secs(10);
+/// How long the next song is shown after the predicted end of the current one while waiting for
+/// the player to update its metadata
+const GAPLESS_TIMEOUT: Duration = Duration::from_secs(3);
+/// Timing slack when deciding whether a song has reached its end
+const BOUNDARY_TOLERANCE: Duration = Duration::from_millis(200);
+/// How long before the next line the position is polled again when trusting the player
+const BOUNDARY_LOOKAHEAD: Duration = Duration::from_millis(100);
+
+/// The lyrics at a position in a song
+struct LyricsAt {
+    /// The active line of each track
+    lines: Vec<(TrackKind, String)>,
+    /// When the active lines started
+    line_start: Option<Duration>,
+    /// When the next line starts
+    line_end: Option<Duration>,
+    /// Number of the current line of the original lyrics, starting at 1
+    line_index: Option<usize>,
+    /// Number of lines of the original lyrics
+    line_total: usize,
+    /// The lines of the original lyrics around the current one
+    prev_line: Option<String>,
+    next_line: Option<String>,
+    /// How long to sleep until the lines or their transition classes change
+    sleep: Duration,
+}
+
+/// Get the lyric lines active at a position, and how long until they change
+fn lines_at(song: &SongInfo, position: TimeTag, rate: f64, options: &Options) -> LyricsAt {
+    let position = TimeTag(position.0 + options.render_advance);
+    let (lines, line_start, line_end) = song
+        .lyrics
+        .as_ref()
+        .map(|l| {
+            let lines = l
+                .lines_at(position)
+                .map(|(kind, line)| (kind, line.text.clone()))
+                .collect();
+            (lines, l.current_timetag(position), l.next_timetag(position))
+        })
+        .unwrap_or_default();
+    let original = song.lyrics.as_ref().and_then(|l| l.tracks().first());
+    let line_index = original
+        .and_then(|track| track.index_at(position))
+        .map(|i| i + 1);
+    let line_total = original.map_or(0, |track| track.lines.len());
+    let [prev_line, _, next_line] = original
+        .map(|track| track.window_at(position))
+        .unwrap_or_default()
+        .map(|line| line.map(|line| line.text.clone()));
+
+    // Sleeping at most `max_sleep` also picks up rate changes happening mid-line
+    let mut sleep = options.max_sleep;
+    // Also wake up when the transition classes of the lines change
+    let transition = options.line_transition;
+    let wakeups = [
+        line_end,
+        line_end.map(|t| TimeTag(t.0.saturating_sub(transition))),
+        line_start.map(|t| TimeTag(t.0 + transition)),
+        // Ask the player for its position again just before the next line, instead of relying
+        // on the position extrapolated since the last poll
+        line_end
+            .filter(|_| options.trust_player_position)
+            .map(|t| TimeTag(t.0.saturating_sub(BOUNDARY_LOOKAHEAD))),
+    ];
+    // And every second of the countdown before the first line
+    let lead_in = line_end
+        .filter(|_| line_start.is_none())
+        .into_iter()
+        .flat_map(|first| {
+            (1..=options.lead_in.as_secs())
+                .map(move |secs| TimeTag(first.0.saturating_sub(Duration::from_secs(secs))))
+        });
+    for wakeup in wakeups.into_iter().flatten().chain(lead_in) {
+        if wakeup > position {
+            sleep = sleep.min(scale_by_rate(wakeup.0.saturating_sub(position.0), rate));
+        }
+    }
+    LyricsAt {
+        lines,
+        line_start: line_start.map(|t| t.0),
+        line_end: line_end.map(|t| t.0),
+        line_index,
+        line_total,
+        prev_line,
+        next_line,
+        sleep,
+    }
+}
+
+/// Genres of podcasts and audiobooks, matched case-insensitively within `xesam:genre`
+const SPOKEN_WORD_GENRES: [&str; 4] = ["podcast", "audiobook", "audio book", "spoken word"];
+
+/// Whether a song is a podcast or an audiobook, from its genre or its length
+fn is_spoken_word(metadata: &Metadata, max_length: Option<Duration>) -> bool {
+    let spoken_genre = metadata.tags().genres().iter().any(|genre| {
+        let genre = genre.to_lowercase();
+        SPOKEN_WORD_GENRES
+            .iter()
+            .any(|spoken| genre.contains(spoken))
+    });
+    let too_long = max_length
+        .zip(metadata.tags().length())
+        .is_some_and(|(max, length)| length > max);
+    spoken_genre || too_long
+}
+
+/// Identify a song by the metadata its lyrics depend on.
+///
+/// Players re-send identical metadata periodically, and some (e.g. browsers) have no URL, so the
+/// URL alone cannot tell songs apart.
+fn song_key(metadata: &Metadata) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    metadata.tags().track_id().hash(&mut hasher);
+    metadata.tags().url().hash(&mut hasher);
+    metadata.tags().title().hash(&mut hasher);
+    metadata.tags().artists().hash(&mut hasher);
+    metadata.tags().as_text().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Maximum number of players whose lyrics are looked up at the same time when choosing between
+/// them
+const MAX_PARALLEL_LOOKUPS: usize = 4;
+/// How often a lookup running in the background is checked for completion
+const LOOKUP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Look up the lyrics of the current songs of tied players, returning the first player whose
+/// song has lyrics, by bus name, with its song key and lyrics.
+///
+/// The lyrics are not translated, as most of the songs are never shown.
+fn first_with_lyrics(
+    tied: &[(String, Metadata)],
+    providers: &Arc<Vec<Box<dyn Provider>>>,
+    options: &Options,
+) -> Option<(String, u64, SongInfo)> {
+    for batch in tied.chunks(MAX_PARALLEL_LOOKUPS) {
+        let songs: Vec<_> = thread::scope(|scope| {
+            let lookups: Vec<_> = batch
+                .iter()
+                .map(|(_, metadata)| {
+                    scope.spawn(|| SongInfo::new(metadata, providers, None, options))
+                })
+                .collect();
+            lookups
+                .into_iter()
+                .map(|lookup| lookup.join().ok())
+                .collect()
+        });
+        for ((bus_name, metadata), song) in batch.iter().zip(songs) {
+            if let Some(song) = song.filter(|song| song.lyrics.is_some()) {
+                return Some((bus_name.clone(), song_key(metadata), song));
+            }
+        }
+    }
+    None
+}
+
+/// Maximum number of tied players whose lyrics are looked up when choosing which one to follow
+const MAX_CANDIDATES: usize = 8;
+/// How long a player that failed to answer is ignored when choosing which one to follow
+const PLAYER_COOLDOWN: Duration = Duration::from_secs(30);
+/// How often players are listed to find one that started playing, when following the latest
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+/// How many times listing the players is attempted before giving up
+const LIST_ATTEMPTS: u32 = 3;
+/// How long to wait before listing the players again after failing to
+const LIST_RETRY_DELAY: Duration = Duration::from_millis(200);
+/// How often the providers and the translator are checked again while one fails, so that they
+/// are no longer reported as degraded once they work
+const RECHECK_INTERVAL: Duration = Duration::from_mins(5);
+
+/// Run an operation until it succeeds, at most `attempts` times, waiting `delay` between attempts
+fn retry<T, E: core::fmt::Display>(
+    attempts: u32,
+    delay: Duration,
+    mut operation: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut attempt = 1;
+    loop {
+        match operation() {
+            Err(e) if attempt < attempts => {
+                tracing::debug!("attempt {} of {} failed: {}", attempt, attempts, e);
+                attempt += 1;
+                thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Track id players publish while they have no current track
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// Whether the metadata describes an actual track.
+///
+/// Players between tracks publish the `NoTrack` sentinel id, or metadata without anything to
+/// identify a song by, which must not be looked up as a new song.
+fn has_track(metadata: &Metadata) -> bool {
+    let tags = metadata.tags();
+    if tags.track_id() == Some(NO_TRACK) {
+        return false;
+    }
+    let non_empty = |s: Option<&str>| s.is_some_and(|s| !s.trim().is_empty());
+    non_empty(tags.title()) || non_empty(tags.url()) || non_empty(tags.as_text())
+}
+
+/// How long playback is observed before guessing the unit of the positions of a player
+const UNIT_PROBE_DURATION: Duration = Duration::from_secs(5);
+
+/// Guess the unit of positions from how much they advanced during some time of playback
+fn detect_unit(advance: u64, elapsed: Duration, rate: f64) -> Option<PositionUnit> {
+    let expected = scale_by_rate(elapsed, 1.0 / rate).as_secs_f64() * 1_000_000.0;
+    #[allow(
+        clippy::cast_precision_loss,
+        reason = "positions are far below 2^52 microseconds"
+    )]
+    let ratio = advance as f64 / expected;
+    if (0.5..2.0).contains(&ratio) {
+        Some(PositionUnit::Microseconds)
+    } else if (0.0005..0.002).contains(&ratio) {
+        Some(PositionUnit::Milliseconds)
+    } else {
+        None
+    }
+}
+
+/// Rank a player for selection, lower ranks being preferred.
+///
+/// Playing players come before paused ones. Under [`SwitchPolicy::MostRecentStart`], the player
+/// that started playing last comes first, and the priority only breaks ties.
+fn switch_rank(
+    policy: SwitchPolicy,
+    status_rank: u8,
+    started: Option<Instant>,
+    priority: usize,
+    now: Instant,
+) -> (u8, Duration, usize) {
+    let since_start = match (policy, started) {
+        (SwitchPolicy::MostRecentStart, Some(started)) => now.saturating_duration_since(started),
+        _ => Duration::ZERO,
+    };
+    (status_rank, since_start, priority)
+}
+
+/// Bus name of `playerctld`, without the MPRIS prefix
+const PLAYERCTLD: &str = "playerctld";
+
+/// Remove players that mirror another one, so that the same song is not followed twice.
+///
+/// Either `playerctld` or the players it mirrors are dropped depending on the policy, then
+/// players with the same identity playing the same song are only kept once.
+fn dedup_players<P: MediaPlayer>(players: Vec<P>, policy: PlayerctldPolicy) -> Vec<P> {
+    let (playerctld, others): (Vec<_>, Vec<_>) = players
+        .into_iter()
+        .partition(|p| p.bus_name_player_name_part() == PLAYERCTLD);
+    let players = match policy {
+        PlayerctldPolicy::Prefer if !playerctld.is_empty() => playerctld,
+        PlayerctldPolicy::Prefer | PlayerctldPolicy::Ignore => others,
+    };
+    let mut seen = Vec::new();
+    players
+        .into_iter()
+        .filter(|player| {
+            let Ok(metadata) = player.get_metadata() else {
+                return true;
+            };
+            let key = (player.identity().to_owned(), song_key(&metadata));
+            if seen.contains(&key) {
+                tracing::debug!("ignoring duplicate player {}", player.bus_name());
+                return false;
+            }
+            seen.push(key);
+            true
+        })
+        .collect()
+}
+
+/// Get the position of a player in the priority list, or the length of the list if absent.
+fn priority_rank(priority: &[String], identity: &str, bus_name: &str) -> usize {
+    priority
+        .iter()
+        .position(|p| p.eq_ignore_ascii_case(identity) || p.eq_ignore_ascii_case(bus_name))
+        .unwrap_or(priority.len())
+}
+
+/// Convert a duration of track time into wall-clock time at the given playback rate
+fn scale_by_rate(duration: Duration, rate: f64) -> Duration {
+    if rate.is_finite() && rate > 0.0 {
+        duration.div_f64(rate)
+    } else {
+        duration
+    }
+}
+
+impl State {
+    /// Create a new, empty player state, following the players of the `DBus` session and looking
+    /// up lyrics from the providers in order
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `DBus` connection cannot be established.
+    #[must_use]
+    pub fn new(options: Options, providers: Vec<Box<dyn Provider>>) -> Self {
+        Self::with_bus(PlayerFinder::new().unwrap(), options, providers)
+    }
+}
+
+impl<B: Bus> State<B> {
+    /// Create a new, empty player state, following the players found on `bus`
+    #[must_use]
+    pub fn with_bus(bus: B, options: Options, providers: Vec<Box<dyn Provider>>) -> Self {
+        Self {
+            bus,
+            player: None,
+            song: None,
+            pending: None,
+            options,
+            providers: Arc::new(providers),
+            translator: None,
+            next_song: None,
+            predicted_end: None,
+            player_failures: 0,
+            position_unit: None,
+            unit_probe: None,
+            smoother: None,
+            stale: None,
+            switching: None,
+            last_update: None,
+            plays: None,
+            cooldowns: Vec::new(),
+            started: Vec::new(),
+            last_scan: None,
+            warm_up: None,
+            degraded: false,
+            checked: None,
+            tie_break: None,
+            retrying: None,
+        }
+    }
+
+    /// Translate lyrics that have no translation of their own with the given service
+    #[must_use]
+    pub fn with_translator(mut self, translator: Box<dyn Translator>) -> Self {
+        self.translator = Some(Arc::from(translator));
+        self
+    }
+
+    /// Check the providers and the translator in the background, so that a misconfiguration is
+    /// logged at startup and shown in the snapshots, rather than found at the first lookup.
+    /// The translation service is not contacted while offline. The checks are run again every
+    /// [`RECHECK_INTERVAL`] while one fails.
+    pub fn warm_up(&mut self) {
+        self.checked = Some(Instant::now());
+        let providers = Arc::clone(&self.providers);
+        let translator = self.translator.clone().filter(|_| !self.options.offline);
+        self.warm_up = Some(thread::spawn(move || {
+            let mut degraded = false;
+            for provider in providers.iter() {
+                if let Err(e) = provider.check() {
+                    tracing::error!("provider {} cannot work: {}", provider.name(), e);
+                    degraded = true;
+                }
+            }
+            if let Some(translator) = translator {
+                if let Err(e) = translator.check() {
+                    tracing::error!("translator {} cannot work: {}", translator.name(), e);
+                    degraded = true;
+                }
+            }
+            degraded
+        }));
+    }
+
+    /// Switch between translating lyrics and never contacting the translation service, for the
+    /// songs looked up from now on
+    pub fn set_offline(&mut self, offline: bool) {
+        tracing::info!("offline mode {}", if offline { "on" } else { "off" });
+        self.options.offline = offline;
+        if self.degraded && self.warm_up.is_none() {
+            // The failing check may have been the translator's
+            self.warm_up();
+        }
+    }
+
+    /// What to display while the metadata of a new song is settling
+    fn settling(&self, metadata: &Metadata, position: Duration) -> Snapshot {
+        let formatted = SongInfo::format_metadata(metadata, &self.options.metadata_filter);
+        Self::track_snapshot(metadata, position, formatted.into())
+    }
+
+    /// Snapshot of the current song without lyrics
+    fn track_snapshot(metadata: &Metadata, position: Duration, formatted: Arc<str>) -> Snapshot {
+        Snapshot {
+            metadata: formatted,
+            title: metadata.tags().title().map(str::to_owned),
+            artists: metadata
+                .tags()
+                .artists()
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+            position,
+            length: metadata.tags().length(),
+            track_id: metadata.tags().track_id().map(str::to_owned),
+            ..Snapshot::default()
+        }
+    }
+
+    /// Pick the player the user is most likely listening to.
+    ///
+    /// Players are ranked by playback status, then by the configured priority. If several players
+    /// are tied, the first one is picked while the lyrics of the others are looked up in the
+    /// background, and the first of them whose song has lyrics is followed instead if the picked
+    /// one has none. Only playing players are considered, and paused ones if `allow_paused` is
+    /// set.
+    fn select_player(&mut self, allow_paused: bool) -> Result<Option<B::Player>, DBusError> {
+        let players = self.list_players()?;
+        let now = Instant::now();
+        self.cooldowns.retain(|(_, until)| *until > now);
+        let players = dedup_players(players, self.options.playerctld);
+        let players: Vec<_> = players
+            .into_iter()
+            .filter(|player| {
+                !self
+                    .cooldowns
+                    .iter()
+                    .any(|(unique_name, _)| unique_name == player.unique_name())
+            })
+            .map(|player| {
+                let priority = priority_rank(
+                    &self.options.priority,
+                    player.identity(),
+                    player.bus_name_player_name_part(),
+                );
+                (priority, player)
+            })
+            .collect();
+        let mut failed = Vec::new();
+        let mut playing = Vec::new();
+        let mut candidates: Vec<_> = players
+            .into_iter()
+            .filter_map(|(priority, player)| {
+                let status = player
+                    .get_playback_status()
+                    .map_err(|e| {
+                        tracing::warn!(
+                            "Failed to get status of {}, ignoring it for {:?}: {}",
+                            player.bus_name(),
+                            PLAYER_COOLDOWN,
+                            e
+                        );
+                        failed.push((player.unique_name().to_owned(), now + PLAYER_COOLDOWN));
+                    })
+                    .ok()?;
+                let status_rank = match status {
+                    PlaybackStatus::Playing => 0,
+                    PlaybackStatus::Paused if allow_paused => 1,
+                    PlaybackStatus::Paused | PlaybackStatus::Stopped => return None,
+                };
+                let started = (status == PlaybackStatus::Playing).then(|| {
+                    let started = self
+                        .started
+                        .iter()
+                        .find(|(bus_name, _)| bus_name == player.bus_name())
+                        .map_or(now, |(_, started)| *started);
+                    playing.push((player.bus_name().to_owned(), started));
+                    started
+                });
+                let rank = switch_rank(
+                    self.options.switch_policy,
+                    status_rank,
+                    started,
+                    priority,
+                    now,
+                );
+                Some((rank, player))
+            })
+            .collect();
+        self.cooldowns.extend(failed);
+        self.started = playing;
+        metrics::ACTIVE_PLAYERS.set(candidates.len() as u64);
+        candidates.sort_by_key(|(rank, _)| *rank);
+        let Some(best) = candidates.first().map(|(rank, _)| *rank) else {
+            return Ok(None);
+        };
+        // Only look up the lyrics of the most preferred tied players when many are running (e.g.
+        // one per browser tab)
+        let tied = candidates
+            .iter()
+            .take_while(|(rank, _)| *rank == best)
+            .count()
+            .min(MAX_CANDIDATES);
+        let mut candidates = candidates.into_iter().map(|(_, player)| player);
+        let chosen = candidates.next();
+        if let Some(chosen) = chosen
+            .as_ref()
+            .filter(|_| tied > 1 && self.options.switch_policy == SwitchPolicy::PreferWithLyrics)
+        {
+            self.start_tie_break(chosen, candidates.take(tied - 1));
+        }
+        Ok(chosen)
+    }
+
+    /// Look up the lyrics of the current songs of the players tied with the chosen one in the
+    /// background
+    fn start_tie_break(&mut self, chosen: &B::Player, others: impl Iterator<Item = B::Player>) {
+        // D-Bus proxies cannot be sent to another thread, but the metadata of their songs can
+        let tied: Vec<_> = others
+            .filter_map(|player| Some((player.bus_name().to_owned(), player.get_metadata().ok()?)))
+            .collect();
+        if tied.is_empty() {
+            return;
+        }
+        let providers = Arc::clone(&self.providers);
+        let options = self.options.clone();
+        self.tie_break = Some(TieBreak {
+            chosen: chosen.bus_name().to_owned(),
+            lookup: thread::spawn(move || first_with_lyrics(&tied, &providers, &options)),
+        });
+    }
+
+    /// Follow the tied player found to have lyrics once the lookup finished, if the chosen player
+    /// is still followed and its song has none
+    fn finish_tie_break(&mut self) -> Result<(), DBusError> {
+        if !self
+            .tie_break
+            .as_ref()
+            .is_some_and(|tie_break| tie_break.lookup.is_finished())
+        {
+            return Ok(());
+        }
+        let Some(tie_break) = self.tie_break.take() else {
+            return Ok(());
+        };
+        let Ok(Some((bus_name, key, song))) = tie_break.lookup.join() else {
+            return Ok(());
+        };
+        if self.player.as_ref().map(MediaPlayer::bus_name) != Some(tie_break.chosen.as_str())
+            || self
+                .song
+                .as_ref()
+                .is_some_and(|(_, song)| song.lyrics.is_some())
+        {
+            return Ok(());
+        }
+        // The player must still be playing the song whose lyrics were found
+        let Some(player) = self.list_players()?.into_iter().find(|player| {
+            player.bus_name() == bus_name
+                && player
+                    .get_metadata()
+                    .is_ok_and(|metadata| song_key(&metadata) == key)
+        }) else {
+            return Ok(());
+        };
+        tracing::debug!("selected {} as it has lyrics", bus_name);
+        self.set_player(player, Some(song));
+        Ok(())
+    }
+
+    /// Whether the players are due to be listed again, which they are at most every
+    /// [`SCAN_INTERVAL`]
+    fn scan_due(&mut self) -> bool {
+        let now = Instant::now();
+        if self
+            .last_scan
+            .is_some_and(|at| now.duration_since(at) < SCAN_INTERVAL)
+        {
+            return false;
+        }
+        self.last_scan = Some(now);
+        true
+    }
+
+    /// Find another player that is playing, while the followed one is paused.
+    ///
+    /// Players are listed at most every [`SCAN_INTERVAL`].
+    fn playing_elsewhere(&mut self) -> Result<Option<B::Player>, DBusError> {
+        if !self.scan_due() {
+            return Ok(None);
+        }
+        self.select_player(false)
+    }
+
+    /// Switch to the player that started playing last, if it is not the followed one.
+    ///
+    /// Players are listed at most every [`SCAN_INTERVAL`].
+    fn follow_latest_start(&mut self) -> Result<(), DBusError> {
+        if !self.scan_due() {
+            return Ok(());
+        }
+        if let Some(player) = self.select_player(false)? {
+            let current = self.player.as_ref().map(MediaPlayer::bus_name);
+            if current != Some(player.bus_name()) {
+                self.set_player(player, None);
+            }
+        }
+        Ok(())
+    }
+
+    /// Follow the player with the given bus name or identity, whatever its status, returning
+    /// whether it was found.
+    ///
+    /// This is the only way to switch players under [`SwitchPolicy::ManualOnly`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the players cannot be listed.
+    pub fn switch_player(&mut self, name: &str) -> Result<bool, DBusError> {
+        let Some(player) = self
+            .list_players()?
+            .into_iter()
+            .find(|player| player.bus_name_player_name_part() == name || player.identity() == name)
+        else {
+            return Ok(false);
+        };
+        self.set_player(player, None);
+        Ok(true)
+    }
+
+    /// List the players on the bus.
+    ///
+    /// Listing is retried a few times, on a new connection to the bus, as it may fail while the
+    /// session is busy starting (e.g. sandboxed players registering their names late) or after
+    /// the bus was restarted.
+    fn list_players(&mut self) -> Result<Vec<B::Player>, DBusError> {
+        let bus = &mut self.bus;
+        let mut reconnect = false;
+        retry(LIST_ATTEMPTS, LIST_RETRY_DELAY, || {
+            if core::mem::replace(&mut reconnect, true) {
+                bus.reconnect()?;
+            }
+            bus.find_all()
+        })
+    }
+
+    /// Follow a newly selected player, keeping the lyrics looked up during selection and
+    /// translating them now that they are shown
+    fn set_player(&mut self, player: B::Player, song: Option<SongInfo>) {
+        tracing::info!("using player {}", player.bus_name());
+        metrics::PLAYER_SWITCHES.inc();
+        if let Some((mut song, metadata)) = song.zip(player.get_metadata().ok()) {
+            if let Some(lyrics) = &song.lyrics {
+                song.translation =
+                    SongInfo::translate(lyrics, &metadata, self.translator.as_ref(), &self.options);
+            }
+            self.song = Some((song_key(&metadata), song));
+        }
+        self.next_song = None;
+        self.predicted_end = None;
+        self.player_failures = 0;
+        self.position_unit = None;
+        self.unit_probe = None;
+        self.switching = self.player.is_some().then(|| Instant::now() + SWITCH_HOLD);
+        self.player = Some(player);
+    }
+
+    /// Find the active player and its playback status
+    ///
+    /// The outer error is a failure to list players, the inner one a failure to query the player.
+    #[allow(
+        clippy::type_complexity,
+        reason = "the two errors are handled differently"
+    )]
+    fn try_find_player(&mut self) -> Result<Option<Result<PlaybackStatus, DBusError>>, DBusError> {
+        if self.player.is_none() {
+            if let Some(player) = self.select_player(self.options.show_when_paused)? {
+                self.set_player(player, None);
+            }
+        }
+        Ok(self.player.as_ref().map(MediaPlayer::get_playback_status))
+    }
+
+    /// Find the current player if it was restarted, i.e. its bus name is now owned by another
+    /// process, whose player must be queried instead
+    fn restarted_player(&mut self) -> Option<B::Player> {
+        self.player.as_ref()?;
+        let players = self.list_players().ok()?;
+        let current = self.player.as_ref()?;
+        players.into_iter().find(|player| {
+            player.bus_name() == current.bus_name() && player.unique_name() != current.unique_name()
+        })
+    }
+
+    /// Handle a failure to query the current player.
+    ///
+    /// Players still starting up (e.g. Electron apps) or shutting down may fail to answer, so
+    /// the query is retried shortly without changing the display. The player is dropped after
+    /// repeated failures so that another one can be selected, unless it was restarted, in which
+    /// case the new process is followed at once.
+    fn player_failed(&mut self, e: &DBusError) -> (Option<Snapshot>, Duration) {
+        if let Some(player) = self.restarted_player() {
+            tracing::info!("player {} restarted, following it again", player.bus_name());
+            self.set_player(player, None);
+            return (None, Duration::ZERO);
+        }
+        self.player_failures += 1;
+        tracing::warn!(
+            "Failed to query player ({} consecutive failures): {}",
+            self.player_failures,
+            e
+        );
+        if self.player_failures >= MAX_PLAYER_FAILURES {
+            tracing::info!("dropping unresponsive player");
+            self.player = None;
+            self.player_failures = 0;
+        }
+        (None, PLAYER_RETRY_DELAY)
+    }
+
+    /// Convert the position reported by the player into microseconds.
+    ///
+    /// The unit is taken from the configuration, or detected by watching how fast the position
+    /// advances while the player is playing. Until it is known, microseconds are assumed.
+    fn position_micros(&mut self, raw: u64, key: u64, rate: f64, paused: bool) -> u64 {
+        let configured = self.player.as_ref().and_then(|player| {
+            self.options
+                .position_units
+                .iter()
+                .find(|(name, _)| {
+                    name.eq_ignore_ascii_case(player.identity())
+                        || name.eq_ignore_ascii_case(player.bus_name_player_name_part())
+                })
+                .map(|(_, unit)| *unit)
+        });
+        if let Some(unit) = configured.or(self.position_unit) {
+            return unit.to_micros(raw);
+        }
+        if paused {
+            self.unit_probe = None;
+            return raw;
+        }
+        match self.unit_probe {
+            Some((song, start, since)) if song == key && raw >= start => {
+                let elapsed = since.elapsed();
+                if elapsed < UNIT_PROBE_DURATION {
+                    return raw;
+                }
+                if let Some(unit) = detect_unit(raw - start, elapsed, rate) {
+                    if unit != PositionUnit::Microseconds {
+                        tracing::warn!("player reports positions in {:?}, correcting", unit);
+                    }
+                    self.position_unit = Some(unit);
+                    self.unit_probe = None;
+                    return unit.to_micros(raw);
+                }
+                // Probably a seek, start over
+                self.unit_probe = Some((key, raw, Instant::now()));
+            }
+            _ => self.unit_probe = Some((key, raw, Instant::now())),
+        }
+        raw
+    }
+
+    /// Replace stale positions reported by players known to report them
+    fn unstale_position(
+        &mut self,
+        key: u64,
+        reported: Duration,
+        rate: f64,
+        paused: bool,
+    ) -> Duration {
+        let designated = self.player.as_ref().is_some_and(|player| {
+            self.options.stale_position_players.iter().any(|name| {
+                name.eq_ignore_ascii_case(player.identity())
+                    || name.eq_ignore_ascii_case(player.bus_name_player_name_part())
+            })
+        });
+        if !designated || paused {
+            self.stale = None;
+            return reported;
+        }
+        let now = Instant::now();
+        match &mut self.stale {
+            Some((song, detector)) if *song == key => detector.position(reported, rate, now),
+            _ => {
+                self.stale = Some((key, StaleDetector::new(reported, rate, now)));
+                reported
+            }
+        }
+    }
+
+    /// Smooth the position reported by the player if enabled
+    fn smooth_position(
+        &mut self,
+        key: u64,
+        reported: Duration,
+        rate: f64,
+        paused: bool,
+    ) -> Duration {
+        let tolerance = self.options.sync_tolerance;
+        if tolerance.is_zero() || self.options.trust_player_position {
+            return reported;
+        }
+        let now = Instant::now();
+        match &mut self.smoother {
+            Some((song, smoother)) if *song == key => {
+                smoother.smooth(reported, rate, paused, tolerance, now)
+            }
+            _ => {
+                self.smoother = Some((key, Smoother::new(reported, now)));
+                reported
+            }
+        }
+    }
+
+    /// Look up the lyrics of the next track ahead of time if the current one is about to end
+    fn prefetch_next(&mut self, metadata: &Metadata, remaining: Duration) {
+        if remaining > PREFETCH_WINDOW || self.next_song.is_some() {
+            return;
+        }
+        let Some(player) = &self.player else {
+            return;
+        };
+        let Some(next) = player.next_track_metadata(metadata) else {
+            return;
+        };
+        if song_key(&next) == song_key(metadata) {
+            return;
+        }
+        tracing::debug!(
+            "looking up lyrics of next song {}",
+            next.tags().url().unwrap_or_default()
+        );
+        let song = SongInfo::new(
+            &next,
+            &self.providers,
+            self.translator.as_ref(),
+            &self.options,
+        );
+        self.next_song = Some((next, song));
+    }
+
+    /// Show the next song if the current one should have ended but the player has not updated
+    /// its metadata yet, as happens with gapless playback.
+    ///
+    /// Gives up once the player reports a position that does not fit a track change, or after
+    /// [`GAPLESS_TIMEOUT`], in which case the current song is shown again.
+    fn gapless_transition(
+        &mut self,
+        key: u64,
+        position: Duration,
+        length: Option<Duration>,
+        rate: f64,
+    ) -> Option<(Option<Snapshot>, Duration)> {
+        let end = self.predicted_end?;
+        let since_end = (Instant::now() + BOUNDARY_TOLERANCE).checked_duration_since(end)?;
+        let track_elapsed = if rate.is_finite() && rate > 0.0 {
+            since_end.mul_f64(rate)
+        } else {
+            since_end
+        };
+        // The player either restarted the position for the new track or is stuck at the end
+        let restarted = position <= track_elapsed + BOUNDARY_TOLERANCE;
+        let at_end = length.is_some_and(|l| position + BOUNDARY_TOLERANCE >= l);
+        let current = self.song.as_ref().is_some_and(|(k, _)| *k == key);
+        if since_end > GAPLESS_TIMEOUT + BOUNDARY_TOLERANCE {
+            tracing::debug!("player did not change tracks at the predicted end of the song");
+            self.next_song = None;
+        }
+        if !current || self.next_song.is_none() || !(restarted || at_end) {
+            self.predicted_end = None;
+            return None;
+        }
+
+        let (metadata, song) = self.next_song.as_ref()?;
+        let position = TimeTag(if restarted { position } else { track_elapsed });
+        let lyrics = lines_at(song, position, rate, &self.options);
+        let snapshot = Snapshot {
+            lines: lyrics.lines,
+            line_start: lyrics.line_start,
+            line_end: lyrics.line_end,
+            line_index: lyrics.line_index,
+            line_total: lyrics.line_total,
+            prev_line: lyrics.prev_line,
+            next_line: lyrics.next_line,
+            source: song.source.map(str::to_owned),
+            quality: song.quality,
+            spoken_word: song.spoken_word,
+            instrumental: song.instrumental,
+            offset: self.options.audio_latency,
+            diagnostic: song.diagnostic.clone(),
+            ..Self::track_snapshot(metadata, position.0, Arc::clone(&song.metadata))
+        };
+        Some((Some(snapshot), lyrics.sleep.min(BOUNDARY_TOLERANCE * 2)))
+    }
+
+    /// Count the times the current song was played in a row, returning the count
+    fn count_plays(&mut self, key: u64, position: Duration, length: Option<Duration>) -> u32 {
+        let plays = match self.plays {
+            Some((song, plays, previous)) if song == key => {
+                if replayed(previous, position, length) {
+                    tracing::debug!("song played again, {} times in a row", plays + 1);
+                    plays + 1
+                } else {
+                    plays
+                }
+            }
+            _ => 1,
+        };
+        self.plays = Some((key, plays, position));
+        plays
+    }
+
+    /// Forget the lyrics of the previous song when the song changes.
+    ///
+    /// Returns what to display while the metadata of a new song is settling, before its lyrics
+    /// are looked up.
+    fn track_change(
+        &mut self,
+        key: u64,
+        metadata: &Metadata,
+        position: Duration,
+    ) -> Option<(Option<Snapshot>, Duration)> {
+        let url = metadata.tags().url().unwrap_or_default();
+        if let Some((current, song)) = &self.song {
+            if *current != key {
+                self.song = None;
+            } else if song.retry_due() {
+                tracing::info!("looking up the lyrics of {} again after a failure", url);
+                self.retrying = song.retry.map(|delay| (key, delay));
+                self.song = None;
+            } else if song.requery_due(self.options.requery_interval) {
+                tracing::info!("looking up the lyrics of {} again", url);
+                self.song = None;
+            }
+        }
+        if self.song.is_none() {
+            // The lyrics of the new song may have been looked up before it started
+            if let Some((_, song)) = self
+                .next_song
+                .take()
+                .filter(|(next, _)| song_key(next) == key)
+            {
+                tracing::debug!("using lyrics looked up ahead of time for {}", url);
+                self.song = Some((key, song));
+                self.pending = None;
+            }
+        }
+        // The metadata of a song looked up again has long settled
+        let retrying = self.retrying.is_some_and(|(song, _)| song == key);
+        if self.song.is_none() && !retrying {
+            // Players send several metadata updates in quick succession when changing tracks, so
+            // wait for the metadata to settle before looking up lyrics
+            match &self.pending {
+                Some((pending, since)) if *pending == key => {
+                    let remaining = self.options.debounce.saturating_sub(since.elapsed());
+                    if !remaining.is_zero() {
+                        return Some((Some(self.settling(metadata, position)), remaining));
+                    }
+                    self.pending = None;
+                }
+                _ if !self.options.debounce.is_zero() => {
+                    tracing::info!("new song {}, waiting for metadata to settle", url);
+                    self.pending = Some((key, Instant::now()));
+                    return Some((
+                        Some(self.settling(metadata, position)),
+                        self.options.debounce,
+                    ));
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Get the current lyrics and duration until the next refresh.
+    ///
+    /// Failures to query the current player are retried. If the players on the `DBus` session
+    /// bus cannot be listed, nothing is displayed and they are listed again after
+    /// [`SCAN_INTERVAL`].
+    pub fn update(&mut self) -> (Option<Snapshot>, Duration) {
+        metrics::WAKEUPS.inc();
+        self.detect_resume();
+        let result = self
+            .finish_tie_break()
+            .and_then(|()| self.poll())
+            .unwrap_or_else(|e| {
+                tracing::warn!(
+                    "Failed to list players, trying again in {:?}: {}",
+                    SCAN_INTERVAL,
+                    e
+                );
+                (Some(Snapshot::default()), SCAN_INTERVAL)
+            });
+        let held = self
+            .switching
+            .and_then(|deadline| hold_during_switch(&result, deadline, Instant::now()));
+        let (mut snapshot, mut sleep) = if let Some(sleep) = held {
+            (None, sleep)
+        } else {
+            self.switching = None;
+            result
+        };
+        self.finish_checks();
+        if let Some(snapshot) = &mut snapshot {
+            snapshot.degraded = self.degraded;
+        }
+        let translating = self
+            .song
+            .as_ref()
+            .is_some_and(|(_, song)| song.translation.is_some());
+        if self.tie_break.is_some() || translating {
+            sleep = sleep.min(LOOKUP_POLL_INTERVAL);
+        }
+        (snapshot, sleep.max(self.options.min_redraw_interval))
+    }
+
+    /// Take the outcome of the checks of the providers and the translator once they finished, and
+    /// run them again every [`RECHECK_INTERVAL`] while one fails
+    fn finish_checks(&mut self) {
+        if self.warm_up.as_ref().is_some_and(JoinHandle::is_finished) {
+            self.degraded = self
+                .warm_up
+                .take()
+                .is_some_and(|checks| checks.join().unwrap_or(true));
+        } else if self.degraded
+            && self.warm_up.is_none()
+            && self
+                .checked
+                .is_some_and(|at| at.elapsed() >= RECHECK_INTERVAL)
+        {
+            tracing::debug!("checking the providers and the translator again");
+            self.warm_up();
+        }
+    }
+
+    /// Forget what was expected from the playback before the system was suspended, so that the
+    /// position is taken again from the player after resuming
+    fn detect_resume(&mut self) {
+        let now = (Instant::now(), SystemTime::now());
+        if let Some((instant, time)) = self.last_update.replace(now) {
+            if suspended(
+                now.0.saturating_duration_since(instant),
+                now.1.duration_since(time).ok(),
+            ) {
+                tracing::info!("resumed from suspend, resynchronising with the player");
+                self.smoother = None;
+                self.stale = None;
+                self.unit_probe = None;
+                self.predicted_end = None;
+                self.pending = None;
+            }
+        }
+    }
+
+    /// Get the lyrics of the current player and duration until the next refresh
+    fn poll(&mut self) -> Result<(Option<Snapshot>, Duration), DBusError> {
+        let status = match self.try_find_player()? {
+            Some(Ok(status)) => status,
+            Some(Err(e)) => return Ok(self.player_failed(&e)),
+            // Nothing is playing: blank the display
+            None => return Ok((Some(Snapshot::default()), self.options.max_sleep)),
+        };
+        let mut paused = status != PlaybackStatus::Playing;
+        if paused && self.options.switch_policy == SwitchPolicy::ManualOnly {
+            if !(status == PlaybackStatus::Paused && self.options.show_when_paused) {
+                // Blank the display, but wait for the player to resume
+                return Ok((Some(Snapshot::default()), self.options.max_sleep));
+            }
+        } else if paused {
+            // Switch to another player if one is playing
+            if let Some(player) = self.playing_elsewhere()? {
+                self.set_player(player, None);
+                paused = false;
+            } else if !(status == PlaybackStatus::Paused && self.options.show_when_paused) {
+                tracing::info!("player is no longer playing");
+                self.player = None;
+                return Ok((Some(Snapshot::default()), self.options.max_sleep));
+            }
+        }
+        if !paused && self.options.switch_policy == SwitchPolicy::MostRecentStart {
+            self.follow_latest_start()?;
+        }
+        let Some(player) = &self.player else {
+            return Ok((Some(Snapshot::default()), self.options.max_sleep));
+        };
+        let (metadata, raw_position) = match player
+            .get_metadata()
+            .and_then(|m| Ok((m, player.get_position_in_microseconds()?)))
+        {
+            Ok(result) => result,
+            Err(e) => return Ok(self.player_failed(&e)),
+        };
+        self.player_failures = 0;
+        if !has_track(&metadata) {
+            // Wait for the player to publish the next track, without looking anything up
+            tracing::debug!("player has no current track");
+            return Ok((Some(Snapshot::default()), self.options.max_sleep));
+        }
+        // Not all players support changing the rate, in which case it is always 1
+        let rate = player
+            .get_playback_rate()
+            .map_err(|e| tracing::warn!("Failed to get playback rate: {}", e))
+            .unwrap_or(1.0);
+
+        let key = song_key(&metadata);
+        let raw_position = self.position_micros(raw_position, key, rate, paused);
+        let reported_position = sanitize_position(raw_position, metadata.tags().length());
+        let reported_position = self.unstale_position(key, reported_position, rate, paused);
+        let position = reported_position.saturating_sub(self.options.audio_latency);
+        let position = self.smooth_position(key, position, rate, paused);
+        let plays = self.count_plays(key, position, metadata.tags().length());
+        if !paused {
+            if let Some((snapshot, sleep)) =
+                self.gapless_transition(key, position, metadata.tags().length(), rate)
+            {
+                return Ok((snapshot.map(|s| Snapshot { plays, ..s }), sleep));
+            }
+        }
+        if let Some(settling) = self.track_change(key, &metadata, position) {
+            return Ok(settling);
+        }
+        let retried = self.retrying.take().filter(|(song, _)| *song == key);
+        let (providers, translator, options) =
+            (&self.providers, self.translator.as_ref(), &self.options);
+        let song = self.song.get_or_insert_with(|| {
+            let song = SongInfo::new(&metadata, providers, translator, options);
+            (key, song.back_off(retried.map(|(_, waited)| waited)))
+        });
+
+        song.1.finish_translation();
+        // Get the current lyrics
+        let lyrics = lines_at(&song.1, TimeTag(position), rate, &self.options);
+        let mut sleep = lyrics.sleep;
+        if self
+            .smoother
+            .as_ref()
+            .is_some_and(|(song, smoother)| *song == key && smoother.correcting)
+        {
+            // The next line is due earlier or later than it seems from the smoothed position
+            sleep = sleep.min(SLEW_STEP);
+        }
+        let snapshot = Snapshot {
+            lines: lyrics.lines,
+            line_start: lyrics.line_start,
+            line_end: lyrics.line_end,
+            line_index: lyrics.line_index,
+            line_total: lyrics.line_total,
+            prev_line: lyrics.prev_line,
+            next_line: lyrics.next_line,
+            source: song.1.source.map(str::to_owned),
+            quality: song.1.quality,
+            spoken_word: song.1.spoken_word,
+            instrumental: song.1.instrumental,
+            offset: self.options.audio_latency,
+            diagnostic: song.1.diagnostic.clone(),
+            reported_position: Some(reported_position),
+            paused,
+            plays,
+            ..Self::track_snapshot(&metadata, position, Arc::clone(&song.1.metadata))
+        };
+
+        if let Some(until_end) = self.predict_end(&metadata, position, rate, paused) {
+            sleep = sleep.min(until_end.max(BOUNDARY_TOLERANCE));
+        }
+        Ok((Some(snapshot), sleep))
+    }
+
+    /// Predict when the song ends, so that the state is updated then to catch gapless
+    /// transitions, and look up the lyrics of the next song when it is close. Returns how long
+    /// until the end, if the song is playing and its length is known.
+    fn predict_end(
+        &mut self,
+        metadata: &Metadata,
+        position: Duration,
+        rate: f64,
+        paused: bool,
+    ) -> Option<Duration> {
+        self.predicted_end = None;
+        let length = metadata
+            .tags()
+            .length()
+            .filter(|l| !paused && !l.is_zero())?;
+        let remaining = length.saturating_sub(position);
+        let until_end = scale_by_rate(remaining, rate);
+        self.predicted_end = Some(Instant::now() + until_end);
+        self.prefetch_next(metadata, remaining);
+        Some(until_end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority() {
+        let priority = ["mpd".to_owned(), "Spotify".to_owned()];
+        assert_eq!(priority_rank(&priority, "Music Player Daemon", "mpd"), 0);
+        assert_eq!(priority_rank(&priority, "spotify", "spotify"), 1);
+        assert_eq!(priority_rank(&priority, "Firefox", "firefox.instance1"), 2);
+    }
+
+    #[test]
+    fn switch_ranks() {
+        let now = Instant::now();
+        let earlier = now.checked_sub(Duration::from_secs(30)).unwrap();
+        let rank = |policy, started, priority| switch_rank(policy, 0, started, priority, now);
+        // The player that started last wins over a preferred one that has played for longer
+        assert!(
+            rank(SwitchPolicy::MostRecentStart, Some(now), 1)
+                < rank(SwitchPolicy::MostRecentStart, Some(earlier), 0)
+        );
+        assert!(
+            rank(SwitchPolicy::PreferPriorityList, Some(now), 1)
+                > rank(SwitchPolicy::PreferPriorityList, Some(earlier), 0)
+        );
+        // Playing players always win over paused ones
+        assert!(
+            switch_rank(SwitchPolicy::MostRecentStart, 1, None, 0, now)
+                > rank(SwitchPolicy::MostRecentStart, Some(earlier), 1)
+        );
+    }
+
+    #[test]
+    fn metadata_filter() {
+        assert!(glob_match("xesam:*", "xesam:title"));
+        assert!(glob_match("*:artUrl", "mpris:artUrl"));
+        assert!(glob_match("*ART*", "mpris:artUrl"));
+        assert!(!glob_match("xesam:*", "mpris:length"));
+        assert!(!glob_match("xesam:title", "xesam:titles"));
+
+        let metadata = Metadata::from(std::collections::HashMap::from([
+            (
+                "xesam:title".to_owned(),
+                mpris::MetadataValue::String("Song".to_owned()),
+            ),
+            (
+                "xesam:album".to_owned(),
+                mpris::MetadataValue::String("Album".to_owned()),
+            ),
+            (
+                "xesam:genre".to_owned(),
+                mpris::MetadataValue::Array(vec![mpris::MetadataValue::String("Pop".to_owned())]),
+            ),
+            ("mpris:length".to_owned(), mpris::MetadataValue::I64(1)),
+        ]));
+        let format = |skip: &[&str], only: &[&str]| {
+            let filter = MetadataFilter {
+                skip: skip.iter().map(|&s| s.to_owned()).collect(),
+                only: only.iter().map(|&s| s.to_owned()).collect(),
+            };
+            SongInfo::format_metadata(&metadata, &filter)
+        };
+        assert_eq!(format(&[], &[]), "album: Album\ntitle: Song\n");
+        assert_eq!(format(&["*:album"], &[]), "title: Song\n");
+        assert_eq!(
+            format(&["xesam:album"], &["xesam:*"]),
+            "title: Song\nxesam:genre: Pop\n"
+        );
+    }
+
+    #[test]
+    fn no_track() {
+        use mpris::MetadataValue;
+
+        let metadata = |entries: &[(&str, MetadataValue)]| {
+            Metadata::from(
+                entries
+                    .iter()
+                    .map(|(k, v)| ((*k).to_owned(), v.clone()))
+                    .collect::<std::collections::HashMap<_, _>>(),
+            )
+        };
+        let title = ("xesam:title", MetadataValue::String("Song".to_owned()));
+        assert!(has_track(&metadata(std::slice::from_ref(&title))));
+        assert!(!has_track(&metadata(&[])));
+        assert!(!has_track(&metadata(&[(
+            "xesam:title",
+            MetadataValue::String(" ".to_owned())
+        )])));
+        assert!(!has_track(&metadata(&[
+            title,
+            ("mpris:trackid", MetadataValue::String(NO_TRACK.to_owned()))
+        ])));
+    }
+
+    #[test]
+    fn spoken_word() {
+        use mpris::MetadataValue;
+
+        let metadata = |genre: &str, minutes: i64| {
+            Metadata::from(std::collections::HashMap::from([
+                (
+                    "xesam:genre".to_owned(),
+                    MetadataValue::Array(vec![MetadataValue::String(genre.to_owned())]),
+                ),
+                (
+                    "mpris:length".to_owned(),
+                    MetadataValue::I64(minutes * 60_000_000),
+                ),
+            ]))
+        };
+        let hour = Some(Duration::from_hours(1));
+        assert!(!is_spoken_word(&metadata("Rock", 4), hour));
+        assert!(is_spoken_word(&metadata("Podcasts", 4), hour));
+        assert!(is_spoken_word(&metadata("Rock", 90), hour));
+        assert!(!is_spoken_word(&metadata("Rock", 90), None));
+    }
+
+    #[test]
+    fn smoothing() {
+        let start = Instant::now();
+        let tolerance = Duration::from_millis(300);
+        let mut smoother = Smoother::new(Duration::from_secs(10), start);
+        let at = |ms| start + Duration::from_millis(ms);
+        // Small jitter is ignored
+        assert_eq!(
+            smoother.smooth(
+                Duration::from_millis(11_200),
+                1.0,
+                false,
+                tolerance,
+                at(1000)
+            ),
+            Duration::from_secs(11)
+        );
+        // Larger corrections are spread over a second
+        assert_eq!(
+            smoother.smooth(
+                Duration::from_millis(12_500),
+                1.0,
+                false,
+                tolerance,
+                at(1500)
+            ),
+            Duration::from_secs(12)
+        );
+        assert!(smoother.correcting);
+        // Seeks are followed immediately
+        assert_eq!(
+            smoother.smooth(Duration::from_secs(45), 1.0, false, tolerance, at(2000)),
+            Duration::from_secs(45)
+        );
+        assert!(!smoother.correcting);
+    }
+
+    #[test]
+    fn playback_clock() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut clock = PlaybackClock::new(Duration::from_secs(10), 1.0, start);
+        assert_eq!(clock.now(at(2)), Duration::from_secs(12));
+        clock.pause(at(2));
+        // Later updates while paused, e.g. after a missed resume, do not move the position
+        clock.pause(at(4));
+        assert_eq!(clock.now(at(7)), Duration::from_secs(12));
+        clock.resume(at(7));
+        clock.resume(at(8));
+        assert_eq!(clock.now(at(8)), Duration::from_secs(13));
+        clock.seek(Duration::from_secs(50), at(9));
+        assert_eq!(clock.now(at(10)), Duration::from_secs(51));
+        clock.set_rate(2.0, at(10));
+        assert_eq!(clock.now(at(11)), Duration::from_secs(53));
+        clock.set_rate(f64::NAN, at(11));
+        assert_eq!(clock.now(at(12)), Duration::from_secs(54));
+    }
+
+    #[test]
+    fn boundary_polling() {
+        let song = SongInfo {
+            metadata: Arc::from(""),
+            lyrics: Some(Lrc::from_str("[00:01.00]One\n[00:05.00]Two\n").unwrap()),
+            source: None,
+            quality: None,
+            diagnostic: None,
+            retry: None,
+            spoken_word: false,
+            instrumental: false,
+            looked_up: Instant::now(),
+            translation: None,
+        };
+        let mut options = Options {
+            max_sleep: Duration::from_secs(10),
+            ..Options::default()
+        };
+        let position = TimeTag(Duration::from_secs(2));
+        assert_eq!(
+            lines_at(&song, position, 1.0, &options).sleep,
+            Duration::from_secs(3)
+        );
+        options.trust_player_position = true;
+        assert_eq!(
+            lines_at(&song, position, 1.0, &options).sleep,
+            Duration::from_millis(2900)
+        );
+        let close = TimeTag(Duration::from_millis(4950));
+        assert_eq!(
+            lines_at(&song, close, 1.0, &options).sleep,
+            Duration::from_millis(50)
+        );
+
+        options.trust_player_position = false;
+        options.render_advance = Duration::from_millis(100);
+        let early = lines_at(&song, close, 1.0, &options);
+        assert_eq!(early.lines, [(TrackKind::Original, "Two".to_owned())]);
+        assert_eq!(
+            lines_at(&song, position, 1.0, &options).sleep,
+            Duration::from_millis(2900)
+        );
+    }
+
+    #[test]
+    fn suspend() {
+        let second = Duration::from_secs(1);
+        assert!(!suspended(second, Some(second * 2)));
+        assert!(suspended(second, Some(second * 30)));
+        // The wall clock was set back
+        assert!(!suspended(second, None));
+    }
+
+    #[test]
+    fn replays() {
+        let length = Some(Duration::from_mins(3));
+        let at = Duration::from_secs;
+        assert!(replayed(at(179), at(0), length));
+        assert!(!replayed(at(179), at(0), None), "the end is unknown");
+        assert!(!replayed(at(90), at(0), length), "a seek to the start");
+        assert!(!replayed(at(179), at(60), length), "a seek back");
+    }
+
+    #[test]
+    fn switch_hold() {
+        let now = Instant::now();
+        let deadline = now + Duration::from_millis(300);
+        let sleep = Duration::from_secs(1);
+        let blank = Snapshot::default();
+        assert_eq!(
+            hold_during_switch(&(Some(blank.clone()), sleep), deadline, now),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(
+            hold_during_switch(&(Some(blank.clone()), sleep), deadline, deadline),
+            None
+        );
+        let line = Snapshot {
+            lines: vec![(TrackKind::Original, "Hello".to_owned())],
+            ..blank
+        };
+        assert_eq!(
+            hold_during_switch(&(Some(line), sleep), deadline, now),
+            None
+        );
+    }
+
+    #[test]
+    fn stale_position() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let reported = Duration::from_secs(10);
+        let mut detector = StaleDetector::new(reported, 1.0, start);
+        assert_eq!(detector.position(reported, 1.0, at(1)), reported);
+        assert_eq!(detector.position(reported, 1.0, at(2)), reported);
+        assert_eq!(
+            detector.position(reported, 1.0, at(3)),
+            Duration::from_secs(13)
+        );
+        // A new position, e.g. after a seek, is followed again
+        let seeked = Duration::from_secs(50);
+        assert_eq!(detector.position(seeked, 1.0, at(4)), seeked);
+        assert_eq!(detector.position(seeked, 1.0, at(5)), seeked);
+    }
+
+    #[test]
+    fn position_units() {
+        let elapsed = Duration::from_secs(5);
+        assert_eq!(
+            detect_unit(5_020_000, elapsed, 1.0),
+            Some(PositionUnit::Microseconds)
+        );
+        assert_eq!(
+            detect_unit(10_000_000, elapsed, 2.0),
+            Some(PositionUnit::Microseconds)
+        );
+        assert_eq!(
+            detect_unit(5_000, elapsed, 1.0),
+            Some(PositionUnit::Milliseconds)
+        );
+        assert_eq!(detect_unit(60_000_000, elapsed, 1.0), None);
+        assert_eq!(PositionUnit::Milliseconds.to_micros(1_500), 1_500_000);
+    }
+
+    #[test]
+    fn negative_position() {
+        #[allow(clippy::cast_sign_loss, reason = "simulating the cast done by mpris")]
+        let raw = -1_i64 as u64;
+        assert_eq!(sanitize_position(raw, None), Duration::ZERO);
+    }
+
+    #[test]
+    fn position_beyond_length() {
+        let length = Some(Duration::from_secs(200));
+        assert_eq!(sanitize_position(3_601_000_000, length), Duration::ZERO);
+        assert_eq!(
+            sanitize_position(201_000_000, length),
+            Duration::from_secs(201)
+        );
+        assert_eq!(
+            sanitize_position(3_601_000_000, None),
+            Duration::from_secs(3601)
+        );
+    }
+
+    #[test]
+    fn requery() {
+        let song = SongInfo {
+            metadata: Arc::from(""),
+            lyrics: None,
+            source: None,
+            quality: None,
+            diagnostic: Some("no lyrics found".to_owned()),
+            retry: None,
+            spoken_word: false,
+            instrumental: false,
+            looked_up: Instant::now()
+                .checked_sub(Duration::from_secs(30))
+                .expect("the clock started long ago"),
+            translation: None,
+        };
+        assert!(song.requery_due(Some(Duration::from_secs(10))));
+        assert!(!song.requery_due(Some(Duration::from_secs(50))));
+        assert!(!song.requery_due(None));
+        let spoken = SongInfo {
+            spoken_word: true,
+            ..song
+        };
+        assert!(!spoken.requery_due(Some(Duration::from_secs(10))));
+        let instrumental = SongInfo {
+            spoken_word: false,
+            instrumental: true,
+            ..spoken
+        };
+        assert!(!instrumental.requery_due(Some(Duration::from_secs(10))));
+        let failed = SongInfo {
+            instrumental: false,
+            retry: Some(Duration::from_secs(10)),
+            ..instrumental
+        };
+        assert!(failed.retry_due());
+        let failed = SongInfo {
+            retry: Some(Duration::from_secs(50)),
+            ..failed
+        };
+        assert!(!failed.retry_due(), "the retry waits out its delay");
+        assert_eq!(
+            failed.back_off(Some(Duration::from_mins(4))).retry,
+            Some(MAX_LOOKUP_RETRY_DELAY)
+        );
+    }
+
+    #[test]
+    fn retries() {
+        let mut calls = 0;
+        let result: Result<u32, &str> = retry(3, Duration::ZERO, || {
+            calls += 1;
+            if calls < 2 {
+                Err("busy")
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result, Ok(2));
+
+        calls = 0;
+        let result: Result<(), &str> = retry(3, Duration::ZERO, || {
+            calls += 1;
+            Err("gone")
+        });
+        assert_eq!((result, calls), (Err("gone"), 3));
+    }
+
+    /// A player always playing the same song
+    #[derive(Clone)]
+    struct FakePlayer {
+        name: &'static str,
+        title: &'static str,
+    }
+
+    impl MediaPlayer for FakePlayer {
+        fn bus_name(&self) -> &str {
+            self.name
+        }
+
+        fn bus_name_player_name_part(&self) -> &str {
+            self.name
+        }
+
+        fn unique_name(&self) -> &str {
+            self.name
+        }
+
+        fn identity(&self) -> &str {
+            self.name
+        }
+
+        fn get_metadata(&self) -> Result<Metadata, DBusError> {
+            Ok(Metadata::from(std::collections::HashMap::from([(
+                TITLE_KEY.to_owned(),
+                mpris::MetadataValue::String(self.title.to_owned()),
+            )])))
+        }
+
+        fn get_playback_status(&self) -> Result<PlaybackStatus, DBusError> {
+            Ok(PlaybackStatus::Playing)
+        }
+
+        fn get_position_in_microseconds(&self) -> Result<u64, DBusError> {
+            Ok(1_000_000)
+        }
+
+        fn get_playback_rate(&self) -> Result<f64, DBusError> {
+            Ok(1.0)
+        }
+
+        fn next_track_metadata(&self, _: &Metadata) -> Option<Metadata> {
+            None
+        }
+    }
+
+    /// A bus with a fixed set of players, failing to list them a number of times first
+    struct FakeBus {
+        players: Vec<FakePlayer>,
+        failures: core::cell::Cell<u32>,
+    }
+
+    impl Bus for FakeBus {
+        type Player = FakePlayer;
+
+        fn find_all(&self) -> Result<Vec<FakePlayer>, DBusError> {
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err(DBusError::Miscellaneous("bus is busy".to_owned()));
+            }
+            Ok(self.players.clone())
+        }
+
+        fn reconnect(&mut self) -> Result<(), DBusError> {
+            Ok(())
+        }
+    }
+
+    /// Lyrics made of the title of the song
+    struct Titles;
+
+    impl Provider for Titles {
+        fn name(&self) -> &'static str {
+            "titles"
+        }
+
+        fn fetch(&self, metadata: &Metadata) -> Result<Lrc, provider::error::Resolve> {
+            let title = metadata.tags().title().unwrap_or_default();
+            Ok(Lrc::from_str(&format!("[00:00.00]{title}"))?)
+        }
+    }
+
+    /// A translator remembering the lines it was sent
+    struct Recorder(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl Translator for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn translate(&self, lines: &[&str]) -> Result<Vec<String>, provider::error::Resolve> {
+            let mut sent = self.0.lock().unwrap();
+            sent.extend(lines.iter().map(|&line| line.to_owned()));
+            Ok(lines.iter().map(|line| line.to_uppercase()).collect())
+        }
+    }
+
+    #[test]
+    fn tie_break_translation() {
+        let sent = Arc::default();
+        let bus = FakeBus {
+            players: vec![
+                FakePlayer {
+                    name: "org.mpris.MediaPlayer2.shown",
+                    title: "Shown",
+                },
+                FakePlayer {
+                    name: "org.mpris.MediaPlayer2.hidden",
+                    title: "Hidden",
+                },
+            ],
+            failures: core::cell::Cell::new(0),
+        };
+        let options = Options {
+            debounce: Duration::ZERO,
+            switch_policy: SwitchPolicy::PreferWithLyrics,
+            ..Options::default()
+        };
+        let mut state = State::with_bus(bus, options, vec![Box::new(Titles)])
+            .with_translator(Box::new(Recorder(Arc::clone(&sent))));
+        for _ in 0..500 {
+            state.update();
+            let translating = state
+                .song
+                .as_ref()
+                .is_some_and(|(_, song)| song.translation.is_some());
+            if state.tie_break.is_none() && !translating {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(state.tie_break.is_none(), "the tie-break finished");
+        assert_eq!(
+            state.player.as_ref().map(MediaPlayer::bus_name),
+            Some("org.mpris.MediaPlayer2.shown")
+        );
+        assert_eq!(
+            *sent.lock().unwrap(),
+            ["Shown"],
+            "the songs of the other players are not translated"
+        );
+    }
+
+    #[test]
+    fn bus_failure() {
+        let bus = FakeBus {
+            players: vec![FakePlayer {
+                name: "org.mpris.MediaPlayer2.fake",
+                title: "Song",
+            }],
+            failures: core::cell::Cell::new(LIST_ATTEMPTS),
+        };
+        let options = Options {
+            debounce: Duration::ZERO,
+            ..Options::default()
+        };
+        let mut state = State::with_bus(bus, options, vec![Box::new(Titles)]);
+        let (snapshot, sleep) = state.update();
+        assert!(snapshot.is_some_and(|snapshot| snapshot.lines.is_empty()));
+        assert_eq!(sleep, SCAN_INTERVAL, "the players are listed again later");
+        let (snapshot, _) = state.update();
+        assert_eq!(
+            snapshot.unwrap().lines,
+            [(TrackKind::Original, "Song".to_owned())]
+        );
+    }
+}